@@ -0,0 +1,177 @@
+//! 按实测速度自动调整分片连接数。
+//!
+//! 合适的连接数取决于用户线路和 CDN 节点，固定的 `split` 难以兼顾。任务仍按 `split` 切分片，
+//! 但起初只放开少量连接（该主机上次测得的最佳值，或 [`INITIAL_CONNECTIONS`]），其余分片排队。
+//! 之后每隔 [`PROBE_INTERVAL`] 测一次聚合速度，比上一档明显提升就再放开一个连接，直到达到
+//! 分片数或 `ConnectionController` 的有效连接数，或该主机出现 CDN 限流为止。最终结果按主机
+//! 记入 [`cdn_limits`]，之后的任务直接从该值起步。
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, info};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant, MissedTickBehavior};
+
+use super::cdn_limits;
+use super::http::{ConnectionController, current_epoch_ms};
+
+/// 主机没有调优记录时的起始连接数。
+const INITIAL_CONNECTIONS: u16 = 4;
+/// 测速间隔；任务开始后的第一个间隔用于预热，不参与比较。
+const PROBE_INTERVAL: Duration = Duration::from_secs(2);
+/// 新增连接后速度至少提升这么多才算有效。
+const MIN_GAIN: f64 = 0.1;
+
+/// 任务起步时放开的连接数，不超过待下载的分片数。
+pub fn initial_connections(host: Option<&str>, max: u16) -> u16 {
+    host.and_then(cdn_limits::tuned_connections)
+        .unwrap_or(INITIAL_CONNECTIONS)
+        .clamp(1, max.max(1))
+}
+
+/// 一次测速后的决定。
+#[derive(Debug, PartialEq)]
+enum Step {
+    /// 还没有有效样本，继续等待。
+    Wait,
+    /// 速度仍在提升，再放开一个连接。
+    AddConnection,
+    /// 停止调优，附带测得的最佳连接数。
+    Settle(u16),
+}
+
+/// 逐档加连接的测速状态。
+struct Probe {
+    open: u16,
+    best_open: u16,
+    best_speed: f64,
+}
+
+impl Probe {
+    fn new(open: u16) -> Self {
+        Self {
+            open,
+            best_open: open,
+            best_speed: 0.0,
+        }
+    }
+
+    /// 记录当前连接数下的聚合速度，`cap` 为当前允许的最大连接数。
+    fn observe(&mut self, speed: f64, cap: u16) -> Step {
+        if speed <= 0.0 {
+            return Step::Wait;
+        }
+        if speed > self.best_speed * (1.0 + MIN_GAIN) {
+            self.best_speed = speed;
+            self.best_open = self.open;
+            if self.open < cap {
+                self.open += 1;
+                return Step::AddConnection;
+            }
+        }
+        Step::Settle(self.best_open)
+    }
+}
+
+/// 运行中的调优任务，drop 时停止。
+pub struct Tuner(Option<JoinHandle<()>>);
+
+impl Drop for Tuner {
+    fn drop(&mut self) {
+        if let Some(handle) = self.0.take() {
+            handle.abort();
+        }
+    }
+}
+
+/// 启动调优：`slots` 为任务级连接许可，初始已放开 `open` 个，最多放开到 `max` 个。
+///
+/// 无法再加连接或没有主机名时不启动，只返回空的守卫。
+pub fn start(
+    task_id: &str,
+    host: Option<String>,
+    slots: Arc<Semaphore>,
+    open: u16,
+    max: u16,
+    progress_snapshot: Arc<Mutex<HashMap<u16, u64>>>,
+    conn_controller: Arc<ConnectionController>,
+) -> Tuner {
+    let Some(host) = host.filter(|_| open < max) else {
+        return Tuner(None);
+    };
+    let task_id = task_id.to_string();
+    Tuner(Some(tokio::spawn(async move {
+        let since_ms = current_epoch_ms();
+        let downloaded = || progress_snapshot.lock().unwrap().values().sum::<u64>();
+        let mut probe = Probe::new(open);
+        let mut ticker = tokio::time::interval(PROBE_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+        ticker.tick().await;
+        let mut last_bytes = downloaded();
+        let mut last_at = Instant::now();
+
+        let best = loop {
+            ticker.tick().await;
+            if conn_controller.rate_limited_since(&host, since_ms) {
+                debug!("[调优][{}] 出现 CDN 限流，停止加连接", task_id);
+                break probe.best_open.min(conn_controller.effective_limit());
+            }
+            let bytes = downloaded();
+            let speed = bytes.saturating_sub(last_bytes) as f64 / last_at.elapsed().as_secs_f64();
+            last_bytes = bytes;
+            last_at = Instant::now();
+
+            let cap = max.min(conn_controller.effective_limit());
+            match probe.observe(speed, cap) {
+                Step::Wait => {}
+                Step::AddConnection => {
+                    slots.add_permits(1);
+                    debug!(
+                        "[调优][{}] {:.1}MB/s，连接数 {} → {}",
+                        task_id,
+                        speed / 1024.0 / 1024.0,
+                        probe.open - 1,
+                        probe.open
+                    );
+                }
+                Step::Settle(best) => break best,
+            }
+        };
+        info!(
+            "[调优][{}] 主机 {} 最佳连接数 {}（起步 {}）",
+            task_id, host, best, open
+        );
+        cdn_limits::record_tuned(&host, best);
+
+        // 超出最佳值的连接没有带来提升：等分片释放许可后逐个收回，不要求当时有空闲许可。
+        for _ in best..probe.open {
+            match slots.acquire().await {
+                Ok(permit) => permit.forget(),
+                Err(_) => break,
+            }
+        }
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_connections_while_speed_improves() {
+        let mut probe = Probe::new(2);
+        assert_eq!(probe.observe(0.0, 8), Step::Wait);
+        assert_eq!(probe.observe(10.0, 8), Step::AddConnection);
+        assert_eq!(probe.observe(15.0, 8), Step::AddConnection);
+        // 提升不足 10%，回到上一档
+        assert_eq!(probe.observe(16.0, 8), Step::Settle(3));
+
+        // 达到上限时停在上限
+        let mut probe = Probe::new(3);
+        assert_eq!(probe.observe(10.0, 4), Step::AddConnection);
+        assert_eq!(probe.observe(20.0, 4), Step::Settle(4));
+    }
+}
@@ -0,0 +1,211 @@
+//! 按 CDN 主机记住学到的限流状态。
+//!
+//! `ConnectionController` 遇到 CDN 限流时把有效连接数减半，恢复时逐个加回。这里按主机记录最近的
+//! 安全连接数，新任务直接从该水平起步，不必每次都用满配置的连接数再触发一轮 403。
+//!
+//! 记录随时间衰减：每过 [`DECAY_HALF_LIFE_MS`] 允许的连接数翻倍，放宽到 [`MAX_TRACKED_LIMIT`]
+//! 以上即视为不再受限并丢弃。
+//!
+//! 另外记录 [`super::autotune`] 为每个主机测得的最佳分片连接数，新任务直接从该值起步。
+//! 状态有变化时定期写入数据库，应用退出前再写入一次，启动时读回。
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+
+use super::http::current_epoch_ms;
+use super::store::{DbHandle, HostLimit, HostTuning};
+
+/// 衰减半衰期：距上次限流每过这么久，允许的连接数翻倍。
+const DECAY_HALF_LIFE_MS: u64 = 10 * 60 * 1000;
+/// 衰减到该连接数及以上时不再限制。
+const MAX_TRACKED_LIMIT: u64 = 64;
+/// 写入数据库的间隔，仅在有变化时写入。
+const PERSIST_INTERVAL: Duration = Duration::from_secs(30);
+
+static HOSTS: LazyLock<Mutex<HostTable>> = LazyLock::new(|| Mutex::new(HostTable::default()));
+
+#[derive(Default)]
+struct HostTable {
+    hosts: HashMap<String, HostLimit>,
+    tuned: HashMap<String, HostTuning>,
+    dirty: bool,
+    tuned_dirty: bool,
+}
+
+/// 考虑衰减后的当前安全连接数；已衰减到不再限制时返回空。
+fn decayed_limit(limit: &HostLimit, now_ms: u64) -> Option<u16> {
+    let halvings = now_ms.saturating_sub(limit.updated_at.max(0) as u64) / DECAY_HALF_LIFE_MS;
+    if halvings >= 16 {
+        return None;
+    }
+    let value = u64::from(limit.max_connections.max(1)) << halvings;
+    (value < MAX_TRACKED_LIMIT).then_some(value as u16)
+}
+
+/// 提取下载地址的主机名（小写）。
+pub fn host_of(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    Some(url.host_str()?.to_ascii_lowercase())
+}
+
+/// 该主机当前的安全连接数；没有记录或已衰减完时返回空。
+pub fn learned_limit(host: &str) -> Option<u16> {
+    let table = HOSTS.lock().unwrap();
+    decayed_limit(table.hosts.get(host)?, current_epoch_ms())
+}
+
+/// 记录一次限流，`connections` 为降速后的有效连接数。
+pub fn record_rate_limit(host: &str, connections: u16) {
+    let mut table = HOSTS.lock().unwrap();
+    table.hosts.insert(
+        host.to_string(),
+        HostLimit {
+            host: host.to_string(),
+            max_connections: connections.max(1),
+            updated_at: current_epoch_ms() as i64,
+        },
+    );
+    table.dirty = true;
+}
+
+/// 限流后连接数恢复时调用，抬高该主机的安全连接数；恢复到配置上限时清除记录。
+pub fn record_recovery(host: &str, connections: u16, configured_max: u16) {
+    let mut table = HOSTS.lock().unwrap();
+    let Some(limit) = table.hosts.get_mut(host) else {
+        return;
+    };
+    let now_ms = current_epoch_ms();
+    if connections >= configured_max {
+        table.hosts.remove(host);
+        table.dirty = true;
+        debug!("[CDN限流] 主机 {} 已恢复到配置上限，清除记录", host);
+    } else if decayed_limit(limit, now_ms).is_some_and(|current| connections > current) {
+        limit.max_connections = connections;
+        limit.updated_at = now_ms as i64;
+        table.dirty = true;
+    }
+}
+
+/// 该主机上次调优测得的最佳分片连接数。
+pub fn tuned_connections(host: &str) -> Option<u16> {
+    HOSTS
+        .lock()
+        .unwrap()
+        .tuned
+        .get(host)
+        .map(|tuning| tuning.connections)
+}
+
+/// 记录一次调优结果。
+pub fn record_tuned(host: &str, connections: u16) {
+    let mut table = HOSTS.lock().unwrap();
+    table.tuned.insert(
+        host.to_string(),
+        HostTuning {
+            host: host.to_string(),
+            connections: connections.max(1),
+            updated_at: current_epoch_ms() as i64,
+        },
+    );
+    table.tuned_dirty = true;
+}
+
+/// 读回上次保存的状态，之后定期把变化写入数据库。
+pub fn start(db: DbHandle) {
+    tauri::async_runtime::spawn(async move {
+        match db.load_host_limits().await {
+            Ok(limits) => {
+                let now_ms = current_epoch_ms();
+                let mut table = HOSTS.lock().unwrap();
+                let mut restored = 0;
+                for limit in limits {
+                    // 运行期间已经学到的新状态优先。
+                    if decayed_limit(&limit, now_ms).is_some()
+                        && !table.hosts.contains_key(&limit.host)
+                    {
+                        table.hosts.insert(limit.host.clone(), limit);
+                        restored += 1;
+                    }
+                }
+                if restored > 0 {
+                    info!("[CDN限流] 恢复 {} 个主机的限流记录", restored);
+                }
+            }
+            Err(e) => warn!("[CDN限流] 读取限流记录失败: {}", e),
+        }
+        match db.load_host_tuning().await {
+            Ok(tuning) => {
+                let mut table = HOSTS.lock().unwrap();
+                for entry in tuning {
+                    if !table.tuned.contains_key(&entry.host) {
+                        table.tuned.insert(entry.host.clone(), entry);
+                    }
+                }
+            }
+            Err(e) => warn!("[CDN限流] 读取调优记录失败: {}", e),
+        }
+
+        let mut ticker = tokio::time::interval(PERSIST_INTERVAL);
+        loop {
+            ticker.tick().await;
+            flush(&db).await;
+        }
+    });
+}
+
+/// 把有变化的状态写入数据库；写入失败时保留脏标记，下次重试。
+pub async fn flush(db: &DbHandle) {
+    let (limits, tuning) = {
+        let mut table = HOSTS.lock().unwrap();
+        let limits = std::mem::take(&mut table.dirty).then(|| {
+            let now_ms = current_epoch_ms();
+            table
+                .hosts
+                .retain(|_, limit| decayed_limit(limit, now_ms).is_some());
+            table.hosts.values().cloned().collect::<Vec<_>>()
+        });
+        let tuning = std::mem::take(&mut table.tuned_dirty)
+            .then(|| table.tuned.values().cloned().collect::<Vec<_>>());
+        (limits, tuning)
+    };
+    if let Some(limits) = limits
+        && let Err(e) = db.save_host_limits(limits).await
+    {
+        warn!("[CDN限流] 保存限流记录失败: {}", e);
+        HOSTS.lock().unwrap().dirty = true;
+    }
+    if let Some(tuning) = tuning
+        && let Err(e) = db.save_host_tuning(tuning).await
+    {
+        warn!("[CDN限流] 保存调优记录失败: {}", e);
+        HOSTS.lock().unwrap().tuned_dirty = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn learned_limit_doubles_each_half_life_until_released() {
+        let limit = HostLimit {
+            host: "cdn.example.com".into(),
+            max_connections: 4,
+            updated_at: 0,
+        };
+        assert_eq!(decayed_limit(&limit, 0), Some(4));
+        assert_eq!(decayed_limit(&limit, DECAY_HALF_LIFE_MS - 1), Some(4));
+        assert_eq!(decayed_limit(&limit, DECAY_HALF_LIFE_MS), Some(8));
+        assert_eq!(decayed_limit(&limit, 3 * DECAY_HALF_LIFE_MS), Some(32));
+        assert_eq!(decayed_limit(&limit, 4 * DECAY_HALF_LIFE_MS), None);
+
+        assert_eq!(
+            host_of("https://CDN.Example.com:8443/a?b=c").as_deref(),
+            Some("cdn.example.com")
+        );
+        assert_eq!(host_of("not a url"), None);
+    }
+}
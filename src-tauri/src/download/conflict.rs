@@ -0,0 +1,182 @@
+//! 保存路径冲突处理。
+//!
+//! 入队前检查目标文件是否已存在，按用户选择的策略覆盖、改名或跳过。
+//! 存在匹配的 `.oofp` 时视为断点续传，不算冲突。
+//!
+//! 文件夹同步下载（sync down）另按大小和 SHA1 判断本地副本是否已是最新，只下载缺失或有变化的文件。
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::http::verify_file_sha1;
+use super::persistence::oofp_path;
+use super::types::DownloadError;
+
+/// 保存路径已存在同名文件时的处理策略。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictPolicy {
+    /// 覆盖已有文件，与旧版本行为一致。
+    #[default]
+    Overwrite,
+    /// 自动改名为 `name (1).ext`、`name (2).ext`……
+    Rename,
+    /// 直接跳过。
+    Skip,
+    /// 本地文件 SHA1 与预期一致时跳过，否则覆盖。
+    SkipIfSameSha1,
+}
+
+/// 文件夹同步下载时判断本地文件是否已是最新的方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncCompare {
+    /// 只比较文件大小。
+    Size,
+    /// 大小一致后再比较 SHA1；云端未提供 SHA1 时只比较大小。
+    Sha1,
+}
+
+/// 冲突处理结果。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// 下载到给定路径（可能已改名）。
+    Download(String),
+    /// 跳过下载，附带原因。
+    Skip(String),
+}
+
+/// 在文件名（扩展名之前）插入序号后缀，如 `a/photo.jpg` → `a/photo (2).jpg`。
+pub fn numbered_path(path: &str, n: usize) -> String {
+    let name_start = path.rfind(['/', '\\']).map(|pos| pos + 1).unwrap_or(0);
+    match path[name_start..].rfind('.') {
+        // 以点开头的隐藏文件（如 `.bashrc`）没有扩展名
+        Some(dot) if dot > 0 => {
+            let dot = name_start + dot;
+            format!("{} ({}){}", &path[..dot], n, &path[dot..])
+        }
+        _ => format!("{} ({})", path, n),
+    }
+}
+
+/// 路径上是否已有文件或其他任务的断点记录。
+pub fn is_occupied(path: &str) -> bool {
+    Path::new(path).exists() || Path::new(&oofp_path(path)).exists()
+}
+
+/// 按策略处理保存路径冲突。
+pub async fn resolve_conflict(
+    save_path: &str,
+    policy: ConflictPolicy,
+    expected_sha1: Option<&str>,
+) -> Result<ConflictResolution, DownloadError> {
+    let conflicts = Path::new(save_path).is_file() && !Path::new(&oofp_path(save_path)).exists();
+    if !conflicts {
+        return Ok(ConflictResolution::Download(save_path.to_string()));
+    }
+
+    match policy {
+        ConflictPolicy::Overwrite => Ok(ConflictResolution::Download(save_path.to_string())),
+        ConflictPolicy::Rename => {
+            let renamed = (1..)
+                .map(|n| numbered_path(save_path, n))
+                .find(|candidate| !is_occupied(candidate))
+                .expect("序号后缀无上限，必然能找到空闲路径");
+            Ok(ConflictResolution::Download(renamed))
+        }
+        ConflictPolicy::Skip => Ok(ConflictResolution::Skip(
+            "本地已存在同名文件，按冲突策略跳过".to_string(),
+        )),
+        ConflictPolicy::SkipIfSameSha1 => match expected_sha1 {
+            Some(expected) if verify_file_sha1(save_path, Some(expected)).await? => Ok(
+                ConflictResolution::Skip("本地文件 SHA1 与云端一致，跳过下载".to_string()),
+            ),
+            _ => Ok(ConflictResolution::Download(save_path.to_string())),
+        },
+    }
+}
+
+/// 本地文件是否与云端一致，可以跳过下载。
+///
+/// 存在 `.oofp` 说明上次下载尚未完成，本地文件不可信，按需要下载处理。
+pub async fn is_up_to_date(
+    save_path: &str,
+    size: i64,
+    expected_sha1: Option<&str>,
+    compare: SyncCompare,
+) -> Result<bool, DownloadError> {
+    if Path::new(&oofp_path(save_path)).exists() {
+        return Ok(false);
+    }
+    let local_size = match std::fs::metadata(save_path) {
+        Ok(meta) if meta.is_file() => meta.len(),
+        _ => return Ok(false),
+    };
+    if local_size != size.max(0) as u64 {
+        return Ok(false);
+    }
+    match (compare, expected_sha1) {
+        (SyncCompare::Sha1, Some(expected)) => verify_file_sha1(save_path, Some(expected)).await,
+        _ => Ok(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_path_inserts_suffix_before_extension() {
+        assert_eq!(numbered_path("/d/photo.jpg", 1), "/d/photo (1).jpg");
+        assert_eq!(
+            numbered_path("/d/archive.tar.gz", 2),
+            "/d/archive.tar (2).gz"
+        );
+        assert_eq!(numbered_path("/d.v2/README", 1), "/d.v2/README (1)");
+        assert_eq!(numbered_path("/d/.env", 3), "/d/.env (3)");
+    }
+
+    #[tokio::test]
+    async fn up_to_date_compares_size_then_sha1() {
+        let dir = crate::download::test_dir("sync");
+        let path = dir.join("a.txt");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, b"abc").unwrap();
+        // SHA1("abc")
+        let sha1 = "A9993E364706816ABA3E25717850C26C9CD0D89D";
+
+        assert!(
+            is_up_to_date(path, 3, None, SyncCompare::Size)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !is_up_to_date(path, 4, None, SyncCompare::Size)
+                .await
+                .unwrap()
+        );
+        assert!(
+            is_up_to_date(path, 3, Some(sha1), SyncCompare::Sha1)
+                .await
+                .unwrap()
+        );
+        assert!(
+            is_up_to_date(path, 3, Some(&sha1[1..]), SyncCompare::Size)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !is_up_to_date(path, 3, Some(&sha1[1..]), SyncCompare::Sha1)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !is_up_to_date(&format!("{}.missing", path), 3, None, SyncCompare::Size)
+                .await
+                .unwrap()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
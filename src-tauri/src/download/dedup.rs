@@ -0,0 +1,235 @@
+//! 按 SHA1 复用已下载的本地文件。
+//!
+//! 校验通过的下载完成后记录到 `file_index`（SHA1 → 路径、大小、修改时间）。新任务带有预期 SHA1 时，
+//! 先查找大小和修改时间都与记录一致的本地文件，按设置复制、reflink 或硬链接到保存路径，
+//! 不再从 CDN 下载。文件被修改、移动或删除后记录随之失效并被清理。
+//!
+//! 硬链接与源文件共用数据，修改任一处都会影响另一处；跨卷时硬链接和 reflink 都会退回普通复制。
+//! 硬链接也共用文件时间，云端修改时间与源文件不同时改用 reflink，避免改动源文件的时间。
+
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::file_times::{RemoteTimes, apply_times};
+use super::persistence::{oofp_path, part_file_path};
+use super::store::{DbHandle, IndexedFile};
+
+/// 当前复用方式 — 由设置页通过 `download_set_dedup_mode` 更新，任务启动时读取。
+static DEDUP_MODE: Mutex<DedupMode> = Mutex::new(DedupMode::Reflink);
+
+/// 复用本地文件的方式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DedupMode {
+    /// 不复用，始终下载。
+    Off,
+    /// 普通复制。
+    Copy,
+    /// 写时复制（Btrfs、XFS、APFS、ReFS），不支持时退回复制。
+    #[default]
+    Reflink,
+    /// 硬链接，不占额外空间；跨卷时退回复制。
+    Hardlink,
+}
+
+impl DedupMode {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Off => "不复用",
+            Self::Copy => "复制",
+            Self::Reflink => "reflink",
+            Self::Hardlink => "硬链接",
+        }
+    }
+}
+
+/// 文件修改时间（Unix 毫秒）。
+fn mtime_ms(metadata: &std::fs::Metadata) -> Option<i64> {
+    let modified = metadata.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis() as i64)
+}
+
+/// 记录校验通过的已完成文件，失败只记录日志。
+pub async fn index_file(db: &DbHandle, sha1: &str, path: &str) {
+    let Ok(metadata) = tokio::fs::metadata(path).await else {
+        return;
+    };
+    let Some(mtime) = mtime_ms(&metadata) else {
+        return;
+    };
+    let file = IndexedFile {
+        sha1: sha1.to_ascii_uppercase(),
+        path: path.to_string(),
+        size: metadata.len() as i64,
+        mtime,
+    };
+    if let Err(e) = db.index_file(file).await {
+        warn!("[复用] 记录文件索引失败 {}: {}", path, e);
+    }
+}
+
+/// 记录的大小和修改时间与磁盘上的文件一致时视为仍然有效。
+fn is_still_valid(file: &IndexedFile) -> bool {
+    std::fs::metadata(&file.path).is_ok_and(|metadata| {
+        metadata.is_file()
+            && metadata.len() as i64 == file.size
+            && mtime_ms(&metadata) == Some(file.mtime)
+    })
+}
+
+/// 查找与 SHA1 和大小都匹配的有效本地文件，顺带清理失效的记录。
+async fn find_local_copy(db: &DbHandle, sha1: &str, size: i64) -> Option<IndexedFile> {
+    let files = match db.find_indexed_files(sha1.to_ascii_uppercase()).await {
+        Ok(files) => files,
+        Err(e) => {
+            warn!("[复用] 查询文件索引失败: {}", e);
+            return None;
+        }
+    };
+    for file in files {
+        if file.size == size && is_still_valid(&file) {
+            return Some(file);
+        }
+        let _ = db.remove_indexed_file(file.sha1, file.path).await;
+    }
+    None
+}
+
+/// 以指定方式把 `source` 放到 `target`，返回实际使用的方式。
+///
+/// 先写到同目录的临时文件再改名，避免中途失败留下不完整的目标文件。
+fn materialize(source: &Path, target: &Path, mode: DedupMode) -> std::io::Result<DedupMode> {
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut temp = target.as_os_str().to_owned();
+    temp.push(".oofdedup");
+    let temp = Path::new(&temp);
+    let _ = std::fs::remove_file(temp);
+
+    let used = match mode {
+        DedupMode::Hardlink if std::fs::hard_link(source, temp).is_ok() => DedupMode::Hardlink,
+        DedupMode::Reflink if reflink_copy::reflink(source, temp).is_ok() => DedupMode::Reflink,
+        _ => {
+            std::fs::copy(source, temp)?;
+            DedupMode::Copy
+        }
+    };
+    let renamed = std::fs::rename(temp, target);
+    // 目标已是源文件的硬链接时 rename 不做任何事，临时文件仍在，这里一并清理。
+    let _ = std::fs::remove_file(temp);
+    renamed.map(|()| used)
+}
+
+/// 尝试用本地已有的相同文件完成任务，成功时返回复用的源文件路径。
+///
+/// 复用出的文件是独立副本时写入云端时间 `times`；硬链接与源文件共用时间，保持不变。
+/// 未开启复用、没有预期 SHA1 或找不到有效副本时返回空，任务照常下载。
+pub async fn try_reuse(
+    db: &DbHandle,
+    gid: &str,
+    expected_sha1: Option<&str>,
+    size: i64,
+    save_path: &str,
+    times: RemoteTimes,
+) -> Option<String> {
+    let mut mode = *DEDUP_MODE.lock().unwrap();
+    if mode == DedupMode::Off {
+        return None;
+    }
+    let source = find_local_copy(db, expected_sha1?, size).await?;
+    if source.path == save_path {
+        info!("[复用] 保存路径上已是相同文件 gid={}", gid);
+        return Some(source.path);
+    }
+    if mode == DedupMode::Hardlink
+        && times
+            .modified
+            .is_some_and(|secs| secs.saturating_mul(1000) != source.mtime)
+    {
+        debug!("[复用] gid={} 云端时间与源文件不同，改用 reflink", gid);
+        mode = DedupMode::Reflink;
+    }
+
+    let (source_path, target) = (source.path.clone(), save_path.to_string());
+    let result = tokio::task::spawn_blocking(move || {
+        let used = materialize(Path::new(&source_path), Path::new(&target), mode)?;
+        // 复用成功后才清理目标上未完成的断点，避免之后被误当作断点续传；
+        // 复用失败时保留断点，改为下载后仍可续传。
+        let _ = std::fs::remove_file(part_file_path(&target));
+        let _ = std::fs::remove_file(oofp_path(&target));
+        if used != DedupMode::Hardlink
+            && let Err(e) = apply_times(Path::new(&target), times)
+        {
+            warn!("[复用] 设置文件时间失败 {}: {}", target, e);
+        }
+        Ok::<_, std::io::Error>(used)
+    })
+    .await;
+    match result {
+        Ok(Ok(used)) => {
+            info!(
+                "[复用] gid={} 以{}方式复用 {} → {}",
+                gid,
+                used.label(),
+                source.path,
+                save_path
+            );
+            Some(source.path)
+        }
+        Ok(Err(e)) => {
+            warn!("[复用] 复用本地文件失败 gid={}，改为下载: {}", gid, e);
+            None
+        }
+        Err(e) => {
+            warn!("[复用] 复用任务异常 gid={}，改为下载: {}", gid, e);
+            None
+        }
+    }
+}
+
+/// 设置复用本地文件的方式。
+#[tauri::command]
+pub fn download_set_dedup_mode(mode: DedupMode) {
+    *DEDUP_MODE.lock().unwrap() = mode;
+    info!("[设置复用] 设置为 {:?}", mode);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn materialize_places_file_and_falls_back_to_copy() {
+        let dir = crate::download::test_dir("dedup");
+        let source = dir.join("a.bin");
+        std::fs::write(&source, b"same content").unwrap();
+
+        for (i, mode) in [DedupMode::Copy, DedupMode::Reflink, DedupMode::Hardlink]
+            .into_iter()
+            .enumerate()
+        {
+            let target = dir.join(format!("sub/b{}.bin", i));
+            let used = materialize(&source, &target, mode).unwrap();
+            assert!(used == mode || used == DedupMode::Copy);
+            assert_eq!(std::fs::read(&target).unwrap(), b"same content");
+        }
+
+        let indexed = IndexedFile {
+            sha1: "X".into(),
+            path: source.to_string_lossy().into_owned(),
+            size: 12,
+            mtime: mtime_ms(&std::fs::metadata(&source).unwrap()).unwrap(),
+        };
+        assert!(is_still_valid(&indexed));
+        assert!(!is_still_valid(&IndexedFile {
+            size: 13,
+            ..indexed
+        }));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+//! 磁盘空间与保存卷看门狗。
+//!
+//! 任务启动时的 `check_disk_space` 只针对单个任务的大小检查一次，多任务并发或其他程序
+//! 同时写盘时仍可能在下载中途写满磁盘。队列主循环定期用这里的工具检查每个活跃任务所在卷的
+//! 剩余空间：低于预留值时暂停该卷上的任务（状态 `paused_disk_full`），空间恢复后自动继续。
+//!
+//! U 盘拔出、网络盘断开时保存目录整体消失，任务停放为 `waiting_for_volume`，
+//! 轮询到目录重新出现后从 `.oofp` 断点自动继续。
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::types::DownloadError;
+
+/// 默认预留空间 512 MiB。
+pub const DEFAULT_DISK_RESERVE: u64 = 512 * 1024 * 1024;
+/// 检查间隔。
+pub const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// 自动恢复需要在预留值之上额外空出的空间，避免在阈值附近反复暂停/恢复。
+const RESUME_MARGIN: u64 = 256 * 1024 * 1024;
+
+/// 磁盘写满导致暂停时写入数据库的任务状态。
+pub const STATUS_PAUSED_DISK_FULL: &str = "paused_disk_full";
+/// 保存卷不可用时写入数据库的任务状态。
+pub const STATUS_WAITING_FOR_VOLUME: &str = "waiting_for_volume";
+
+/// 任务被队列停放的原因。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParkReason {
+    /// 所在卷剩余空间低于预留值或已写满。
+    DiskFull,
+    /// 保存目录所在卷已断开。
+    VolumeUnavailable,
+}
+
+impl ParkReason {
+    /// 停放期间的数据库任务状态。
+    pub fn status(self) -> &'static str {
+        match self {
+            Self::DiskFull => STATUS_PAUSED_DISK_FULL,
+            Self::VolumeUnavailable => STATUS_WAITING_FOR_VOLUME,
+        }
+    }
+}
+
+/// 单轮检查内的剩余空间缓存 — 同一目录下的多个任务只查询一次。
+#[derive(Default)]
+pub struct SpaceProbe {
+    cache: HashMap<PathBuf, Option<u64>>,
+}
+
+impl SpaceProbe {
+    /// 查询保存路径所在卷的剩余空间；查询失败时返回 `None`，调用方不据此暂停任务。
+    pub fn available(&mut self, save_path: &str) -> Option<u64> {
+        let dir = existing_ancestor(Path::new(save_path))?;
+        *self
+            .cache
+            .entry(dir.clone())
+            .or_insert_with(|| fs4::available_space(&dir).ok())
+    }
+}
+
+/// 找到最近的已存在祖先目录 — 文件夹下载的子目录可能还未创建。
+fn existing_ancestor(path: &Path) -> Option<PathBuf> {
+    path.parent()?
+        .ancestors()
+        .find(|dir| dir.is_dir())
+        .map(Path::to_path_buf)
+}
+
+/// 查询目录所在卷的剩余空间；目录尚未创建时按最近的已存在祖先目录查询。
+pub fn available_space_for_dir(dir: &str) -> Option<u64> {
+    let dir = Path::new(dir).ancestors().find(|d| d.is_dir())?;
+    fs4::available_space(dir).ok()
+}
+
+/// 剩余空间是否已低于预留值；预留值为 0 表示不主动暂停。
+pub fn below_reserve(available: u64, reserve: u64) -> bool {
+    reserve > 0 && available < reserve
+}
+
+/// 剩余空间是否足以自动恢复。
+pub fn can_resume(available: u64, reserve: u64) -> bool {
+    available >= reserve.saturating_add(RESUME_MARGIN)
+}
+
+/// 保存路径所在卷的挂载点；目录尚未创建时按最近的已存在祖先目录查询。
+///
+/// Unix 上向上查找仍属于同一设备的最高一级目录，其他系统取路径的根（如 `D:\`）。
+pub fn mount_point(save_path: &str) -> Option<String> {
+    let dir = existing_ancestor(Path::new(save_path))?;
+    #[cfg(unix)]
+    let root = {
+        use std::os::unix::fs::MetadataExt;
+        let dev = std::fs::metadata(&dir).ok()?.dev();
+        dir.ancestors()
+            .take_while(|d| std::fs::metadata(d).is_ok_and(|m| m.dev() == dev))
+            .last()?
+    };
+    #[cfg(not(unix))]
+    let root = dir.ancestors().last()?;
+    Some(root.to_string_lossy().into_owned())
+}
+
+/// 保存目录是否仍可访问 — 卷被拔出或网络盘断开时目录随之消失。
+///
+/// 卸载后原挂载点目录可能仍然存在（如 `/mnt/usb`），因此记录过挂载点 `volume_root` 时
+/// 还要求保存路径仍位于该挂载点上。只对已经开始写入的任务有意义；全新任务的目录会在创建文件时自动建立。
+pub fn save_dir_available(save_path: &str, volume_root: Option<&str>) -> bool {
+    Path::new(save_path).parent().is_some_and(Path::is_dir)
+        && volume_root.is_none_or(|root| mount_point(save_path).as_deref() == Some(root))
+}
+
+/// 下载中途写盘是否因磁盘写满失败。
+///
+/// 启动前的 `InsufficientDiskSpace` 表示文件本身放不下，按普通失败处理，不在此列。
+pub fn is_disk_full_error(error: &DownloadError) -> bool {
+    matches!(error, DownloadError::Io(e) if e.kind() == std::io::ErrorKind::StorageFull)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_leave_a_margin_between_pause_and_resume() {
+        let reserve = 1024 * 1024 * 1024;
+        assert!(below_reserve(reserve - 1, reserve));
+        assert!(!below_reserve(reserve, reserve));
+        assert!(!below_reserve(0, 0));
+
+        assert!(!can_resume(reserve, reserve));
+        assert!(can_resume(reserve + RESUME_MARGIN, reserve));
+        assert!(can_resume(RESUME_MARGIN, 0));
+    }
+
+    #[test]
+    fn volume_must_stay_on_recorded_mount_point() {
+        let path = std::env::temp_dir().join("volume-check.bin");
+        let path = path.to_str().unwrap();
+        let root = mount_point(path).unwrap();
+        assert!(Path::new(path).starts_with(&root));
+        assert!(save_dir_available(path, None));
+        assert!(save_dir_available(path, Some(&root)));
+        // 挂载点对不上：原来的卷已卸载，只剩空的挂载点目录
+        assert!(!save_dir_available(path, Some("/no-such-volume")));
+    }
+
+    #[test]
+    fn only_storage_full_io_errors_count_as_disk_full() {
+        let full = DownloadError::Io(std::io::ErrorKind::StorageFull.into());
+        let other = DownloadError::Io(std::io::ErrorKind::PermissionDenied.into());
+        assert!(is_disk_full_error(&full));
+        assert!(!is_disk_full_error(&other));
+        assert!(!is_disk_full_error(&DownloadError::InsufficientDiskSpace {
+            needed: 2,
+            available: 1,
+        }));
+    }
+}
@@ -11,7 +11,7 @@ pub use progress::{
     emit_url_expired,
 };
 pub use sync::emit_download_task_status;
-pub use url::UrlResolver;
+pub use url::{UrlKind, UrlResolver};
 
 use super::store::DbHandle;
 use std::sync::Arc;
@@ -16,6 +16,16 @@ use super::super::store::DmError;
 
 const ERR_URL_RESOLVER_POISONED: &str = "下载地址协调器状态异常：内部锁已损坏";
 
+/// 需要前端换取的地址类型。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UrlKind {
+    /// 文件直链（`/open/ufile/downurl`）。
+    File,
+    /// 视频 HLS 播放列表（`/open/video/play`）。
+    Hls,
+}
+
 /// download:url-needed 事件载荷。
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -23,19 +33,23 @@ pub struct UrlNeededPayload {
     pub request_id: String,
     pub task_id: String,
     pub pick_code: String,
+    pub kind: UrlKind,
+    /// HLS 任务期望的最大画面高度，前端据此回传与原任务相同清晰度的播放列表。
+    pub max_height: Option<u32>,
 }
 
+/// 等待前端回填的请求：request_id → 回填结果（地址，或前端换取失败的原因）。
+type PendingUrls = HashMap<String, oneshot::Sender<Result<String, String>>>;
+
 /// 下载地址请求协调器。
 ///
 /// 负责将 download:url-needed 事件和 download_provide_url 命令按 request_id 对应起来。
 pub struct UrlResolver {
-    pending: Mutex<HashMap<String, oneshot::Sender<String>>>,
+    pending: Mutex<PendingUrls>,
 }
 
 impl UrlResolver {
-    fn pending_lock(
-        &self,
-    ) -> Result<std::sync::MutexGuard<'_, HashMap<String, oneshot::Sender<String>>>, DmError> {
+    fn pending_lock(&self) -> Result<std::sync::MutexGuard<'_, PendingUrls>, DmError> {
         self.pending
             .lock()
             .map_err(|_| DmError::Internal(ERR_URL_RESOLVER_POISONED.into()))
@@ -57,6 +71,21 @@ impl UrlResolver {
         app: &AppHandle,
         task_id: &str,
         pick_code: &str,
+    ) -> Result<String, DmError> {
+        self.request_url_for(app, task_id, pick_code, UrlKind::File, None)
+            .await
+    }
+
+    /// 请求指定类型的地址，HLS 任务据此换取新的播放列表。
+    ///
+    /// 前端报告无法换取地址时立即返回 `DmError::NotFound`，不再重试。
+    pub async fn request_url_for(
+        &self,
+        app: &AppHandle,
+        task_id: &str,
+        pick_code: &str,
+        kind: UrlKind,
+        max_height: Option<u32>,
     ) -> Result<String, DmError> {
         for attempt in 0..3u8 {
             let request_id = Uuid::new_v4().to_string();
@@ -74,12 +103,20 @@ impl UrlResolver {
                     request_id: request_id.clone(),
                     task_id: task_id.to_string(),
                     pick_code: pick_code.to_string(),
+                    kind,
+                    max_height,
                 },
             );
 
             // 等待前端回传新地址，超时后清理挂起请求并重试。
             match timeout(Duration::from_secs(30), rx).await {
-                Ok(Ok(url)) => return Ok(url),
+                Ok(Ok(Ok(url))) => return Ok(url),
+                Ok(Ok(Err(reason))) => {
+                    return Err(DmError::NotFound(format!(
+                        "任务 {} 无法获取下载地址：{}",
+                        task_id, reason
+                    )));
+                }
                 _ => {
                     self.pending_lock()?.remove(&request_id);
                     log::warn!(
@@ -97,8 +134,12 @@ impl UrlResolver {
         )))
     }
 
-    /// 由 download_provide_url 命令调用，接收前端回传的新下载地址。
-    pub fn provide_url(&self, request_id: &str, url: String) -> Result<(), DmError> {
+    /// 由 download_provide_url 命令调用，接收前端回传的新下载地址或失败原因。
+    pub fn provide_url(
+        &self,
+        request_id: &str,
+        url: Result<String, String>,
+    ) -> Result<(), DmError> {
         let sender = self.pending_lock()?.remove(request_id);
         match sender {
             Some(tx) => {
@@ -114,11 +155,18 @@ impl UrlResolver {
 }
 
 /// Tauri 命令：前端收到 download:url-needed 后通过该命令回传新的下载地址。
+///
+/// 无法换取地址时传入 `error`，等待中的任务随即失败，不必等到超时。
 #[tauri::command]
 pub async fn download_provide_url(
     request_id: String,
     url: String,
+    error: Option<String>,
     resolver: tauri::State<'_, Arc<UrlResolver>>,
 ) -> Result<(), DmError> {
-    resolver.provide_url(&request_id, url)
+    let result = match error {
+        Some(error) => Err(error),
+        None => Ok(url),
+    };
+    resolver.provide_url(&request_id, result)
 }
@@ -0,0 +1,570 @@
+//! 下载后自动解压。
+//!
+//! 开启后，zip、tar、tar.gz/tgz 与单文件 gz 下载完成时解压到同目录下与压缩包同名的文件夹
+//! （gz 解压为同名文件），已存在时自动加序号。`name.zip.001`、`name.zip.002`…… 形式的分卷
+//! 视为一组，同一文件夹任务（或同为顶层任务）中的所有分卷都下载完成后才开始解压。
+//!
+//! 解压阶段记录在任务的 `extract_status` 中，进度通过 `download:extract-progress` 推送；
+//! 解压失败只记录原因，不影响任务的下载状态。
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use flate2::read::MultiGzDecoder;
+use log::{info, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use super::conflict::numbered_path;
+use super::sanitize::{FilenameRules, sanitize_relative_path};
+use super::store::{DbHandle, DownloadTask as StoreDownloadTask, TaskUpdate};
+
+/// 解压进度推送间隔。
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 自动解压设置 — 由设置页通过 `download_set_auto_extract` 更新。
+static SETTINGS: Mutex<ExtractSettings> = Mutex::new(ExtractSettings {
+    enabled: false,
+    delete_after: false,
+});
+
+/// 正在解压的分卷组，避免多个分卷同时完成时重复解压。
+static IN_PROGRESS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy)]
+struct ExtractSettings {
+    enabled: bool,
+    /// 解压成功后删除压缩包（含全部分卷）。
+    delete_after: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    Gz,
+}
+
+impl ArchiveKind {
+    /// 可识别的扩展名，长的在前，保证 `.tar.gz` 先于 `.gz` 匹配。
+    const EXTENSIONS: &[(&str, ArchiveKind)] = &[
+        (".tar.gz", ArchiveKind::TarGz),
+        (".tgz", ArchiveKind::TarGz),
+        (".tar", ArchiveKind::Tar),
+        (".zip", ArchiveKind::Zip),
+        (".gz", ArchiveKind::Gz),
+    ];
+}
+
+/// 从文件名识别出的压缩包（分卷）。
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ArchiveName {
+    kind: ArchiveKind,
+    /// 去掉分卷序号后的文件名，如 `movie.zip`。
+    base: String,
+    /// 去掉压缩扩展名后的名称，作为解压目标名。
+    stem: String,
+    /// 分卷序号；非分卷为空。
+    part: Option<u32>,
+}
+
+fn ends_with_ignore_case(name: &str, suffix: &str) -> bool {
+    name.len() > suffix.len()
+        && name.is_char_boundary(name.len() - suffix.len())
+        && name[name.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+}
+
+fn parse_archive_name(name: &str) -> Option<ArchiveName> {
+    let (base, part) = match name.rsplit_once('.') {
+        Some((base, digits)) if digits.len() == 3 && digits.bytes().all(|b| b.is_ascii_digit()) => {
+            (base, digits.parse().ok())
+        }
+        _ => (name, None),
+    };
+    let (ext, kind) = ArchiveKind::EXTENSIONS
+        .iter()
+        .find(|(ext, _)| ends_with_ignore_case(base, ext))?;
+    Some(ArchiveName {
+        kind: *kind,
+        base: base.to_string(),
+        stem: base[..base.len() - ext.len()].to_string(),
+        part,
+    })
+}
+
+/// 待解压的一组文件：单个压缩包或按序排列的全部分卷。
+#[derive(Debug)]
+struct ArchiveSet {
+    kind: ArchiveKind,
+    dir: PathBuf,
+    stem: String,
+    parts: Vec<PathBuf>,
+    gids: Vec<String>,
+}
+
+impl ArchiveSet {
+    fn key(&self) -> String {
+        self.parts[0].to_string_lossy().to_lowercase()
+    }
+}
+
+fn task_file(task: &StoreDownloadTask) -> Option<(PathBuf, ArchiveName)> {
+    if task.is_folder {
+        return None;
+    }
+    let path = PathBuf::from(task.path.as_deref()?);
+    let name = parse_archive_name(path.file_name()?.to_str()?)?;
+    Some((path, name))
+}
+
+/// 找出任务所在的压缩包组；分卷未全部完成或已解压过时返回空。
+fn ready_set(task: &StoreDownloadTask, siblings: &[StoreDownloadTask]) -> Option<ArchiveSet> {
+    let (path, name) = task_file(task)?;
+    let dir = path.parent()?.to_path_buf();
+    if name.part.is_none() {
+        return (task.extract_status.is_none()).then(|| ArchiveSet {
+            kind: name.kind,
+            dir,
+            stem: name.stem,
+            parts: vec![path],
+            gids: vec![task.gid.clone()],
+        });
+    }
+
+    let mut parts: Vec<(u32, PathBuf, &StoreDownloadTask)> = siblings
+        .iter()
+        .filter_map(|sibling| {
+            let (sibling_path, sibling_name) = task_file(sibling)?;
+            let same_set = sibling_path.parent() == Some(dir.as_path())
+                && sibling_name.base.eq_ignore_ascii_case(&name.base);
+            Some((sibling_name.part?, sibling_path, sibling)).filter(|_| same_set)
+        })
+        .collect();
+    parts.sort_by_key(|(part, _, _)| *part);
+    let first = parts.first()?.0;
+    let contiguous = parts
+        .iter()
+        .enumerate()
+        .all(|(i, (part, _, _))| *part == first + i as u32);
+    let ready = first <= 1
+        && contiguous
+        && parts
+            .iter()
+            .all(|(_, _, t)| t.status == "complete" && t.extract_status.is_none());
+    ready.then(|| ArchiveSet {
+        kind: name.kind,
+        dir,
+        stem: name.stem,
+        gids: parts.iter().map(|(_, _, t)| t.gid.clone()).collect(),
+        parts: parts.into_iter().map(|(_, path, _)| path).collect(),
+    })
+}
+
+/// 按顺序把多个分卷拼接为一个可定位的流。
+struct PartsReader {
+    files: Vec<File>,
+    /// 每个分卷在拼接流中的起始偏移，末尾追加总长度。
+    starts: Vec<u64>,
+    pos: u64,
+    /// 已读取到的最远位置，用于估算 tar/gz 的解压进度。
+    read_pos: Arc<AtomicU64>,
+}
+
+impl PartsReader {
+    fn open(parts: &[PathBuf], read_pos: Arc<AtomicU64>) -> io::Result<Self> {
+        let mut files = Vec::with_capacity(parts.len());
+        let mut starts = vec![0];
+        for part in parts {
+            let file = File::open(part)?;
+            let len = file.metadata()?.len();
+            starts.push(starts.last().unwrap() + len);
+            files.push(file);
+        }
+        Ok(Self {
+            files,
+            starts,
+            pos: 0,
+            read_pos,
+        })
+    }
+
+    fn len(&self) -> u64 {
+        *self.starts.last().unwrap()
+    }
+}
+
+impl Read for PartsReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self.starts.partition_point(|start| *start <= self.pos) - 1;
+        let offset = self.pos - self.starts[index];
+        let remaining = self.starts[index + 1] - self.pos;
+        let file = &mut self.files[index];
+        file.seek(SeekFrom::Start(offset))?;
+        let limit = buf.len().min(remaining as usize);
+        let n = file.read(&mut buf[..limit])?;
+        self.pos += n as u64;
+        self.read_pos.fetch_max(self.pos, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl Seek for PartsReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// 解压进度（字节）：zip 按已解出的原始大小计，tar/gz 按已读取的压缩数据计。
+#[derive(Default)]
+struct ExtractProgress {
+    done: AtomicU64,
+    total: AtomicU64,
+}
+
+/// 条目路径只保留普通分量并按目标文件系统清理，拒绝绝对路径和 `..`。
+fn safe_relative_path(path: &Path, rules: FilenameRules) -> Option<String> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    let relative = sanitize_relative_path(&components.join("/"), rules);
+    (!relative.is_empty()).then_some(relative)
+}
+
+fn extract_zip(reader: PartsReader, dest: &Path, progress: &ExtractProgress) -> io::Result<()> {
+    let rules = FilenameRules::for_dir(&dest.to_string_lossy());
+    let mut archive = zip::ZipArchive::new(BufReader::new(reader)).map_err(io::Error::other)?;
+    let total = (0..archive.len())
+        .filter_map(|i| archive.by_index_raw(i).ok().map(|entry| entry.size()))
+        .sum();
+    progress.total.store(total, Ordering::Relaxed);
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(io::Error::other)?;
+        let Some(relative) = entry
+            .enclosed_name()
+            .and_then(|path| safe_relative_path(&path, rules))
+        else {
+            warn!("[解压] 跳过不安全的路径: {}", entry.name());
+            continue;
+        };
+        let out = dest.join(relative);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&out)?;
+            continue;
+        }
+        if let Some(parent) = out.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&out)?)?;
+        progress.done.fetch_add(entry.size(), Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+fn extract_tar(reader: impl Read, dest: &Path) -> io::Result<()> {
+    let rules = FilenameRules::for_dir(&dest.to_string_lossy());
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let entry_type = entry.header().entry_type();
+        // 链接可能指向解压目录之外，只解出普通文件和目录。
+        if !(entry_type.is_file() || entry_type.is_dir()) {
+            warn!("[解压] 跳过链接或特殊文件: {}", path.display());
+            continue;
+        }
+        let Some(relative) = safe_relative_path(&path, rules) else {
+            warn!("[解压] 跳过不安全的路径: {}", path.display());
+            continue;
+        };
+        let out = dest.join(relative);
+        if entry_type.is_dir() {
+            std::fs::create_dir_all(&out)?;
+            continue;
+        }
+        if let Some(parent) = out.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&out)?)?;
+    }
+    Ok(())
+}
+
+/// 解压到 `dest`（gz 为目标文件路径，其余为目标目录）。
+fn extract_blocking(
+    set: &ArchiveSet,
+    dest: &Path,
+    progress: Arc<ExtractProgress>,
+) -> io::Result<()> {
+    let read_pos = Arc::new(AtomicU64::new(0));
+    let reader = PartsReader::open(&set.parts, read_pos.clone())?;
+    let total = reader.len();
+    let track_input = || {
+        progress.total.store(total, Ordering::Relaxed);
+        progress
+            .done
+            .store(read_pos.load(Ordering::Relaxed), Ordering::Relaxed);
+    };
+
+    match set.kind {
+        ArchiveKind::Zip => {
+            std::fs::create_dir_all(dest)?;
+            extract_zip(reader, dest, &progress)
+        }
+        ArchiveKind::Tar | ArchiveKind::TarGz => {
+            std::fs::create_dir_all(dest)?;
+            let reader = ProgressReader {
+                inner: BufReader::new(reader),
+                on_read: &track_input,
+            };
+            if set.kind == ArchiveKind::TarGz {
+                extract_tar(MultiGzDecoder::new(reader), dest)
+            } else {
+                extract_tar(reader, dest)
+            }
+        }
+        ArchiveKind::Gz => {
+            let mut decoder = MultiGzDecoder::new(ProgressReader {
+                inner: BufReader::new(reader),
+                on_read: &track_input,
+            });
+            io::copy(&mut decoder, &mut File::create(dest)?).map(|_| ())
+        }
+    }
+}
+
+/// 每次读取后回调，用于把拼接流的读取位置同步到进度。
+struct ProgressReader<'a, R> {
+    inner: R,
+    on_read: &'a dyn Fn(),
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        (self.on_read)();
+        Ok(n)
+    }
+}
+
+/// 解压目标：与压缩包同目录、同名（去掉扩展名），已存在时加序号。
+fn destination(set: &ArchiveSet) -> PathBuf {
+    let base = set.dir.join(&set.stem).to_string_lossy().into_owned();
+    let mut dest = base.clone();
+    let mut n = 2;
+    while Path::new(&dest).exists() {
+        dest = numbered_path(&base, n);
+        n += 1;
+    }
+    PathBuf::from(dest)
+}
+
+/// 解压进度事件。
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractProgressEvent {
+    /// 分卷组中每个任务的 gid。
+    pub task_ids: Vec<String>,
+    pub extracted_bytes: u64,
+    pub total_bytes: u64,
+}
+
+async fn set_extract_status(
+    db: &DbHandle,
+    app: &AppHandle,
+    gids: &[String],
+    status: &str,
+    error: Option<String>,
+) {
+    for gid in gids {
+        if let Err(e) = db
+            .update_task(
+                gid.clone(),
+                TaskUpdate {
+                    extract_status: Some(Some(status.to_string())),
+                    extract_error: Some(error.clone()),
+                    ..TaskUpdate::default()
+                },
+            )
+            .await
+        {
+            warn!("[解压] 更新解压状态失败 {}: {}", gid, e);
+        }
+        super::events::emit_download_task_status(app, db, gid).await;
+    }
+}
+
+async fn extract_set(set: ArchiveSet, delete_after: bool, db: &DbHandle, app: &AppHandle) {
+    let dest = destination(&set);
+    info!(
+        "[解压] 开始 {} ({}个分卷) → {}",
+        set.parts[0].display(),
+        set.parts.len(),
+        dest.display()
+    );
+    set_extract_status(db, app, &set.gids, "extracting", None).await;
+
+    let progress = Arc::new(ExtractProgress::default());
+    let set = Arc::new(set);
+    let mut handle = {
+        let (set, dest, progress) = (set.clone(), dest.clone(), progress.clone());
+        tokio::task::spawn_blocking(move || extract_blocking(&set, &dest, progress))
+    };
+    let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+    let result = loop {
+        tokio::select! {
+            result = &mut handle => break result.unwrap_or_else(|e| Err(io::Error::other(e))),
+            _ = ticker.tick() => {
+                let _ = app.emit(
+                    "download:extract-progress",
+                    ExtractProgressEvent {
+                        task_ids: set.gids.clone(),
+                        extracted_bytes: progress.done.load(Ordering::Relaxed),
+                        total_bytes: progress.total.load(Ordering::Relaxed),
+                    },
+                );
+            }
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            info!("[解压] 完成 {}", dest.display());
+            set_extract_status(db, app, &set.gids, "extracted", None).await;
+            if delete_after {
+                for part in &set.parts {
+                    if let Err(e) = std::fs::remove_file(part) {
+                        warn!("[解压] 删除压缩包失败 {}: {}", part.display(), e);
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            warn!("[解压] 失败 {}: {}", set.parts[0].display(), e);
+            // 目标是本次新建的，清理解出一半的内容。
+            let _ = if dest.is_dir() {
+                std::fs::remove_dir_all(&dest)
+            } else {
+                std::fs::remove_file(&dest)
+            };
+            set_extract_status(db, app, &set.gids, "failed", Some(e.to_string())).await;
+        }
+    }
+}
+
+/// 文件下载完成后检查是否需要解压，在后台任务中执行，调用方不等待结果。
+pub fn on_file_complete(gid: &str, db: &DbHandle, app: &AppHandle) {
+    let settings = *SETTINGS.lock().unwrap();
+    if !settings.enabled {
+        return;
+    }
+    let (gid, db, app) = (gid.to_string(), db.clone(), app.clone());
+    tokio::spawn(async move {
+        let Ok(Some(task)) = db.get_task_by_gid(gid).await else {
+            return;
+        };
+        if task_file(&task).is_none() {
+            return;
+        }
+        let siblings = match &task.parent_gid {
+            Some(parent_gid) => db.get_child_tasks(parent_gid.clone()).await,
+            None => db.get_top_level_tasks().await,
+        };
+        let Some(set) = siblings
+            .ok()
+            .and_then(|siblings| ready_set(&task, &siblings))
+        else {
+            return;
+        };
+        let key = set.key();
+        {
+            let mut in_progress = IN_PROGRESS.lock().unwrap();
+            if in_progress.contains(&key) {
+                return;
+            }
+            in_progress.push(key.clone());
+        }
+        extract_set(set, settings.delete_after, &db, &app).await;
+        IN_PROGRESS.lock().unwrap().retain(|k| k != &key);
+    });
+}
+
+/// 更新自动解压设置。
+#[tauri::command]
+pub fn download_set_auto_extract(enabled: bool, delete_after: Option<bool>) {
+    let settings = ExtractSettings {
+        enabled,
+        delete_after: delete_after.unwrap_or(false),
+    };
+    info!("[设置自动解压] {:?}", settings);
+    *SETTINGS.lock().unwrap() = settings;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_names_and_parts() {
+        let zip = parse_archive_name("Movie.ZIP").unwrap();
+        assert_eq!(
+            (zip.kind, zip.stem.as_str(), zip.part),
+            (ArchiveKind::Zip, "Movie", None)
+        );
+        let part = parse_archive_name("a.tar.gz.002").unwrap();
+        assert_eq!(
+            (part.kind, part.base.as_str(), part.stem.as_str(), part.part),
+            (ArchiveKind::TarGz, "a.tar.gz", "a", Some(2))
+        );
+        assert_eq!(parse_archive_name("b.gz").unwrap().kind, ArchiveKind::Gz);
+        assert_eq!(parse_archive_name("c.7z.001"), None);
+        assert_eq!(parse_archive_name("d.mkv"), None);
+    }
+
+    #[test]
+    fn parts_reader_concatenates_and_seeks() {
+        let dir = crate::download::test_dir("parts");
+        let parts: Vec<PathBuf> = [&b"hello "[..], b"split ", b"world"]
+            .iter()
+            .enumerate()
+            .map(|(i, data)| {
+                let path = dir.join(format!("x.zip.{:03}", i + 1));
+                std::fs::write(&path, data).unwrap();
+                path
+            })
+            .collect();
+
+        let mut reader = PartsReader::open(&parts, Arc::default()).unwrap();
+        let mut all = String::new();
+        reader.read_to_string(&mut all).unwrap();
+        assert_eq!(all, "hello split world");
+        reader.seek(SeekFrom::End(-8)).unwrap();
+        let mut tail = String::new();
+        reader.read_to_string(&mut tail).unwrap();
+        assert_eq!(tail, "it world");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
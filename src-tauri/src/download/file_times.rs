@@ -0,0 +1,146 @@
+//! 保留云端文件时间。
+//!
+//! 下载完成的文件默认以写入时间为修改时间，按日期排序的相册和基于“大小+修改时间”的比对都会失效。
+//! 入队时记录 115 的修改/创建时间，文件校验通过并提交后写回；文件夹在全部子任务结束后
+//! 再设置各级目录的时间，避免写入子文件时目录时间又被刷新。
+//!
+//! 创建时间只有 Windows 和 macOS 支持设置，其他系统只设置修改时间。
+
+use std::fs::{File, FileTimes};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// 115 文件的时间（Unix 秒）。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RemoteTimes {
+    pub modified: Option<i64>,
+    pub created: Option<i64>,
+}
+
+impl RemoteTimes {
+    pub fn new(modified: Option<i64>, created: Option<i64>) -> Self {
+        Self { modified, created }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modified.is_none() && self.created.is_none()
+    }
+}
+
+/// 文件夹中的子目录及其时间，由前端遍历 115 目录时收集。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderDirItem {
+    /// 相对于文件夹根目录的路径。
+    pub path: String,
+    #[serde(default)]
+    pub remote_mtime: Option<i64>,
+    #[serde(default)]
+    pub remote_ctime: Option<i64>,
+}
+
+fn to_system_time(secs: i64) -> Option<SystemTime> {
+    (secs > 0).then(|| UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
+
+/// 打开文件或目录用于修改时间。Windows 打开目录需要 `FILE_FLAG_BACKUP_SEMANTICS`。
+fn open_for_times(path: &Path) -> std::io::Result<File> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(path)
+    }
+    #[cfg(not(windows))]
+    {
+        if path.is_dir() {
+            File::open(path)
+        } else {
+            std::fs::OpenOptions::new().write(true).open(path)
+        }
+    }
+}
+
+/// 把云端时间写到本地文件或目录；没有可用时间时不做任何事。
+pub fn apply_times(path: &Path, times: RemoteTimes) -> std::io::Result<()> {
+    let modified = times.modified.and_then(to_system_time);
+    let created = times.created.and_then(to_system_time);
+    if modified.is_none() && created.is_none() {
+        return Ok(());
+    }
+
+    let mut file_times = FileTimes::new();
+    if let Some(modified) = modified {
+        file_times = file_times.set_modified(modified);
+    }
+    #[cfg(windows)]
+    if let Some(created) = created {
+        use std::os::windows::fs::FileTimesExt;
+        file_times = file_times.set_created(created);
+    }
+    #[cfg(target_os = "macos")]
+    if let Some(created) = created {
+        use std::os::macos::fs::FileTimesExt;
+        file_times = file_times.set_created(created);
+    }
+    #[cfg(not(any(windows, target_os = "macos")))]
+    let _ = created;
+
+    open_for_times(path)?.set_times(file_times)
+}
+
+/// 设置文件夹各级目录的时间：先子目录，最后是文件夹本身。
+///
+/// 单个子目录设置失败只记录日志并继续，返回值仅反映文件夹本身。
+pub fn apply_dir_times(
+    parent_path: &str,
+    parent_times: RemoteTimes,
+    dirs: &[FolderDirItem],
+) -> std::io::Result<()> {
+    for dir in dirs {
+        let path = Path::new(parent_path).join(&dir.path);
+        if !path.is_dir() {
+            continue;
+        }
+        if let Err(e) = apply_times(&path, RemoteTimes::new(dir.remote_mtime, dir.remote_ctime)) {
+            warn!("[时间] 设置目录时间失败 {}: {}", path.display(), e);
+        }
+    }
+    apply_times(Path::new(parent_path), parent_times)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_times_sets_modified_time() {
+        let dir = crate::download::test_dir("times");
+        let file = dir.join("a.txt");
+        std::fs::write(&file, b"x").unwrap();
+        let mtime = 1_600_000_000;
+
+        apply_times(&file, RemoteTimes::new(Some(mtime), None)).unwrap();
+        apply_dir_times(
+            dir.to_str().unwrap(),
+            RemoteTimes::new(Some(mtime), None),
+            &[],
+        )
+        .unwrap();
+
+        for path in [&file, &dir] {
+            let modified = std::fs::metadata(path).unwrap().modified().unwrap();
+            assert_eq!(
+                modified.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                mtime as u64
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,786 @@
+//! HLS 离线下载引擎。
+//!
+//! 流程：解析播放列表 → 选择清晰度 → 并发下载 TS 分段到 `<save_path>.hls/` 临时目录
+//! → AES-128 解密 → 按序拼接为单个 `.ts` 文件。
+//!
+//! 分段进度复用 `.oofp`：每个 TS 分段对应一个 `Segment`，完成后 `end` 记录分段落盘长度。
+//! 分段大小事先未知，未完成的分段续传时整段重下，已完成分段按临时文件长度校验后跳过。
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use aes::Aes128;
+use cbc::cipher::{BlockDecryptMut, KeyIvInit, block_padding::Pkcs7};
+use futures_util::StreamExt;
+use log::{debug, info, warn};
+use reqwest::Url;
+use tauri::AppHandle;
+use tokio::sync::{Semaphore, mpsc, watch};
+use tokio::task::JoinSet;
+use tokio::time::Duration;
+
+use super::super::events::{
+    DownloadSegmentEvent, DownloadTaskEvent, ProgressRegistry, UrlExpiredEvent,
+    emit_segment_status, emit_task_status, emit_url_expired,
+};
+use super::super::http::{
+    DownloadSignal, MAX_SEGMENT_RETRIES, RETRY_BASE_DELAY_MS, current_epoch_ms, is_retryable_error,
+    is_url_expired, spawn_flush_task,
+};
+use super::super::persistence::{ProgressFile, part_file_path};
+use super::super::throttle::get_throttle;
+use super::super::types::{
+    DownloadError, ProgressUpdate, Segment, SegmentStatus, TaskAbortReason, TaskStatus,
+};
+use super::super::volume;
+use super::super::writer::FileWriter;
+use super::playlist::{MediaPlaylist, MediaSegment, Playlist, parse_playlist, select_variant};
+
+type Aes128CbcDec = cbc::Decryptor<Aes128>;
+
+/// 已获取的 AES-128 密钥缓存：key uri → key。
+type KeyCache = tokio::sync::Mutex<HashMap<String, [u8; 16]>>;
+
+type SegmentJoinSet = JoinSet<Result<(u16, u64), (u16, DownloadError)>>;
+
+/// 播放列表地址刷新的最大次数。
+const MAX_PLAYLIST_REFRESHES: u32 = 5;
+/// 等待前端回传新播放列表的超时秒数。
+const PLAYLIST_REFRESH_TIMEOUT_SECS: u64 = 30;
+/// 分段流读取超时秒数，与普通分片下载一致。
+const READ_TIMEOUT_SECS: u64 = 60;
+
+/// 分段临时目录：`<save_path>.hls/`。
+fn parts_dir(save_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.hls", save_path))
+}
+
+fn part_path(dir: &Path, index: u16) -> PathBuf {
+    dir.join(format!("{:05}.ts", index))
+}
+
+fn check_signal(signal_rx: &watch::Receiver<DownloadSignal>) -> Result<(), DownloadError> {
+    match *signal_rx.borrow() {
+        DownloadSignal::Running => Ok(()),
+        DownloadSignal::Paused => Err(DownloadError::TaskAborted(TaskAbortReason::Paused)),
+        DownloadSignal::Cancelled => Err(DownloadError::TaskAborted(TaskAbortReason::Cancelled)),
+    }
+}
+
+fn status_error(status: reqwest::StatusCode) -> DownloadError {
+    let code = status.as_u16();
+    if is_url_expired(code) {
+        return DownloadError::UrlExpired {
+            status: code,
+            message: format!("HLS 地址已失效（HTTP {}）", code),
+        };
+    }
+    DownloadError::HttpStatus {
+        status: code,
+        message: status.canonical_reason().unwrap_or("未知状态").to_string(),
+    }
+}
+
+pub(super) async fn fetch_playlist(
+    client: &reqwest::Client,
+    url: &str,
+    token: &str,
+    user_agent: &str,
+) -> Result<Playlist, DownloadError> {
+    let base = Url::parse(url)
+        .map_err(|err| DownloadError::Hls(format!("播放列表地址无效 {}：{}", url, err)))?;
+    let resp = client
+        .get(url)
+        .header("Authorization", format!("Bearer {}", token))
+        .header("User-Agent", user_agent)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(status_error(resp.status()));
+    }
+    let content = resp.text().await?;
+    parse_playlist(&content, &base)
+}
+
+/// 获取媒体播放列表；传入主播放列表时按 `max_height` 选择清晰度后再取一次。
+async fn resolve_media_playlist(
+    client: &reqwest::Client,
+    url: &str,
+    max_height: Option<u32>,
+    token: &str,
+    user_agent: &str,
+) -> Result<MediaPlaylist, DownloadError> {
+    match fetch_playlist(client, url, token, user_agent).await? {
+        Playlist::Media(media) => Ok(media),
+        Playlist::Master(variants) => {
+            let variant = select_variant(&variants, max_height)
+                .ok_or_else(|| DownloadError::Hls("主播放列表中没有可用的清晰度".to_string()))?;
+            debug!(
+                "[HLS] 选择清晰度 {}p 码率={} uri={}...",
+                variant.height.unwrap_or(0),
+                variant.bandwidth,
+                &variant.uri[..variant.uri.len().min(80)]
+            );
+            match fetch_playlist(client, &variant.uri, token, user_agent).await? {
+                Playlist::Media(media) => Ok(MediaPlaylist {
+                    height: variant.height,
+                    ..media
+                }),
+                Playlist::Master(_) => Err(DownloadError::Hls(
+                    "清晰度变体仍是主播放列表，不支持多级嵌套".to_string(),
+                )),
+            }
+        }
+    }
+}
+
+async fn segment_key(
+    client: &reqwest::Client,
+    keys: &KeyCache,
+    uri: &str,
+    token: &str,
+    user_agent: &str,
+) -> Result<[u8; 16], DownloadError> {
+    let mut cache = keys.lock().await;
+    if let Some(key) = cache.get(uri) {
+        return Ok(*key);
+    }
+
+    let resp = client
+        .get(uri)
+        .header("Authorization", format!("Bearer {}", token))
+        .header("User-Agent", user_agent)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(status_error(resp.status()));
+    }
+    let bytes = resp.bytes().await?;
+    let key: [u8; 16] = bytes.as_ref().try_into().map_err(|_| {
+        DownloadError::Hls(format!(
+            "AES-128 密钥长度应为 16 字节，实际 {}",
+            bytes.len()
+        ))
+    })?;
+    cache.insert(uri.to_string(), key);
+    Ok(key)
+}
+
+/// 流式获取单个分段的完整字节，期间响应暂停/取消并走全局限速。
+async fn fetch_segment_bytes(
+    client: &reqwest::Client,
+    segment: &MediaSegment,
+    index: u16,
+    token: &str,
+    user_agent: &str,
+    progress_tx: &mpsc::Sender<ProgressUpdate>,
+    task_id: &str,
+    signal_rx: &mut watch::Receiver<DownloadSignal>,
+) -> Result<Vec<u8>, DownloadError> {
+    check_signal(signal_rx)?;
+
+    let resp = client
+        .get(&segment.uri)
+        .header("Authorization", format!("Bearer {}", token))
+        .header("User-Agent", user_agent)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(status_error(resp.status()));
+    }
+
+    let mut data = Vec::with_capacity(resp.content_length().unwrap_or(0) as usize);
+    let mut stream = resp.bytes_stream();
+    loop {
+        tokio::select! {
+            biased;
+
+            result = signal_rx.changed() => {
+                if result.is_err() {
+                    return Err(DownloadError::TaskAborted(TaskAbortReason::SignalChannelClosed));
+                }
+                check_signal(signal_rx)?;
+            }
+
+            chunk = tokio::time::timeout(Duration::from_secs(READ_TIMEOUT_SECS), stream.next()) => {
+                let bytes = match chunk {
+                    Ok(Some(bytes)) => bytes?,
+                    Ok(None) => break,
+                    Err(_) => {
+                        return Err(DownloadError::TaskAborted(TaskAbortReason::ReadTimeout {
+                            seconds: READ_TIMEOUT_SECS,
+                        }));
+                    }
+                };
+                data.extend_from_slice(&bytes);
+                let _ = progress_tx.try_send(ProgressUpdate {
+                    task_id: task_id.to_string(),
+                    segment_index: index,
+                    downloaded: data.len() as u64,
+                });
+                get_throttle().consume(bytes.len()).await;
+                volume::consume(task_id, bytes.len()).await;
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// 下载、解密并落盘单个分段，对瞬态错误按指数退避重试。
+async fn download_hls_segment(
+    client: reqwest::Client,
+    media: Arc<MediaPlaylist>,
+    index: u16,
+    keys: Arc<KeyCache>,
+    token: String,
+    user_agent: String,
+    dir: PathBuf,
+    progress_tx: mpsc::Sender<ProgressUpdate>,
+    task_id: String,
+    mut signal_rx: watch::Receiver<DownloadSignal>,
+) -> Result<u64, DownloadError> {
+    let segment = &media.segments[index as usize];
+    let mut retry_count: u32 = 0;
+
+    let data = loop {
+        match fetch_segment_bytes(
+            &client,
+            segment,
+            index,
+            &token,
+            &user_agent,
+            &progress_tx,
+            &task_id,
+            &mut signal_rx,
+        )
+        .await
+        {
+            Ok(data) => break data,
+            Err(e) if is_retryable_error(&e) && retry_count < MAX_SEGMENT_RETRIES => {
+                retry_count += 1;
+                let delay_ms =
+                    RETRY_BASE_DELAY_MS * 2u64.pow(retry_count - 1) + ((index as u64) % 16) * 150;
+                warn!(
+                    "[HLS分段{}][{}] 重试#{} (延迟{}ms): {}",
+                    index, task_id, retry_count, delay_ms, e
+                );
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    let data = match segment.key {
+        Some(ref key) => {
+            let key_bytes = segment_key(&client, &keys, &key.uri, &token, &user_agent).await?;
+            let iv = key
+                .iv
+                .unwrap_or_else(|| (segment.sequence as u128).to_be_bytes());
+            Aes128CbcDec::new(&key_bytes.into(), &iv.into())
+                .decrypt_padded_vec_mut::<Pkcs7>(&data)
+                .map_err(|_| DownloadError::Hls(format!("分段 {} 解密失败", index)))?
+        }
+        None => data,
+    };
+
+    // 先写临时文件再改名，崩溃后不会留下被误判为完成的半截分段。
+    let target = part_path(&dir, index);
+    let tmp = target.with_extension("ts.tmp");
+    tokio::fs::write(&tmp, &data).await?;
+    tokio::fs::rename(&tmp, &target).await?;
+
+    let written = data.len() as u64;
+    let _ = progress_tx.try_send(ProgressUpdate {
+        task_id: task_id.clone(),
+        segment_index: index,
+        downloaded: written,
+    });
+    Ok(written)
+}
+
+fn spawn_hls_segment(
+    join_set: &mut SegmentJoinSet,
+    semaphore: &Arc<Semaphore>,
+    client: &reqwest::Client,
+    media: &Arc<MediaPlaylist>,
+    index: u16,
+    keys: &Arc<KeyCache>,
+    token: &str,
+    user_agent: &str,
+    dir: &Path,
+    progress_tx: &mpsc::Sender<ProgressUpdate>,
+    task_id: &str,
+    signal_rx: &watch::Receiver<DownloadSignal>,
+) {
+    let semaphore = semaphore.clone();
+    let client = client.clone();
+    let media = media.clone();
+    let keys = keys.clone();
+    let token = token.to_string();
+    let user_agent = user_agent.to_string();
+    let dir = dir.to_path_buf();
+    let progress_tx = progress_tx.clone();
+    let task_id = task_id.to_string();
+    let signal_rx = signal_rx.clone();
+
+    join_set.spawn(async move {
+        let _permit = match semaphore.acquire_owned().await {
+            Ok(permit) => permit,
+            Err(_) => {
+                return Err((
+                    index,
+                    DownloadError::TaskAborted(TaskAbortReason::SemaphoreClosed),
+                ));
+            }
+        };
+        download_hls_segment(
+            client,
+            media,
+            index,
+            keys,
+            token,
+            user_agent,
+            dir,
+            progress_tx,
+            task_id,
+            signal_rx,
+        )
+        .await
+        .map(|bytes| (index, bytes))
+        .map_err(|e| (index, e))
+    });
+}
+
+/// 等待队列层刷新播放列表地址，返回新地址。
+async fn wait_for_playlist_refresh(
+    app: &AppHandle,
+    task_id: &str,
+    pick_code: &str,
+    url_rx: &mut watch::Receiver<String>,
+    url_refresh_requested: &AtomicBool,
+    signal_rx: &mut watch::Receiver<DownloadSignal>,
+) -> Result<String, DownloadError> {
+    let current = url_rx.borrow_and_update().clone();
+    if url_refresh_requested
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+    {
+        emit_url_expired(
+            app,
+            &UrlExpiredEvent {
+                task_id: task_id.to_string(),
+                pick_code: pick_code.to_string(),
+            },
+        );
+    }
+
+    let wait = async {
+        loop {
+            tokio::select! {
+                result = url_rx.changed() => {
+                    if result.is_err() {
+                        return Err(DownloadError::TaskAborted(TaskAbortReason::UrlChannelClosed));
+                    }
+                    let new_url = url_rx.borrow_and_update().clone();
+                    if new_url != current {
+                        return Ok(new_url);
+                    }
+                }
+                result = signal_rx.changed() => {
+                    if result.is_err() {
+                        return Err(DownloadError::TaskAborted(TaskAbortReason::SignalChannelClosed));
+                    }
+                    check_signal(signal_rx)?;
+                }
+            }
+        }
+    };
+
+    tokio::time::timeout(Duration::from_secs(PLAYLIST_REFRESH_TIMEOUT_SECS), wait)
+        .await
+        .unwrap_or(Err(DownloadError::TaskAborted(
+            TaskAbortReason::UrlRefreshTimeout {
+                seconds: PLAYLIST_REFRESH_TIMEOUT_SECS,
+            },
+        )))
+}
+
+/// 按序拼接分段临时文件为最终 `.ts` 文件。
+fn concat_parts(dir: &Path, save_path: &str, count: usize) -> Result<(), DownloadError> {
+    if let Some(parent) = Path::new(save_path).parent()
+        && !parent.exists()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut output = std::io::BufWriter::new(std::fs::File::create(save_path)?);
+    for index in 0..count {
+        let mut part = std::fs::File::open(part_path(dir, index as u16))?;
+        std::io::copy(&mut part, &mut output)?;
+    }
+    let file = output
+        .into_inner()
+        .map_err(|err| DownloadError::Io(err.into_error()))?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// 载入或新建 `.oofp` 分段记录。
+///
+/// 旧断点与当前播放列表是同一清晰度（分段数、目标时长、画面高度一致）时复用，
+/// 已完成分段需临时文件仍在且长度吻合；否则清理旧断点和临时目录，从头开始。
+fn prepare_segments(
+    db: &ProgressFile,
+    task_id: &str,
+    file_name: &str,
+    save_path: &str,
+    playlist_url: &str,
+    pick_code: &str,
+    estimated_size: u64,
+    media: &MediaPlaylist,
+    dir: &Path,
+) -> Result<Vec<Segment>, DownloadError> {
+    let segment_count = media.segments.len();
+    let existing = db.load_task(save_path).ok().map(|existing| {
+        // 旧版本断点未记录目标时长，此时只比较分段数和画面高度
+        let mismatch = media.mismatch_against(
+            existing.segments.len(),
+            existing.hls_target_duration.or(media.target_duration),
+            existing.hls_height,
+        );
+        (existing, mismatch)
+    });
+    match existing {
+        Some((existing, None)) => {
+            if existing.task_id != task_id {
+                db.rebind_task(
+                    save_path,
+                    &existing.task_id,
+                    task_id,
+                    file_name,
+                    playlist_url,
+                    pick_code,
+                    None,
+                )?;
+            }
+            db.update_task_status(task_id, "active")?;
+            db.update_hls_rendition(task_id, media.target_duration, media.height)?;
+
+            let mut segments = existing.segments;
+            for segment in segments.iter_mut() {
+                let part_len = std::fs::metadata(part_path(dir, segment.index))
+                    .map(|meta| meta.len())
+                    .ok();
+                let intact = segment.status == SegmentStatus::Completed
+                    && part_len == Some(segment.downloaded);
+                if !intact {
+                    segment.status = SegmentStatus::Pending;
+                    segment.downloaded = 0;
+                    segment.end = 0;
+                }
+            }
+            db.save_segments(task_id, &segments)?;
+            return Ok(segments);
+        }
+        Some((existing, Some(reason))) => {
+            info!("[HLS][{}] 旧断点与播放列表{}，重新下载", task_id, reason);
+            db.delete_task(&existing.task_id)?;
+        }
+        None => {}
+    }
+
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    std::fs::create_dir_all(dir)?;
+
+    db.save_task(
+        task_id,
+        file_name,
+        estimated_size,
+        save_path,
+        playlist_url,
+        None,
+        None,
+        None,
+        pick_code,
+        None,
+        current_epoch_ms(),
+    )?;
+    db.update_hls_rendition(task_id, media.target_duration, media.height)?;
+    let segments: Vec<Segment> = (0..segment_count)
+        .map(|index| Segment {
+            index: index as u16,
+            start: 0,
+            end: 0,
+            status: SegmentStatus::Pending,
+            downloaded: 0,
+        })
+        .collect();
+    db.save_segments(task_id, &segments)?;
+    Ok(segments)
+}
+
+/// HLS 流离线下载。
+///
+/// `url_rx` 携带播放列表地址，地址失效时通过 `url_refresh_requested` 请求队列层刷新；
+/// `estimated_size` 仅用于进度展示和磁盘空间预检。
+pub async fn download_hls(
+    client: &reqwest::Client,
+    task_id: &str,
+    file_name: &str,
+    save_path: &str,
+    pick_code: &str,
+    max_height: Option<u32>,
+    token: &str,
+    user_agent: &str,
+    estimated_size: u64,
+    db: &Arc<ProgressFile>,
+    app: &AppHandle,
+    mut signal_rx: watch::Receiver<DownloadSignal>,
+    mut url_rx: watch::Receiver<String>,
+    url_refresh_requested: Arc<AtomicBool>,
+    segment_semaphore: Arc<Semaphore>,
+    progress_registry: Arc<ProgressRegistry>,
+) -> Result<(), DownloadError> {
+    let mut playlist_url = url_rx.borrow().clone();
+    // 恢复任务时入队记录的播放列表可能早已过期，首次解析失效则先刷新一次。
+    let media =
+        match resolve_media_playlist(client, &playlist_url, max_height, token, user_agent).await {
+            Err(DownloadError::UrlExpired { .. }) => {
+                playlist_url = wait_for_playlist_refresh(
+                    app,
+                    task_id,
+                    pick_code,
+                    &mut url_rx,
+                    &url_refresh_requested,
+                    &mut signal_rx,
+                )
+                .await?;
+                resolve_media_playlist(client, &playlist_url, max_height, token, user_agent).await?
+            }
+            result => result?,
+        };
+    let segment_count = media.segments.len();
+    if segment_count > u16::MAX as usize {
+        return Err(DownloadError::Hls(format!(
+            "分段数 {} 超出上限 {}",
+            segment_count,
+            u16::MAX
+        )));
+    }
+
+    // 分段临时文件与拼接结果会同时存在，按两倍预估大小检查空间。
+    FileWriter::check_disk_space(save_path, estimated_size.saturating_mul(2))?;
+
+    let dir = parts_dir(save_path);
+    let mut segments = prepare_segments(
+        db,
+        task_id,
+        file_name,
+        save_path,
+        &playlist_url,
+        pick_code,
+        estimated_size,
+        &media,
+        &dir,
+    )?;
+
+    let completed_count = segments
+        .iter()
+        .filter(|s| s.status == SegmentStatus::Completed)
+        .count();
+    info!(
+        "[HLS][{}] 开始下载 文件={} 分段={} 已完成={} 时长={:.0}s",
+        task_id,
+        file_name,
+        segment_count,
+        completed_count,
+        media.total_duration()
+    );
+
+    let (progress_tx, progress_rx) = mpsc::channel::<ProgressUpdate>(1024);
+    let progress_snapshot: Arc<Mutex<HashMap<u16, u64>>> = Arc::new(Mutex::new(
+        segments
+            .iter()
+            .filter(|s| s.status == SegmentStatus::Completed)
+            .map(|s| (s.index, s.downloaded))
+            .collect(),
+    ));
+    let flush_handle = spawn_flush_task(
+        Arc::clone(db),
+        app.clone(),
+        task_id.to_string(),
+        estimated_size,
+        file_name.to_string(),
+        progress_registry,
+        progress_snapshot,
+        None,
+        progress_rx,
+    );
+
+    emit_task_status(
+        app,
+        &DownloadTaskEvent {
+            task_id: task_id.to_string(),
+            status: TaskStatus::Active,
+        },
+    );
+
+    let keys: Arc<KeyCache> = Arc::new(tokio::sync::Mutex::new(HashMap::new()));
+    let mut media = Arc::new(media);
+    let mut pending: Vec<u16> = segments
+        .iter()
+        .filter(|s| s.status != SegmentStatus::Completed)
+        .map(|s| s.index)
+        .collect();
+    let mut refresh_count: u32 = 0;
+
+    let outcome: Result<(), DownloadError> = loop {
+        let mut join_set: SegmentJoinSet = JoinSet::new();
+        for index in pending.drain(..) {
+            spawn_hls_segment(
+                &mut join_set,
+                &segment_semaphore,
+                client,
+                &media,
+                index,
+                &keys,
+                token,
+                user_agent,
+                &dir,
+                &progress_tx,
+                task_id,
+                &signal_rx,
+            );
+        }
+
+        let mut expired: Vec<u16> = Vec::new();
+        let mut failure: Option<DownloadError> = None;
+        while let Some(result) = join_set.join_next().await {
+            match result {
+                Ok(Ok((index, bytes))) => {
+                    if let Some(segment) = segments.iter_mut().find(|s| s.index == index) {
+                        segment.status = SegmentStatus::Completed;
+                        segment.downloaded = bytes;
+                        segment.end = bytes.saturating_sub(1);
+                        let _ = db.insert_segments(task_id, std::slice::from_ref(segment));
+                    }
+                    emit_segment_status(
+                        app,
+                        &DownloadSegmentEvent {
+                            task_id: task_id.to_string(),
+                            segment_index: index,
+                            status: SegmentStatus::Completed,
+                            downloaded: bytes,
+                        },
+                    );
+                }
+                Ok(Err((index, DownloadError::UrlExpired { status, .. }))) => {
+                    debug!("[HLS分段{}][{}] 地址失效 HTTP {}", index, task_id, status);
+                    expired.push(index);
+                }
+                Ok(Err((index, e))) => {
+                    if !e.is_user_abort() {
+                        warn!("[HLS分段{}][{}] 失败: {}", index, task_id, e);
+                    }
+                    failure = Some(e);
+                    join_set.abort_all();
+                    break;
+                }
+                Err(e) => {
+                    failure = Some(DownloadError::JoinError(e.to_string()));
+                    join_set.abort_all();
+                    break;
+                }
+            }
+        }
+
+        if let Some(e) = failure {
+            break Err(e);
+        }
+        if expired.is_empty() {
+            break Ok(());
+        }
+
+        refresh_count += 1;
+        if refresh_count > MAX_PLAYLIST_REFRESHES {
+            break Err(DownloadError::TaskAborted(
+                TaskAbortReason::UrlRefreshExhausted {
+                    max_attempts: MAX_PLAYLIST_REFRESHES,
+                },
+            ));
+        }
+        warn!(
+            "[HLS][{}] {}个分段地址失效，刷新播放列表 (第{}次)",
+            task_id,
+            expired.len(),
+            refresh_count
+        );
+
+        let refreshed = match wait_for_playlist_refresh(
+            app,
+            task_id,
+            pick_code,
+            &mut url_rx,
+            &url_refresh_requested,
+            &mut signal_rx,
+        )
+        .await
+        {
+            Ok(url) => resolve_media_playlist(client, &url, max_height, token, user_agent).await,
+            Err(e) => Err(e),
+        };
+        match refreshed {
+            Ok(new_media) => match media.rendition_mismatch(&new_media) {
+                None => {
+                    media = Arc::new(new_media);
+                    pending = expired;
+                }
+                Some(reason) => {
+                    break Err(DownloadError::Hls(format!("刷新后的播放列表{}", reason)));
+                }
+            },
+            Err(e) => break Err(e),
+        }
+    };
+
+    // 关闭进度通道，等待刷盘循环最终写入 .oofp。
+    drop(progress_tx);
+    let _ = flush_handle.await;
+
+    if let Err(e) = outcome {
+        let paused = e.is_user_abort();
+        for segment in segments
+            .iter()
+            .filter(|s| s.status != SegmentStatus::Completed)
+        {
+            // 未完成分段续传时整段重下，进度归零避免 .oofp 虚报。
+            let status = if paused {
+                SegmentStatus::Paused
+            } else {
+                SegmentStatus::Failed
+            };
+            let _ = db.update_segment_status(task_id, segment.index, &status, 0);
+        }
+        let _ = db.update_task_status(task_id, if paused { "paused" } else { "error" });
+        return Err(e);
+    }
+
+    // 先拼接到 `.part`，完整落盘后再原子 rename，避免外部工具拾取半成品。
+    let concat_dir = dir.clone();
+    let concat_path = part_file_path(save_path);
+    tokio::task::spawn_blocking(move || concat_parts(&concat_dir, &concat_path, segment_count))
+        .await
+        .map_err(|e| DownloadError::JoinError(e.to_string()))??;
+    std::fs::rename(part_file_path(save_path), save_path)?;
+
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        warn!("[HLS][{}] 清理分段临时目录失败: {}", task_id, e);
+    }
+    db.delete_task(task_id)?;
+    info!("[HLS][{}] 下载完成 文件={}", task_id, file_name);
+    Ok(())
+}
@@ -0,0 +1,37 @@
+//! HLS 流下载。
+//!
+//! - `playlist`：m3u8 主/媒体播放列表解析与清晰度选择
+//! - `downloader`：分段并发下载、AES-128 解密与拼接
+
+mod downloader;
+pub mod playlist;
+
+pub use downloader::download_hls;
+
+use super::store::DmError;
+use downloader::fetch_playlist;
+use playlist::{Playlist, Variant};
+
+/// 探测 HLS 播放列表可用的清晰度，供前端选择 `max_height`。
+///
+/// 传入的是媒体播放列表时只返回一个码率未知的变体。
+#[tauri::command]
+pub async fn download_probe_hls(
+    playlist_url: String,
+    token: String,
+    user_agent: String,
+    client: tauri::State<'_, reqwest::Client>,
+) -> Result<Vec<Variant>, DmError> {
+    match fetch_playlist(&client, &playlist_url, &token, &user_agent)
+        .await
+        .map_err(|e| DmError::Internal(e.to_string()))?
+    {
+        Playlist::Master(variants) => Ok(variants),
+        Playlist::Media(_) => Ok(vec![Variant {
+            uri: playlist_url,
+            bandwidth: 0,
+            width: None,
+            height: None,
+        }]),
+    }
+}
@@ -0,0 +1,408 @@
+//! m3u8 播放列表解析。
+//!
+//! 只覆盖 115 转码流实际用到的子集：主播放列表的 `EXT-X-STREAM-INF`，
+//! 媒体播放列表的 `EXTINF`、`EXT-X-TARGETDURATION`、`EXT-X-MEDIA-SEQUENCE` 与
+//! `EXT-X-KEY`（NONE / AES-128）。
+//! 所有分段、密钥和变体地址在解析阶段即按播放列表地址解析为绝对地址。
+
+use reqwest::Url;
+use serde::Serialize;
+
+use super::super::types::DownloadError;
+
+/// 主播放列表中的单个清晰度变体。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Variant {
+    pub uri: String,
+    /// 峰值码率，单位 bits/sec；媒体播放列表直连时为 0。
+    pub bandwidth: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// AES-128 分段密钥描述。
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentKey {
+    pub uri: String,
+    /// 显式 IV；缺省时使用分段媒体序号。
+    pub iv: Option<[u8; 16]>,
+}
+
+/// 媒体播放列表中的单个 TS 分段。
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaSegment {
+    pub uri: String,
+    pub duration: f64,
+    /// 媒体序号，AES-128 缺省 IV 由它派生。
+    pub sequence: u64,
+    pub key: Option<SegmentKey>,
+}
+
+/// 媒体播放列表。
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaPlaylist {
+    pub segments: Vec<MediaSegment>,
+    /// `EXT-X-TARGETDURATION`，单位秒。
+    pub target_duration: Option<u64>,
+    /// 经主播放列表选出时为所选变体的画面高度；直接给出媒体播放列表时为空。
+    pub height: Option<u32>,
+}
+
+impl MediaPlaylist {
+    /// 刷新得到的播放列表与原列表不是同一清晰度时返回差异说明。
+    ///
+    /// 已下载的分段会和新地址下载的分段拼接成同一个文件，分段数、目标时长或画面高度
+    /// 任何一项不同都说明换了转码档位，不能混用。
+    pub fn rendition_mismatch(&self, refreshed: &Self) -> Option<String> {
+        refreshed.mismatch_against(self.segments.len(), self.target_duration, self.height)
+    }
+
+    /// 与原有分段记录的分段数、目标时长和画面高度比较，不是同一清晰度时返回差异说明。
+    ///
+    /// 续传时原记录来自 `.oofp`，与 [`Self::rendition_mismatch`] 使用同一套规则。
+    pub fn mismatch_against(
+        &self,
+        segment_count: usize,
+        target_duration: Option<u64>,
+        height: Option<u32>,
+    ) -> Option<String> {
+        if self.segments.len() != segment_count {
+            return Some(format!(
+                "分段数 {} 与原列表 {} 不一致",
+                self.segments.len(),
+                segment_count
+            ));
+        }
+        if self.target_duration != target_duration {
+            return Some(format!(
+                "目标时长 {:?} 与原列表 {:?} 不一致",
+                self.target_duration, target_duration
+            ));
+        }
+        if let (Some(old), Some(new)) = (height, self.height)
+            && old != new
+        {
+            return Some(format!("清晰度 {}p 与原列表 {}p 不一致", new, old));
+        }
+        None
+    }
+
+    /// 播放总时长，单位秒。
+    pub fn total_duration(&self) -> f64 {
+        self.segments.iter().map(|segment| segment.duration).sum()
+    }
+}
+
+/// 解析结果：主播放列表或媒体播放列表。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Playlist {
+    Master(Vec<Variant>),
+    Media(MediaPlaylist),
+}
+
+fn playlist_error(message: impl Into<String>) -> DownloadError {
+    DownloadError::Hls(message.into())
+}
+
+/// 拆分属性列表，如 `BANDWIDTH=1280000,RESOLUTION=1280x720,CODECS="avc1,mp4a"`。
+///
+/// 引号内的逗号不作为分隔符，返回值会去掉外层引号。
+fn parse_attributes(raw: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = raw.trim();
+
+    while !rest.is_empty() {
+        let Some(eq_pos) = rest.find('=') else {
+            break;
+        };
+        let key = rest[..eq_pos].trim().to_ascii_uppercase();
+        let after_eq = &rest[eq_pos + 1..];
+
+        let (value, remaining) = if let Some(quoted) = after_eq.strip_prefix('"') {
+            match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            }
+        } else {
+            match after_eq.find(',') {
+                Some(end) => (&after_eq[..end], &after_eq[end..]),
+                None => (after_eq, ""),
+            }
+        };
+
+        attributes.push((key, value.trim().to_string()));
+        rest = remaining.trim_start_matches(',').trim_start();
+    }
+
+    attributes
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.as_str())
+}
+
+/// 解析 `0x` 前缀的 128 位十六进制 IV。
+fn parse_iv(raw: &str) -> Result<[u8; 16], DownloadError> {
+    let hex = raw
+        .strip_prefix("0x")
+        .or_else(|| raw.strip_prefix("0X"))
+        .unwrap_or(raw);
+    if hex.len() != 32 {
+        return Err(playlist_error(format!("IV 长度无效：{}", raw)));
+    }
+
+    let mut iv = [0u8; 16];
+    for (i, byte) in iv.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| playlist_error(format!("IV 不是合法的十六进制：{}", raw)))?;
+    }
+    Ok(iv)
+}
+
+fn resolve_uri(base: &Url, uri: &str) -> Result<String, DownloadError> {
+    base.join(uri)
+        .map(|url| url.to_string())
+        .map_err(|err| playlist_error(format!("无法解析地址 {}：{}", uri, err)))
+}
+
+fn parse_key(
+    attributes: &[(String, String)],
+    base: &Url,
+) -> Result<Option<SegmentKey>, DownloadError> {
+    match attribute(attributes, "METHOD") {
+        None | Some("NONE") => Ok(None),
+        Some("AES-128") => {
+            let uri = attribute(attributes, "URI")
+                .ok_or_else(|| playlist_error("AES-128 密钥缺少 URI"))?;
+            let iv = attribute(attributes, "IV").map(parse_iv).transpose()?;
+            Ok(Some(SegmentKey {
+                uri: resolve_uri(base, uri)?,
+                iv,
+            }))
+        }
+        Some(method) => Err(playlist_error(format!("暂不支持的加密方式：{}", method))),
+    }
+}
+
+/// 解析 m3u8 文本；`base` 为播放列表自身地址，用于解析相对路径。
+pub fn parse_playlist(content: &str, base: &Url) -> Result<Playlist, DownloadError> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    if lines.next() != Some("#EXTM3U") {
+        return Err(playlist_error("缺少 #EXTM3U 头，不是有效的 m3u8 播放列表"));
+    }
+
+    let mut variants = Vec::new();
+    let mut pending_variant: Option<Variant> = None;
+
+    let mut segments = Vec::new();
+    let mut media_sequence: u64 = 0;
+    let mut target_duration: Option<u64> = None;
+    let mut current_key: Option<SegmentKey> = None;
+    let mut pending_duration: Option<f64> = None;
+
+    for line in lines {
+        if let Some(raw) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            let attributes = parse_attributes(raw);
+            let (width, height) = attribute(&attributes, "RESOLUTION")
+                .and_then(|resolution| resolution.split_once(['x', 'X']))
+                .map(|(w, h)| (w.parse().ok(), h.parse().ok()))
+                .unwrap_or((None, None));
+            pending_variant = Some(Variant {
+                uri: String::new(),
+                bandwidth: attribute(&attributes, "BANDWIDTH")
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(0),
+                width,
+                height,
+            });
+        } else if let Some(raw) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            media_sequence = raw
+                .trim()
+                .parse()
+                .map_err(|_| playlist_error(format!("媒体序号无效：{}", raw)))?;
+        } else if let Some(raw) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            target_duration = raw.trim().parse().ok();
+        } else if let Some(raw) = line.strip_prefix("#EXT-X-KEY:") {
+            current_key = parse_key(&parse_attributes(raw), base)?;
+        } else if let Some(raw) = line.strip_prefix("#EXTINF:") {
+            let duration = raw.split(',').next().unwrap_or("").trim();
+            pending_duration = Some(
+                duration
+                    .parse()
+                    .map_err(|_| playlist_error(format!("分段时长无效：{}", raw)))?,
+            );
+        } else if line.starts_with("#EXT-X-BYTERANGE") {
+            return Err(playlist_error("暂不支持 EXT-X-BYTERANGE 分段"));
+        } else if line.starts_with('#') {
+            // 其余标签（版本、目标时长、结束标记等）与离线下载无关。
+            continue;
+        } else if let Some(mut variant) = pending_variant.take() {
+            variant.uri = resolve_uri(base, line)?;
+            variants.push(variant);
+        } else {
+            segments.push(MediaSegment {
+                uri: resolve_uri(base, line)?,
+                duration: pending_duration.take().unwrap_or(0.0),
+                sequence: media_sequence + segments.len() as u64,
+                key: current_key.clone(),
+            });
+        }
+    }
+
+    if !variants.is_empty() {
+        return Ok(Playlist::Master(variants));
+    }
+    if segments.is_empty() {
+        return Err(playlist_error("播放列表中没有可下载的分段"));
+    }
+    Ok(Playlist::Media(MediaPlaylist {
+        segments,
+        target_duration,
+        height: None,
+    }))
+}
+
+/// 按期望高度选择变体。
+///
+/// 指定 `max_height` 时取不超过该高度的最高码率变体，全部超出则退回最低码率；
+/// 未指定时直接取最高码率。未标注分辨率的变体视为满足高度限制。
+pub fn select_variant(variants: &[Variant], max_height: Option<u32>) -> Option<&Variant> {
+    let Some(limit) = max_height else {
+        return variants.iter().max_by_key(|variant| variant.bandwidth);
+    };
+
+    variants
+        .iter()
+        .filter(|variant| variant.height.is_none_or(|height| height <= limit))
+        .max_by_key(|variant| variant.bandwidth)
+        .or_else(|| variants.iter().min_by_key(|variant| variant.bandwidth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MediaPlaylist, Playlist, Url, Variant, parse_playlist, select_variant};
+
+    fn base() -> Url {
+        Url::parse("https://cdn.example.com/video/abc/index.m3u8?token=1").unwrap()
+    }
+
+    fn variant(bandwidth: u64, height: Option<u32>) -> Variant {
+        Variant {
+            uri: format!("https://cdn.example.com/{bandwidth}.m3u8"),
+            bandwidth,
+            width: None,
+            height,
+        }
+    }
+
+    #[test]
+    fn parses_master_playlist_variants() {
+        let content = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"\n\
+            360p/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080\n\
+            https://other.example.com/1080p.m3u8\n";
+
+        let Playlist::Master(variants) = parse_playlist(content, &base()).unwrap() else {
+            panic!("expected master playlist");
+        };
+
+        assert_eq!(variants.len(), 2);
+        assert_eq!(
+            variants[0].uri,
+            "https://cdn.example.com/video/abc/360p/index.m3u8"
+        );
+        assert_eq!(variants[0].bandwidth, 800_000);
+        assert_eq!(variants[0].height, Some(360));
+        assert_eq!(variants[1].uri, "https://other.example.com/1080p.m3u8");
+        assert_eq!(variants[1].width, Some(1920));
+    }
+
+    #[test]
+    fn parses_media_playlist_with_keys_and_sequence() {
+        let content = "#EXTM3U\n\
+            #EXT-X-VERSION:3\n\
+            #EXT-X-TARGETDURATION:10\n\
+            #EXT-X-MEDIA-SEQUENCE:7\n\
+            #EXTINF:10.0,\n\
+            seg0.ts\n\
+            #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x000102030405060708090a0b0c0d0e0f\n\
+            #EXTINF:9.5,\n\
+            seg1.ts\n\
+            #EXT-X-KEY:METHOD=NONE\n\
+            #EXTINF:4,\n\
+            seg2.ts\n\
+            #EXT-X-ENDLIST\n";
+
+        let Playlist::Media(media) = parse_playlist(content, &base()).unwrap() else {
+            panic!("expected media playlist");
+        };
+
+        assert_eq!(media.segments.len(), 3);
+        assert_eq!(media.segments[0].sequence, 7);
+        assert_eq!(media.segments[2].sequence, 9);
+        assert!(media.segments[0].key.is_none());
+        let key = media.segments[1].key.as_ref().unwrap();
+        assert_eq!(key.uri, "https://cdn.example.com/video/abc/key.bin");
+        assert_eq!(key.iv.unwrap()[15], 0x0f);
+        assert!(media.segments[2].key.is_none());
+        assert!((media.total_duration() - 23.5).abs() < f64::EPSILON);
+        assert_eq!(media.target_duration, Some(10));
+
+        // 刷新后换了清晰度或分段方式的列表不能与已下载分段混用
+        assert!(media.rendition_mismatch(&media).is_none());
+        let other_target = MediaPlaylist {
+            target_duration: Some(6),
+            ..media.clone()
+        };
+        assert!(media.rendition_mismatch(&other_target).is_some());
+        let at_720 = MediaPlaylist {
+            height: Some(720),
+            ..media.clone()
+        };
+        let at_1080 = MediaPlaylist {
+            height: Some(1080),
+            ..media.clone()
+        };
+        assert!(at_720.rendition_mismatch(&at_1080).is_some());
+        // 续传时与 .oofp 记录的清晰度比较
+        assert!(at_1080.mismatch_against(3, Some(10), Some(1080)).is_none());
+        assert!(at_1080.mismatch_against(3, Some(10), Some(720)).is_some());
+    }
+
+    #[test]
+    fn rejects_non_m3u8_content() {
+        assert!(parse_playlist("<html></html>", &base()).is_err());
+        assert!(parse_playlist("#EXTM3U\n#EXT-X-ENDLIST\n", &base()).is_err());
+    }
+
+    #[test]
+    fn selects_variant_by_height_limit() {
+        let variants = vec![
+            variant(800_000, Some(360)),
+            variant(2_500_000, Some(720)),
+            variant(5_000_000, Some(1080)),
+        ];
+
+        assert_eq!(
+            select_variant(&variants, None).unwrap().bandwidth,
+            5_000_000
+        );
+        assert_eq!(
+            select_variant(&variants, Some(720)).unwrap().bandwidth,
+            2_500_000
+        );
+        assert_eq!(
+            select_variant(&variants, Some(240)).unwrap().bandwidth,
+            800_000
+        );
+        assert!(select_variant(&[], Some(720)).is_none());
+    }
+}
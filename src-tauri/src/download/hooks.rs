@@ -0,0 +1,406 @@
+//! 下载完成后的钩子 — 运行本地程序或向 HTTP 地址 POST JSON。
+//!
+//! 钩子由设置页通过 `download_set_hooks` 下发，在文件完成、文件夹全部结束或任务失败时触发，
+//! 常用于通知媒体库重新扫描或启动后处理脚本。钩子在独立的 tokio 任务中运行，有超时限制，
+//! 输出只写入日志；执行失败不影响任务状态。
+//!
+//! 本地程序可通过参数占位符 `{path}` `{name}` `{gid}` `{event}` `{status}` `{size}`
+//! 或环境变量 `OOF_HOOK_*` 获取任务信息，`OOF_HOOK_PAYLOAD` 为与 webhook 相同的 JSON。
+
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::store::{DbHandle, DmError, DownloadTask as StoreDownloadTask};
+
+/// 未设置超时时的默认值。
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+/// 超时上限，避免卡住的钩子长期占用进程。
+const MAX_TIMEOUT_SECS: u64 = 600;
+/// 写入日志的输出长度上限（字节）。
+const MAX_LOGGED_OUTPUT: usize = 4096;
+
+/// 当前生效的钩子 — 由设置页通过 `download_set_hooks` 更新，任务结束时读取。
+static HOOKS: Mutex<Vec<DownloadHook>> = Mutex::new(Vec::new());
+
+/// 触发钩子的事件。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HookEvent {
+    /// 单个文件下载完成（含文件夹中的子文件）。
+    FileComplete,
+    /// 文件夹全部子任务结束，`status` 可能为 complete、partial_error 或 error。
+    FolderComplete,
+    /// 任务下载失败或校验失败。
+    TaskFailed,
+}
+
+impl HookEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::FileComplete => "fileComplete",
+            Self::FolderComplete => "folderComplete",
+            Self::TaskFailed => "taskFailed",
+        }
+    }
+}
+
+/// 钩子的执行方式。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum HookAction {
+    /// 运行本地程序，不经过 shell。
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// 向 HTTP(S) 地址 POST JSON。
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+}
+
+/// 单个钩子配置。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadHook {
+    /// 钩子名称，仅用于日志。
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub events: Vec<HookEvent>,
+    #[serde(flatten)]
+    pub action: HookAction,
+    /// 超时（秒），缺省 30 秒。
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl DownloadHook {
+    fn label(&self) -> String {
+        self.name.clone().unwrap_or_else(|| match &self.action {
+            HookAction::Command { program, .. } => program.clone(),
+            HookAction::Webhook { url, .. } => url.clone(),
+        })
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.timeout_secs
+                .unwrap_or(DEFAULT_TIMEOUT_SECS)
+                .clamp(1, MAX_TIMEOUT_SECS),
+        )
+    }
+}
+
+/// 传给钩子的任务信息。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HookPayload {
+    pub event: HookEvent,
+    pub gid: String,
+    pub name: String,
+    pub path: Option<String>,
+    pub size: i64,
+    pub status: String,
+    pub error: Option<String>,
+    pub is_folder: bool,
+    pub parent_gid: Option<String>,
+    pub total_files: Option<i64>,
+    pub completed_files: Option<i64>,
+    pub failed_files: Option<i64>,
+    pub completed_at: Option<i64>,
+}
+
+impl HookPayload {
+    fn new(event: HookEvent, task: StoreDownloadTask) -> Self {
+        Self {
+            event,
+            gid: task.gid,
+            name: task.name,
+            path: task.path,
+            size: task.size,
+            status: task.status,
+            error: task.error_message,
+            is_folder: task.is_folder,
+            parent_gid: task.parent_gid,
+            total_files: task.total_files,
+            completed_files: task.completed_files,
+            failed_files: task.failed_files,
+            completed_at: task.completed_at,
+        }
+    }
+
+    /// 替换命令参数中的占位符。
+    fn render_arg(&self, arg: &str) -> String {
+        arg.replace("{path}", self.path.as_deref().unwrap_or(""))
+            .replace("{name}", &self.name)
+            .replace("{gid}", &self.gid)
+            .replace("{event}", self.event.as_str())
+            .replace("{status}", &self.status)
+            .replace("{size}", &self.size.to_string())
+    }
+
+    fn env_vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("OOF_HOOK_EVENT", self.event.as_str().to_string()),
+            ("OOF_HOOK_GID", self.gid.clone()),
+            ("OOF_HOOK_NAME", self.name.clone()),
+            ("OOF_HOOK_PATH", self.path.clone().unwrap_or_default()),
+            ("OOF_HOOK_SIZE", self.size.to_string()),
+            ("OOF_HOOK_STATUS", self.status.clone()),
+            ("OOF_HOOK_ERROR", self.error.clone().unwrap_or_default()),
+            (
+                "OOF_HOOK_PAYLOAD",
+                serde_json::to_string(self).unwrap_or_default(),
+            ),
+        ]
+    }
+}
+
+fn validate_hooks(hooks: &[DownloadHook]) -> Result<(), DmError> {
+    for (index, hook) in hooks.iter().enumerate() {
+        match &hook.action {
+            HookAction::Command { program, .. } if program.trim().is_empty() => {
+                return Err(DmError::Internal(format!(
+                    "第 {} 个钩子缺少程序路径",
+                    index + 1
+                )));
+            }
+            HookAction::Webhook { url, .. } => {
+                let parsed = reqwest::Url::parse(url).map_err(|e| {
+                    DmError::Internal(format!("第 {} 个钩子的地址无效：{}", index + 1, e))
+                })?;
+                if !matches!(parsed.scheme(), "http" | "https") {
+                    return Err(DmError::Internal(format!(
+                        "第 {} 个钩子仅支持 HTTP(S) 地址",
+                        index + 1
+                    )));
+                }
+            }
+            HookAction::Command { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+fn hooks_for(event: HookEvent) -> Vec<DownloadHook> {
+    HOOKS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|hook| hook.enabled && hook.events.contains(&event))
+        .cloned()
+        .collect()
+}
+
+/// 截断过长的输出，按字符边界处理。
+fn truncate_output(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim();
+    if text.len() <= MAX_LOGGED_OUTPUT {
+        return text.to_string();
+    }
+    let mut end = MAX_LOGGED_OUTPUT;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &text[..end])
+}
+
+async fn run_command(
+    label: &str,
+    program: &str,
+    args: &[String],
+    payload: &HookPayload,
+    timeout: Duration,
+) {
+    let mut command = tokio::process::Command::new(program);
+    command
+        .args(args.iter().map(|arg| payload.render_arg(arg)))
+        .envs(payload.env_vars())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(windows)]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    match tokio::time::timeout(timeout, command.output()).await {
+        Ok(Ok(output)) => {
+            let stdout = truncate_output(&output.stdout);
+            let stderr = truncate_output(&output.stderr);
+            if output.status.success() {
+                info!("[钩子] {} 执行完成 gid={}", label, payload.gid);
+            } else {
+                warn!(
+                    "[钩子] {} 退出码异常 gid={}: {}",
+                    label, payload.gid, output.status
+                );
+            }
+            if !stdout.is_empty() {
+                info!("[钩子] {} stdout: {}", label, stdout);
+            }
+            if !stderr.is_empty() {
+                warn!("[钩子] {} stderr: {}", label, stderr);
+            }
+        }
+        Ok(Err(e)) => warn!("[钩子] {} 启动失败 gid={}: {}", label, payload.gid, e),
+        Err(_) => warn!(
+            "[钩子] {} 超时（{}秒）已终止 gid={}",
+            label,
+            timeout.as_secs(),
+            payload.gid
+        ),
+    }
+}
+
+async fn post_webhook(
+    label: &str,
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    payload: &HookPayload,
+    timeout: Duration,
+    client: &reqwest::Client,
+) {
+    let body = match serde_json::to_vec(payload) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("[钩子] {} 序列化任务信息失败: {}", label, e);
+            return;
+        }
+    };
+    let mut request = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .timeout(timeout);
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+    match request.send().await {
+        Ok(response) => {
+            let status = response.status();
+            let body = response
+                .bytes()
+                .await
+                .map(|bytes| truncate_output(&bytes))
+                .unwrap_or_default();
+            if status.is_success() {
+                info!("[钩子] {} 响应 {} gid={}", label, status, payload.gid);
+            } else {
+                warn!(
+                    "[钩子] {} 响应 {} gid={}: {}",
+                    label, status, payload.gid, body
+                );
+            }
+        }
+        Err(e) => warn!("[钩子] {} 请求失败 gid={}: {}", label, payload.gid, e),
+    }
+}
+
+async fn run_hook(hook: &DownloadHook, payload: &HookPayload, client: &reqwest::Client) {
+    let label = hook.label();
+    match &hook.action {
+        HookAction::Command { program, args } => {
+            run_command(&label, program, args, payload, hook.timeout()).await;
+        }
+        HookAction::Webhook { url, headers } => {
+            post_webhook(&label, url, headers, payload, hook.timeout(), client).await;
+        }
+    }
+}
+
+/// 触发事件对应的钩子。
+///
+/// 在后台任务中读取任务记录并依次执行钩子，调用方不等待结果；应在任务状态写入数据库之后调用。
+pub fn dispatch(event: HookEvent, gid: &str, db: &DbHandle, client: &reqwest::Client) {
+    let hooks = hooks_for(event);
+    if hooks.is_empty() {
+        return;
+    }
+    let gid = gid.to_string();
+    let db = db.clone();
+    let client = client.clone();
+    tokio::spawn(async move {
+        let task = match db.get_task_by_gid(gid.clone()).await {
+            Ok(Some(task)) => task,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("[钩子] 读取任务失败 {}: {}", gid, e);
+                return;
+            }
+        };
+        let payload = HookPayload::new(event, task);
+        for hook in &hooks {
+            run_hook(hook, &payload, &client).await;
+        }
+    });
+}
+
+/// 更新下载钩子，配置无效时整体拒绝，保留原配置。
+#[tauri::command]
+pub fn download_set_hooks(hooks: Vec<DownloadHook>) -> Result<(), DmError> {
+    validate_hooks(&hooks)?;
+    info!("[设置钩子] 共{}个", hooks.len());
+    *HOOKS.lock().unwrap() = hooks;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hook_config_and_placeholders() {
+        let hook: DownloadHook = serde_json::from_str(
+            r#"{"type":"command","program":"notify","args":["{event}","{path}"],"events":["fileComplete"]}"#,
+        )
+        .unwrap();
+        assert!(hook.enabled);
+        assert_eq!(hook.timeout(), Duration::from_secs(DEFAULT_TIMEOUT_SECS));
+
+        let payload = HookPayload {
+            event: HookEvent::FileComplete,
+            gid: "g1".into(),
+            name: "a.mkv".into(),
+            path: Some("/dl/a.mkv".into()),
+            size: 42,
+            status: "complete".into(),
+            error: None,
+            is_folder: false,
+            parent_gid: None,
+            total_files: None,
+            completed_files: None,
+            failed_files: None,
+            completed_at: None,
+        };
+        let HookAction::Command { args, .. } = &hook.action else {
+            panic!("expected command hook");
+        };
+        let rendered: Vec<String> = args.iter().map(|a| payload.render_arg(a)).collect();
+        assert_eq!(rendered, ["fileComplete", "/dl/a.mkv"]);
+
+        let webhook: DownloadHook = serde_json::from_str(
+            r#"{"type":"webhook","url":"ftp://x","events":["taskFailed"],"timeoutSecs":5}"#,
+        )
+        .unwrap();
+        assert!(validate_hooks(&[hook]).is_ok());
+        assert!(validate_hooks(&[webhook]).is_err());
+    }
+}
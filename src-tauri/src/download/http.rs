@@ -4,19 +4,24 @@ use std::sync::{Arc, Mutex};
 
 use futures_util::StreamExt;
 use log::{debug, error, info, warn};
+use reqwest::header::{CONTENT_LENGTH, HeaderMap, HeaderName, HeaderValue};
 use tokio::sync::{Semaphore, mpsc, watch};
 use tokio::task::JoinSet;
 use tokio::time::{Duration, interval};
 
 use tauri::AppHandle;
 
-use super::persistence::ProgressFile;
+use super::autotune;
+use super::cdn_limits;
+use super::disk_watch::{is_disk_full_error, save_dir_available};
+use super::persistence::{ProgressFile, adopt_legacy_data_file, part_file_path};
 use super::segment::compute_segments;
 use super::throttle::get_throttle;
 use super::types::{
-    DownloadConfig, DownloadError, DownloadTask, ProgressUpdate, RangeInfo, Segment, SegmentStatus,
-    TaskAbortReason, TaskStatus,
+    DownloadConfig, DownloadError, DownloadSource, DownloadTask, ProgressUpdate, RangeInfo,
+    Segment, SegmentStatus, TaskAbortReason, TaskStatus,
 };
+use super::volume;
 use super::writer::FileWriter;
 use crate::download::events::{
     DownloadProgressEvent, DownloadSegmentEvent, DownloadTaskEvent, ProgressItem, ProgressRegistry,
@@ -44,6 +49,8 @@ pub struct ConnectionController {
     stolen_permits: AtomicU16,
     /// 上次限流时间戳 (epoch ms)
     last_rate_limit_ms: AtomicU64,
+    /// 各主机上次限流时间戳 (epoch ms)，调优只关心本任务所用主机的限流
+    host_rate_limits: Mutex<HashMap<String, u64>>,
     /// 上次恢复时间戳 (epoch ms)，用于控制恢复频率
     last_restore_ms: AtomicU64,
 }
@@ -53,7 +60,7 @@ const RATE_LIMIT_COOLDOWN_MS: u64 = 5_000;
 /// 连接恢复最小间隔 (ms)，每次最多恢复 1 个连接
 const RESTORE_INTERVAL_MS: u64 = 1_500;
 
-fn current_epoch_ms() -> u64 {
+pub(super) fn current_epoch_ms() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
@@ -67,6 +74,7 @@ impl ConnectionController {
             effective_max: AtomicU16::new(max),
             stolen_permits: AtomicU16::new(0),
             last_rate_limit_ms: AtomicU64::new(0),
+            host_rate_limits: Mutex::new(HashMap::new()),
             last_restore_ms: AtomicU64::new(0),
         }
     }
@@ -74,12 +82,22 @@ impl ConnectionController {
     /// CDN 限流时调用，将有效连接数减半（最小为 1）
     ///
     /// 通过消耗空闲 permit 减少信号量容量，活跃下载不受影响，自然完成后释放。
-    pub fn on_rate_limit(&self, semaphore: &Semaphore) {
-        self.last_rate_limit_ms
-            .store(current_epoch_ms(), Ordering::SeqCst);
+    /// 降速后的连接数按主机记入 [`cdn_limits`]，之后的任务从该水平起步。
+    pub fn on_rate_limit(&self, semaphore: &Semaphore, host: Option<&str>) {
+        let now = current_epoch_ms();
+        self.last_rate_limit_ms.store(now, Ordering::SeqCst);
+        if let Some(host) = host {
+            self.host_rate_limits
+                .lock()
+                .unwrap()
+                .insert(host.to_string(), now);
+        }
         loop {
             let current = self.effective_max.load(Ordering::SeqCst);
             if current <= 1 {
+                if let Some(host) = host {
+                    cdn_limits::record_rate_limit(host, 1);
+                }
                 return;
             }
             let new_val = (current / 2).max(1);
@@ -90,19 +108,11 @@ impl ConnectionController {
                 Ordering::Relaxed,
             ) {
                 Ok(_) => {
-                    let to_steal = (current - new_val) as usize;
-                    let mut actually_stolen = 0u16;
-                    for _ in 0..to_steal {
-                        match semaphore.try_acquire() {
-                            Ok(permit) => {
-                                permit.forget();
-                                actually_stolen += 1;
-                            }
-                            Err(_) => break,
-                        }
+                    if let Some(host) = host {
+                        cdn_limits::record_rate_limit(host, new_val);
                     }
-                    self.stolen_permits
-                        .fetch_add(actually_stolen, Ordering::SeqCst);
+                    let to_steal = current - new_val;
+                    let actually_stolen = self.reclaim_permits(semaphore, to_steal);
                     info!(
                         "CDN限流降速: 连接数 {} → {} (回收{}/{}个permit, 累计回收={})",
                         current,
@@ -118,10 +128,51 @@ impl ConnectionController {
         }
     }
 
+    /// 从信号量回收最多 `count` 个空闲 permit，返回实际回收数。
+    fn reclaim_permits(&self, semaphore: &Semaphore, count: u16) -> u16 {
+        let mut reclaimed = 0u16;
+        for _ in 0..count {
+            match semaphore.try_acquire() {
+                Ok(permit) => {
+                    permit.forget();
+                    reclaimed += 1;
+                }
+                Err(_) => break,
+            }
+        }
+        self.stolen_permits.fetch_add(reclaimed, Ordering::SeqCst);
+        reclaimed
+    }
+
+    /// 任务启动时调用，按该主机学到的安全连接数起步
+    ///
+    /// 低于当前有效连接数时回收多余的 permit 并进入冷却期，之后按 `on_success` 的节奏逐步恢复。
+    pub fn start_at_learned(&self, semaphore: &Semaphore, host: &str) {
+        let Some(learned) = cdn_limits::learned_limit(host) else {
+            return;
+        };
+        let current = self.effective_max.load(Ordering::SeqCst);
+        if learned >= current
+            || self
+                .effective_max
+                .compare_exchange(current, learned, Ordering::SeqCst, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+        self.last_rate_limit_ms
+            .store(current_epoch_ms(), Ordering::SeqCst);
+        let reclaimed = self.reclaim_permits(semaphore, current - learned);
+        info!(
+            "CDN限流记忆: 主机 {} 连接数 {} → {} (回收{}个permit)",
+            host, current, learned, reclaimed
+        );
+    }
+
     /// 分片下载成功时调用，冷却期后逐步恢复连接数
     ///
     /// 限流后等待冷却期，之后按恢复间隔每次恢复 1 个连接，直到达到配置上限。
-    pub fn on_success(&self, semaphore: &Semaphore) {
+    pub fn on_success(&self, semaphore: &Semaphore, host: Option<&str>) {
         let stolen = self.stolen_permits.load(Ordering::SeqCst);
         if stolen == 0 {
             return;
@@ -165,6 +216,9 @@ impl ConnectionController {
             Ok(_) => {
                 semaphore.add_permits(1);
                 let new_effective = self.effective_max.fetch_add(1, Ordering::SeqCst) + 1;
+                if let Some(host) = host {
+                    cdn_limits::record_recovery(host, new_effective, self.configured_max);
+                }
                 debug!(
                     "CDN限流恢复: 连接数 {} → {} (剩余可恢复={})",
                     new_effective - 1,
@@ -180,21 +234,94 @@ impl ConnectionController {
     }
 
     /// 获取当前有效连接数
-    #[allow(dead_code)]
     pub fn effective_limit(&self) -> u16 {
         self.effective_max.load(Ordering::Relaxed)
     }
+
+    /// 给定时间（epoch ms）之后该主机是否出现过限流
+    pub fn rate_limited_since(&self, host: &str, since_ms: u64) -> bool {
+        self.host_rate_limits
+            .lock()
+            .unwrap()
+            .get(host)
+            .is_some_and(|&at| at > since_ms)
+    }
+}
+
+/// 构造下载请求头。
+///
+/// 115 文件携带 Bearer token；直链任务不发送 token，改为附带 Referer、Cookie，
+/// 再叠加自定义请求头（同名时覆盖前面的值）。
+pub fn request_headers(
+    source: &DownloadSource,
+    token: &str,
+    user_agent: &str,
+) -> Result<HeaderMap, DownloadError> {
+    let mut headers = HeaderMap::new();
+    if !user_agent.is_empty() {
+        insert_header(&mut headers, "User-Agent", user_agent)?;
+    }
+    match source {
+        DownloadSource::Pan115 | DownloadSource::Hls { .. } => {
+            insert_header(&mut headers, "Authorization", &format!("Bearer {}", token))?;
+        }
+        DownloadSource::Url {
+            headers: custom,
+            cookies,
+            referer,
+            credentials_required,
+            ..
+        } => {
+            if *credentials_required && custom.is_empty() && cookies.is_none() {
+                return Err(DownloadError::CredentialsLost);
+            }
+            if let Some(referer) = referer.as_deref().filter(|v| !v.is_empty()) {
+                insert_header(&mut headers, "Referer", referer)?;
+            }
+            if let Some(cookies) = cookies.as_deref().filter(|v| !v.is_empty()) {
+                insert_header(&mut headers, "Cookie", cookies)?;
+            }
+            for (name, value) in custom {
+                insert_header(&mut headers, name, value)?;
+            }
+        }
+    }
+    Ok(headers)
+}
+
+/// 插入单个请求头；错误信息只带请求头名称，避免 Cookie 等敏感值进入日志。
+fn insert_header(headers: &mut HeaderMap, name: &str, value: &str) -> Result<(), DownloadError> {
+    let header_name = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| DownloadError::InvalidHeader(name.to_string()))?;
+    let header_value =
+        HeaderValue::from_str(value).map_err(|_| DownloadError::InvalidHeader(name.to_string()))?;
+    headers.insert(header_name, header_value);
+    Ok(())
+}
+
+/// 解析 Content-Length 响应头。
+///
+/// HEAD 响应没有响应体，`Response::content_length()` 对其返回 `Some(0)`，必须直接读响应头。
+fn header_content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
 }
 
 /// HEAD 请求探测服务器是否支持 Range 分片下载
 ///
-/// 解析 Accept-Ranges 和 ETag 响应头。
-/// 遇到 CDN 限流 (HTTP 403) 时指数退避重试，最多 5 次。
+/// 解析 Accept-Ranges、ETag、Last-Modified 和 Content-Length 响应头。
+/// `cdn_retry` 为真（115 文件）时，HTTP 403 视为 CDN 限流，指数退避重试，最多 5 次；
+/// 直链任务的 403 来自任意站点，直接返回错误。
 pub async fn detect_range_support(
     client: &reqwest::Client,
     url: &str,
-    token: &str,
-    user_agent: &str,
+    headers: &HeaderMap,
+    cdn_retry: bool,
 ) -> Result<RangeInfo, DownloadError> {
     const MAX_RETRIES: u32 = 5;
     let mut last_error = None;
@@ -202,8 +329,7 @@ pub async fn detect_range_support(
     for attempt in 0..=MAX_RETRIES {
         let resp = client
             .head(url)
-            .header("Authorization", format!("Bearer {}", token))
-            .header("User-Agent", user_agent)
+            .headers(headers.clone())
             .send()
             .await
             .map_err(DownloadError::Http)?;
@@ -215,15 +341,18 @@ pub async fn detect_range_support(
                 .and_then(|v| v.to_str().ok())
                 .is_some_and(|v| v.eq_ignore_ascii_case("bytes"));
 
-            let etag = resp
-                .headers()
-                .get("etag")
-                .and_then(|v| v.to_str().ok())
-                .map(|v| v.to_string());
+            let header_text = |name: &str| {
+                resp.headers()
+                    .get(name)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string())
+            };
 
             return Ok(RangeInfo {
                 supports_range,
-                etag,
+                etag: header_text("etag"),
+                last_modified: header_text("last-modified"),
+                content_length: header_content_length(resp.headers()),
             });
         }
 
@@ -234,8 +363,8 @@ pub async fn detect_range_support(
             .unwrap_or("未知状态")
             .to_string();
 
-        // 仅对 403 限流进行退避重试
-        if status == 403 && attempt < MAX_RETRIES {
+        // 仅对 115 CDN 的 403 限流进行退避重试
+        if cdn_retry && status == 403 && attempt < MAX_RETRIES {
             let backoff_ms = 1000 * 2u64.pow(attempt);
             let jitter_ms = (attempt as u64) * 200;
             let delay = Duration::from_millis(backoff_ms + jitter_ms);
@@ -258,6 +387,57 @@ pub async fn detect_range_support(
     }))
 }
 
+/// 探测直链的文件大小。
+///
+/// 先用 HEAD 读取 Content-Length；服务器不支持 HEAD 或未返回长度时，
+/// 改发 `Range: bytes=0-0` 的 GET，从 Content-Range 的总长度中解析。
+pub async fn probe_file_size(
+    client: &reqwest::Client,
+    url: &str,
+    headers: &HeaderMap,
+) -> Result<u64, DownloadError> {
+    match detect_range_support(client, url, headers, false).await {
+        Ok(RangeInfo {
+            content_length: Some(len),
+            ..
+        }) => return Ok(len),
+        Ok(_) => debug!("HEAD 未返回文件大小，改用 Range GET 探测"),
+        Err(e) => debug!("HEAD 探测失败（{}），改用 Range GET 探测", e),
+    }
+
+    let resp = client
+        .get(url)
+        .headers(headers.clone())
+        .header(reqwest::header::RANGE, "bytes=0-0")
+        .send()
+        .await?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(DownloadError::HttpStatus {
+            status: status.as_u16(),
+            message: status.canonical_reason().unwrap_or("未知状态").to_string(),
+        });
+    }
+    let total = if status == reqwest::StatusCode::PARTIAL_CONTENT {
+        resp.headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(content_range_total)
+    } else {
+        // 服务器忽略 Range 返回整个文件，此时 Content-Length 即文件大小
+        header_content_length(resp.headers())
+    };
+    total.ok_or_else(|| DownloadError::HttpStatus {
+        status: status.as_u16(),
+        message: "服务器未返回文件大小".to_string(),
+    })
+}
+
+/// 解析 `Content-Range: bytes 0-0/12345` 中的总长度，总长度未知（`*`）时返回空。
+fn content_range_total(value: &str) -> Option<u64> {
+    value.rsplit_once('/')?.1.trim().parse().ok()
+}
+
 /// 下载单个分片
 ///
 /// 支持 Range 时发送 `Range: bytes=start-end` 分段请求，
@@ -265,8 +445,7 @@ pub async fn detect_range_support(
 pub async fn download_segment(
     client: &reqwest::Client,
     url: &str,
-    token: &str,
-    user_agent: &str,
+    headers: &HeaderMap,
     segment: &Segment,
     writer: &FileWriter,
     supports_range: bool,
@@ -279,10 +458,7 @@ pub async fn download_segment(
         return Ok(segment.downloaded);
     }
 
-    let mut request = client
-        .get(url)
-        .header("Authorization", format!("Bearer {}", token))
-        .header("User-Agent", user_agent);
+    let mut request = client.get(url).headers(headers.clone());
 
     if supports_range {
         let start = segment.start + segment.downloaded;
@@ -401,8 +577,7 @@ pub async fn download_segment(
     }
 
     /// 刷盘缓冲区并上报进度，确保 DB 记录不超过实际磁盘数据
-    #[inline]
-    fn flush_buffer(
+    async fn flush_buffer(
         writer: &FileWriter,
         buf: &mut Vec<u8>,
         buf_offset: u64,
@@ -414,8 +589,7 @@ pub async fn download_segment(
         if buf.is_empty() {
             return Ok(());
         }
-        writer.write_at(buf_offset, buf)?;
-        buf.clear();
+        *buf = writer.write_at(buf_offset, std::mem::take(buf)).await?;
         // 刷盘后报告进度，确保 DB 记录不超过磁盘数据
         if let Some(tx) = progress_tx {
             let _ = tx.try_send(ProgressUpdate {
@@ -436,7 +610,7 @@ pub async fn download_segment(
                 if result.is_err() {
                     // 通道关闭，刷盘保留已下载进度
                     flush_buffer(&writer, &mut write_buffer, buffer_start_offset,
-                        &progress_tx, task_id, segment.index, total_written)?;
+                        &progress_tx, task_id, segment.index, total_written).await?;
                     return Err(DownloadError::TaskAborted(TaskAbortReason::SignalChannelClosed));
                 }
                 let signal = signal_rx.borrow_and_update().clone();
@@ -444,7 +618,7 @@ pub async fn download_segment(
                     DownloadSignal::Paused => {
                         // 暂停前刷盘，防止恢复时 DB 进度超过磁盘数据
                         flush_buffer(&writer, &mut write_buffer, buffer_start_offset,
-                            &progress_tx, task_id, segment.index, total_written)?;
+                            &progress_tx, task_id, segment.index, total_written).await?;
                         return Err(DownloadError::TaskAborted(TaskAbortReason::Paused));
                     }
                     DownloadSignal::Cancelled => {
@@ -463,7 +637,7 @@ pub async fn download_segment(
                     Err(_) => {
                         // 超时，刷盘保留部分进度
                         flush_buffer(&writer, &mut write_buffer, buffer_start_offset,
-                            &progress_tx, task_id, segment.index, total_written)?;
+                            &progress_tx, task_id, segment.index, total_written).await?;
                         warn!("[分片{}][{}] 读取超时60s, 已下载={}", segment.index, task_id, total_written);
                         return Err(DownloadError::TaskAborted(TaskAbortReason::ReadTimeout { seconds: 60 }));
                     }
@@ -489,9 +663,10 @@ pub async fn download_segment(
                 // 缓冲区满，批量刷盘
                 if write_buffer.len() >= WRITE_BUFFER_SIZE {
                     let t_write_start = std::time::Instant::now();
-                    writer.write_at(buffer_start_offset, &write_buffer)?;
+                    write_buffer = writer
+                        .write_at(buffer_start_offset, std::mem::take(&mut write_buffer))
+                        .await?;
                     write_ns += t_write_start.elapsed().as_nanos() as u64;
-                    write_buffer.clear();
                     buffer_start_offset = offset;
 
                     // 只在刷盘后报告进度
@@ -510,7 +685,7 @@ pub async fn download_segment(
                     match signal {
                         DownloadSignal::Paused => {
                             flush_buffer(&writer, &mut write_buffer, buffer_start_offset,
-                                &progress_tx, task_id, segment.index, total_written)?;
+                                &progress_tx, task_id, segment.index, total_written).await?;
                             return Err(DownloadError::TaskAborted(TaskAbortReason::Paused));
                         }
                         DownloadSignal::Cancelled => {
@@ -520,7 +695,7 @@ pub async fn download_segment(
                     }
                 }
 
-                // 全局带宽限速（限速等待期间也监听暂停/取消信号，aria2-style）
+                // 全局及保存卷带宽限速（限速等待期间也监听暂停/取消信号，aria2-style）
                 let t_throttle_start = std::time::Instant::now();
                 tokio::select! {
                     biased;
@@ -530,7 +705,7 @@ pub async fn download_segment(
                             match sig {
                                 DownloadSignal::Paused => {
                                     flush_buffer(&writer, &mut write_buffer, buffer_start_offset,
-                                        &progress_tx, task_id, segment.index, total_written)?;
+                                        &progress_tx, task_id, segment.index, total_written).await?;
                                     return Err(DownloadError::TaskAborted(TaskAbortReason::Paused));
                                 }
                                 DownloadSignal::Cancelled => {
@@ -540,11 +715,14 @@ pub async fn download_segment(
                             }
                         } else {
                             flush_buffer(&writer, &mut write_buffer, buffer_start_offset,
-                                &progress_tx, task_id, segment.index, total_written)?;
+                                &progress_tx, task_id, segment.index, total_written).await?;
                             return Err(DownloadError::TaskAborted(TaskAbortReason::SignalChannelClosed));
                         }
                     }
-                    _ = get_throttle().consume(effective_bytes.len()) => {}
+                    _ = async {
+                        get_throttle().consume(effective_bytes.len()).await;
+                        volume::consume(task_id, effective_bytes.len()).await;
+                    } => {}
                 }
                 throttle_ns += t_throttle_start.elapsed().as_nanos() as u64;
 
@@ -571,7 +749,7 @@ pub async fn download_segment(
 
     // 最后一批缓冲区刷盘
     if !write_buffer.is_empty() {
-        writer.write_at(buffer_start_offset, &write_buffer)?;
+        writer.write_at(buffer_start_offset, write_buffer).await?;
         if let Some(ref tx) = progress_tx {
             let _ = tx.try_send(ProgressUpdate {
                 task_id: task_id.to_string(),
@@ -616,9 +794,9 @@ pub async fn download_segment(
 
     Ok(total_written)
 }
-const MAX_SEGMENT_RETRIES: u32 = 3;
+pub(super) const MAX_SEGMENT_RETRIES: u32 = 3;
 /// 重试基准延迟 (ms)，指数退避 1s → 2s → 4s
-const RETRY_BASE_DELAY_MS: u64 = 1000;
+pub(super) const RETRY_BASE_DELAY_MS: u64 = 1000;
 
 /// 分片下载 + 指数退避重试
 ///
@@ -628,8 +806,7 @@ const RETRY_BASE_DELAY_MS: u64 = 1000;
 pub async fn download_segment_with_retry(
     client: &reqwest::Client,
     url_rx: watch::Receiver<String>,
-    token: &str,
-    user_agent: &str,
+    headers: &HeaderMap,
     segment: &Segment,
     writer: &FileWriter,
     supports_range: bool,
@@ -728,8 +905,7 @@ pub async fn download_segment_with_retry(
             match download_segment(
                 client,
                 &current_url,
-                token,
-                user_agent,
+                headers,
                 &local_seg,
                 writer,
                 supports_range,
@@ -745,9 +921,10 @@ pub async fn download_segment_with_retry(
                 Err(DownloadError::UrlExpired { status, message }) => {
                     sync_partial_progress(supports_range, &last_downloaded, &mut local_seg);
 
-                    // HTTP 403 可能是 CDN 并发限流而非 URL 过期
-                    // 先指数退避重试，自然错开并发请求
-                    if status == 403 && retry_count < 2 {
+                    // 115 CDN 的 HTTP 403 可能是并发限流而非 URL 过期，先指数退避重试，
+                    // 自然错开并发请求；直链任务（无 pick_code）的 403 按地址失效处理
+                    let cdn_rate_limit = status == 403 && !pick_code.is_empty();
+                    if cdn_rate_limit && retry_count < 2 {
                         retry_count += 1;
                         warn!(
                             "[分片{}][{}] HTTP 403 疑似CDN限流, 退避重试#{} 已下载={:.1}MB",
@@ -761,7 +938,7 @@ pub async fn download_segment_with_retry(
                     }
 
                     // 退避后仍 403，确认为 CDN 限流，交由编排层降速重排
-                    if status == 403 {
+                    if cdn_rate_limit {
                         warn!(
                             "[分片{}][{}] CDN限流确认, 释放连接等待重新调度 已下载={:.1}MB",
                             local_seg.index,
@@ -771,7 +948,11 @@ pub async fn download_segment_with_retry(
                         break 'retry_loop Err(DownloadError::CdnRateLimit);
                     }
 
-                    // 非 403 状态码（如 401/410），判定为 URL 真正过期
+                    // 非 403 状态码（如 401/410），判定为 URL 真正过期。
+                    // 没有 pick_code 的直链任务无法换取新地址，直接失败。
+                    if pick_code.is_empty() {
+                        break 'retry_loop Err(DownloadError::UrlExpired { status, message });
+                    }
                     url_refresh_count += 1;
                     warn!(
                         "[分片{}][{}] URL过期 (HTTP {}) (第{}次刷新) 已下载={:.1}MB",
@@ -886,7 +1067,7 @@ pub async fn download_segment_with_retry(
 }
 
 /// 判断错误是否为瞬态错误，值得重试
-fn is_retryable_error(err: &DownloadError) -> bool {
+pub(super) fn is_retryable_error(err: &DownloadError) -> bool {
     match err {
         DownloadError::Http(_) => true,
         DownloadError::HttpStatus { status, .. } => *status >= 500,
@@ -903,16 +1084,21 @@ fn is_retryable_error(err: &DownloadError) -> bool {
 /// 校验下载文件 SHA1 完整性
 ///
 /// 匹配或未提供期望值返回 Ok(true)，不匹配返回 Ok(false)。
-async fn verify_file_sha1(
+pub(super) async fn verify_file_sha1(
     file_path: &str,
     expected_sha1: Option<&str>,
 ) -> Result<bool, DownloadError> {
     let Some(expected) = expected_sha1 else {
         return Ok(true); // 未提供期望 SHA1，跳过校验
     };
-    let expected = expected.to_uppercase();
+    let computed = compute_file_sha1(file_path).await?;
+    Ok(computed.eq_ignore_ascii_case(expected))
+}
+
+/// 在阻塞线程池中计算文件 SHA1，返回大写十六进制字符串。
+pub(super) async fn compute_file_sha1(file_path: &str) -> Result<String, DownloadError> {
     let path = file_path.to_string();
-    let computed = tokio::task::spawn_blocking(move || -> Result<String, DownloadError> {
+    tokio::task::spawn_blocking(move || -> Result<String, DownloadError> {
         use sha1::{Digest, Sha1};
         use std::io::Read;
         let mut file = std::fs::File::open(&path).map_err(DownloadError::Io)?;
@@ -933,12 +1119,11 @@ async fn verify_file_sha1(
         Ok(hash)
     })
     .await
-    .map_err(|e| DownloadError::JoinError(e.to_string()))??;
-    Ok(computed == expected)
+    .map_err(|e| DownloadError::JoinError(e.to_string()))?
 }
 
 /// 判断 HTTP 状态码是否表示 CDN 预签名 URL 过期
-fn is_url_expired(status: u16) -> bool {
+pub(super) fn is_url_expired(status: u16) -> bool {
     status == 401 || status == 403 || status == 410
 }
 
@@ -1003,12 +1188,14 @@ fn reallocate_failed_segment(
     Some(sub_segments)
 }
 
-/// 进度刷盘+速度计算+事件发射 — download_file 和 resume_download 共用
+/// 进度刷盘+速度计算+事件发射 — download_file、resume_download 和 HLS 下载共用
+///
+/// `writer` 为空时（如 HLS 分段各自落盘）跳过周期性 sync_data。
 ///
 /// 优化:
 /// - sync_data 频率从 500ms 降低到 5s，减少 fdatasync 系统调用开销
 /// - 通道关闭时执行最终 sync_data，确保数据一致性
-fn spawn_flush_task(
+pub(super) fn spawn_flush_task(
     db: Arc<ProgressFile>,
     app: AppHandle,
     task_id: String,
@@ -1016,7 +1203,7 @@ fn spawn_flush_task(
     file_name: String,
     progress_registry: Arc<ProgressRegistry>,
     snapshot: Arc<Mutex<HashMap<u16, u64>>>,
-    writer: FileWriter,
+    writer: Option<FileWriter>,
     mut progress_rx: mpsc::Receiver<ProgressUpdate>,
 ) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
@@ -1041,7 +1228,9 @@ fn spawn_flush_task(
                     }
                     // 每 5 秒 (10个tick) 刷盘一次，减少 fdatasync 系统调用开销
                     // 最多丢失 5 秒进度，.oofp 始终保守于磁盘数据，断点续传安全
-                    if tick_count % 10 == 0 {
+                    if tick_count % 10 == 0
+                        && let Some(ref writer) = writer
+                    {
                         let _ = writer.sync_data();
                     }
                     let cumulative_downloaded: u64 = snapshot.lock().unwrap().values().sum();
@@ -1101,7 +1290,9 @@ fn spawn_flush_task(
                                 let _ = db.batch_update_downloaded(&updates);
                             }
                             // 确保最终数据落盘
-                            let _ = writer.sync_data();
+                            if let Some(ref writer) = writer {
+                                let _ = writer.sync_data();
+                            }
                             progress_registry.remove(&task_id);
                             break;
                         }
@@ -1121,11 +1312,8 @@ fn spawn_flush_task(
 /// 避免在两个函数中重复声明和管理相同的变量集合
 struct DownloadContext<'a> {
     task_id: &'a str,
-    file_name: &'a str,
-    file_size: u64,
     save_path: &'a str,
     pick_code: &'a str,
-    expected_sha1: Option<&'a str>,
     supports_range: bool,
     segments: &'a mut Vec<Segment>,
     db: &'a Arc<ProgressFile>,
@@ -1136,15 +1324,18 @@ struct DownloadContext<'a> {
     url_rx: &'a watch::Receiver<String>,
     url_refresh_requested: &'a Arc<AtomicBool>,
     semaphore: &'a Arc<Semaphore>,
+    /// 任务级连接许可，由 [`autotune`] 逐步放开
+    task_slots: &'a Arc<Semaphore>,
     conn_controller: &'a Arc<ConnectionController>,
+    /// 保存卷的挂载点，写盘失败时判断卷是否已断开
+    volume_root: Option<&'a str>,
 }
 
 /// 分片 spawn 参数包 — 避免 spawn 闭包捕获过多局部变量
 struct SegmentSpawnParams {
     client: reqwest::Client,
     url_rx: watch::Receiver<String>,
-    token: String,
-    user_agent: String,
+    headers: HeaderMap,
     segment: Segment,
     writer: FileWriter,
     progress_tx: mpsc::Sender<ProgressUpdate>,
@@ -1154,6 +1345,7 @@ struct SegmentSpawnParams {
     url_refresh_requested: Arc<AtomicBool>,
     app: AppHandle,
     supports_range: bool,
+    task_slots: Arc<Semaphore>,
 }
 
 /// 将分片 spawn 到 JoinSet — 统一 download_file 和 resume_download 的 spawn 逻辑
@@ -1164,6 +1356,16 @@ fn spawn_segment_task(
 ) {
     let permit = semaphore.clone();
     join_set.spawn(async move {
+        // 先占任务级许可，再占全局许可，排队的分片不占用全局连接
+        let slot = match params.task_slots.clone().acquire_owned().await {
+            Ok(slot) => slot,
+            Err(_) => {
+                return Err((
+                    params.segment,
+                    DownloadError::TaskAborted(TaskAbortReason::SemaphoreClosed),
+                ));
+            }
+        };
         let p = match permit.acquire_owned().await {
             Ok(p) => p,
             Err(e) => {
@@ -1182,8 +1384,7 @@ fn spawn_segment_task(
         match download_segment_with_retry(
             &params.client,
             params.url_rx,
-            &params.token,
-            &params.user_agent,
+            &params.headers,
             &params.segment,
             &params.writer,
             params.supports_range,
@@ -1198,10 +1399,12 @@ fn spawn_segment_task(
         {
             Ok(bytes) => {
                 drop(p);
+                drop(slot);
                 Ok((params.segment.index, bytes))
             }
             Err(e) => {
                 drop(p);
+                drop(slot);
                 Err((params.segment, e))
             }
         }
@@ -1375,8 +1578,7 @@ fn respawn_cdn_segment(
     failed_seg: &Segment,
     delay: Duration,
     client: &reqwest::Client,
-    token: &str,
-    user_agent: &str,
+    headers: &HeaderMap,
     progress_tx: &mpsc::Sender<ProgressUpdate>,
 ) {
     let mut seg = failed_seg.clone();
@@ -1390,10 +1592,10 @@ fn respawn_cdn_segment(
     seg.downloaded = actual_downloaded;
 
     let semaphore = ctx.semaphore.clone();
+    let task_slots = ctx.task_slots.clone();
     let client = client.clone();
     let url_rx = ctx.url_rx.clone();
-    let token = token.to_string();
-    let user_agent = user_agent.to_string();
+    let headers = headers.clone();
     let writer = ctx.writer.clone();
     let tx = progress_tx.clone();
     let tid = ctx.task_id.to_string();
@@ -1433,6 +1635,15 @@ fn respawn_cdn_segment(
                 ));
             }
         }
+        let slot = match task_slots.acquire_owned().await {
+            Ok(slot) => slot,
+            Err(_) => {
+                return Err((
+                    seg,
+                    DownloadError::TaskAborted(TaskAbortReason::SemaphoreClosed),
+                ));
+            }
+        };
         let permit = match semaphore.acquire_owned().await {
             Ok(p) => p,
             Err(_) => {
@@ -1445,8 +1656,7 @@ fn respawn_cdn_segment(
         match download_segment_with_retry(
             &client,
             url_rx,
-            &token,
-            &user_agent,
+            &headers,
             &seg,
             &writer,
             supports_range,
@@ -1461,10 +1671,12 @@ fn respawn_cdn_segment(
         {
             Ok(bytes) => {
                 drop(permit);
+                drop(slot);
                 Ok((seg.index, bytes))
             }
             Err(e) => {
                 drop(permit);
+                drop(slot);
                 Err((seg, e))
             }
         }
@@ -1478,16 +1690,16 @@ async fn collect_results<'a>(
     ctx: &mut DownloadContext<'a>,
     join_set: &mut JoinSet<Result<(u16, u64), (Segment, DownloadError)>>,
     client: &reqwest::Client,
-    token: &str,
-    user_agent: &str,
+    headers: &HeaderMap,
     progress_tx: mpsc::Sender<ProgressUpdate>,
     flush_handle: &mut tokio::task::JoinHandle<()>,
-    task_start_time: std::time::Instant,
     log_prefix: &str,
 ) -> Result<(), DownloadError> {
     let mut has_failure = false;
     let mut is_paused = false;
     let mut is_cancelled = false;
+    let mut is_disk_full = false;
+    let mut is_volume_lost = false;
     let mut realloc_counter: u32 = 0;
     let mut completed_segments: u32 = 0;
     let total_segments = ctx.segments.len() as u32;
@@ -1503,7 +1715,9 @@ async fn collect_results<'a>(
             Ok(Ok((index, bytes))) => {
                 completed_segments += 1;
                 last_success_time = std::time::Instant::now();
-                ctx.conn_controller.on_success(ctx.semaphore);
+                let host = cdn_limits::host_of(&ctx.url_rx.borrow());
+                ctx.conn_controller
+                    .on_success(ctx.semaphore, host.as_deref());
                 // 分片成功说明CDN已恢复，重置限流重试计数让排队分片快速启动
                 cdn_retry_counts.clear();
                 debug!(
@@ -1548,7 +1762,9 @@ async fn collect_results<'a>(
                 break;
             }
             Ok(Err((failed_seg, DownloadError::CdnRateLimit))) => {
-                ctx.conn_controller.on_rate_limit(ctx.semaphore);
+                let host = cdn_limits::host_of(&ctx.url_rx.borrow());
+                ctx.conn_controller
+                    .on_rate_limit(ctx.semaphore, host.as_deref());
                 match handle_cdn_rate_limit(
                     &failed_seg,
                     &mut cdn_retry_counts,
@@ -1565,10 +1781,11 @@ async fn collect_results<'a>(
                             "[{}][{}] 所有分片停滞, 触发URL刷新 (第{}次)",
                             log_prefix, ctx.task_id, task_url_refresh_count
                         );
-                        if ctx
-                            .url_refresh_requested
-                            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
-                            .is_ok()
+                        if !ctx.pick_code.is_empty()
+                            && ctx
+                                .url_refresh_requested
+                                .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                                .is_ok()
                         {
                             emit_url_expired(
                                 ctx.app,
@@ -1589,8 +1806,7 @@ async fn collect_results<'a>(
                             &failed_seg,
                             delay,
                             client,
-                            token,
-                            user_agent,
+                            headers,
                             &progress_tx,
                         );
                     }
@@ -1608,8 +1824,7 @@ async fn collect_results<'a>(
                             &failed_seg,
                             delay,
                             client,
-                            token,
-                            user_agent,
+                            headers,
                             &progress_tx,
                         );
                     }
@@ -1622,6 +1837,28 @@ async fn collect_results<'a>(
                     }
                 }
             }
+            Ok(Err((failed_seg, e))) if is_disk_full_error(&e) => {
+                // 磁盘写满时重分配分片没有意义，保存进度后交给队列按磁盘满暂停。
+                warn!(
+                    "[{}][{}] 分片{} 写盘失败，磁盘已满",
+                    log_prefix, ctx.task_id, failed_seg.index
+                );
+                is_disk_full = true;
+                join_set.abort_all();
+                break;
+            }
+            Ok(Err((failed_seg, DownloadError::Io(_))))
+                if !save_dir_available(ctx.save_path, ctx.volume_root) =>
+            {
+                // U 盘拔出或网络盘断开，等卷重新出现后从断点继续。
+                warn!(
+                    "[{}][{}] 分片{} 写盘失败，保存位置不可用",
+                    log_prefix, ctx.task_id, failed_seg.index
+                );
+                is_volume_lost = true;
+                join_set.abort_all();
+                break;
+            }
             Ok(Err((failed_seg, _e))) => {
                 warn!(
                     "[{}][{}] 分片{} 失败: {:?}",
@@ -1678,8 +1915,7 @@ async fn collect_results<'a>(
                                     let params = SegmentSpawnParams {
                                         client: client.clone(),
                                         url_rx: ctx.url_rx.clone(),
-                                        token: token.to_string(),
-                                        user_agent: user_agent.to_string(),
+                                        headers: headers.clone(),
                                         segment: sub_seg.clone(),
                                         writer: ctx.writer.clone(),
                                         progress_tx: progress_tx.clone(),
@@ -1689,6 +1925,7 @@ async fn collect_results<'a>(
                                         url_refresh_requested: ctx.url_refresh_requested.clone(),
                                         app: ctx.app.clone(),
                                         supports_range: ctx.supports_range,
+                                        task_slots: ctx.task_slots.clone(),
                                     };
                                     spawn_segment_task(join_set, ctx.semaphore, params);
                                 }
@@ -1716,31 +1953,55 @@ async fn collect_results<'a>(
 
     // 关闭进度通道，触发 flush_handle 最终刷盘
     drop(progress_tx);
-    finalize_download(is_paused, is_cancelled, has_failure, ctx, flush_handle).await?;
+    if is_disk_full || is_volume_lost {
+        // 按暂停保存分片进度，返回对应错误交给队列停放，条件恢复后自动继续
+        let _ = finalize_download(true, false, false, ctx, flush_handle).await;
+        return Err(if is_disk_full {
+            DownloadError::Io(std::io::ErrorKind::StorageFull.into())
+        } else {
+            DownloadError::VolumeUnavailable(ctx.save_path.to_string())
+        });
+    }
+    finalize_download(is_paused, is_cancelled, has_failure, ctx, flush_handle).await
+}
 
-    // SHA1 完整性校验
-    let sha1_ok = verify_file_sha1(ctx.save_path, ctx.expected_sha1)
+/// 下载收尾 — SHA1 校验 `.part` 后原子 rename 到最终路径，再删除断点文件
+///
+/// 调用前必须释放所有 FileWriter 句柄：Windows 下仍被打开的文件无法 rename。
+/// 校验失败时保留 `.part` 与 `.oofp`，由重试逻辑决定是否清理重下。
+pub(super) async fn commit_download(
+    db: &Arc<ProgressFile>,
+    task_id: &str,
+    file_name: &str,
+    file_size: u64,
+    save_path: &str,
+    expected_sha1: Option<&str>,
+    task_start_time: std::time::Instant,
+    log_prefix: &str,
+) -> Result<(), DownloadError> {
+    let part_path = part_file_path(save_path);
+    let sha1_ok = verify_file_sha1(&part_path, expected_sha1)
         .await
         .unwrap_or(false);
-    if sha1_ok {
-        ctx.db.delete_task(ctx.task_id)?;
-        let elapsed = task_start_time.elapsed().as_secs_f64();
-        info!(
-            "[{}][{}] 下载完成 文件={} 大小={:.1}MB 耗时={:.1}s 均速={:.1}MB/s",
-            log_prefix,
-            ctx.task_id,
-            ctx.file_name,
-            ctx.file_size as f64 / 1024.0 / 1024.0,
-            elapsed,
-            ctx.file_size as f64 / elapsed / 1024.0 / 1024.0,
-        );
-    } else {
-        let _ = ctx.db.update_task_status(ctx.task_id, "verify_failed");
+    if !sha1_ok {
+        let _ = db.update_task_status(task_id, "verify_failed");
         return Err(DownloadError::VerificationFailed(
             "SHA1 与服务端返回值不一致".to_string(),
         ));
     }
 
+    std::fs::rename(&part_path, save_path)?;
+    db.delete_task(task_id)?;
+    let elapsed = task_start_time.elapsed().as_secs_f64();
+    info!(
+        "[{}][{}] 下载完成 文件={} 大小={:.1}MB 耗时={:.1}s 均速={:.1}MB/s",
+        log_prefix,
+        task_id,
+        file_name,
+        file_size as f64 / 1024.0 / 1024.0,
+        elapsed,
+        file_size as f64 / elapsed / 1024.0 / 1024.0,
+    );
     Ok(())
 }
 
@@ -1749,8 +2010,7 @@ async fn spawn_segments_with_stagger(
     ctx: &mut DownloadContext<'_>,
     join_set: &mut JoinSet<Result<(u16, u64), (Segment, DownloadError)>>,
     client: &reqwest::Client,
-    token: &str,
-    user_agent: &str,
+    headers: &HeaderMap,
     progress_tx: &mpsc::Sender<ProgressUpdate>,
     skip_completed: bool,
 ) {
@@ -1793,8 +2053,7 @@ async fn spawn_segments_with_stagger(
         let params = SegmentSpawnParams {
             client: client.clone(),
             url_rx: ctx.url_rx.clone(),
-            token: token.to_string(),
-            user_agent: user_agent.to_string(),
+            headers: headers.clone(),
             segment: segment.clone(),
             writer: ctx.writer.clone(),
             progress_tx: progress_tx.clone(),
@@ -1804,6 +2063,7 @@ async fn spawn_segments_with_stagger(
             url_refresh_requested: ctx.url_refresh_requested.clone(),
             app: ctx.app.clone(),
             supports_range: ctx.supports_range,
+            task_slots: ctx.task_slots.clone(),
         };
         spawn_segment_task(join_set, ctx.semaphore, params);
     }
@@ -1811,12 +2071,11 @@ async fn spawn_segments_with_stagger(
 
 /// 多分片并行下载编排
 ///
-/// 磁盘空间检查 → Range 探测 → 分片计算 → 文件预分配 → 信号量控制并行下载
+/// Range 探测 → 磁盘空间检查 → 分片计算 → 文件预分配 → 信号量控制并行下载
 pub async fn download_file(
     client: &reqwest::Client,
     task: &mut DownloadTask,
-    token: &str,
-    user_agent: &str,
+    headers: &HeaderMap,
     config: &DownloadConfig,
     db: &Arc<ProgressFile>,
     app: &AppHandle,
@@ -1827,13 +2086,36 @@ pub async fn download_file(
     conn_controller: Arc<ConnectionController>,
     progress_registry: Arc<ProgressRegistry>,
 ) -> Result<(), DownloadError> {
-    FileWriter::check_disk_space(&task.save_path, task.file_size)?;
     if config.speed_limit > 0 {
         super::throttle::set_speed_limit(config.speed_limit);
     }
 
-    let range_info = detect_range_support(client, &task.url, token, user_agent).await?;
+    let range_info =
+        detect_range_support(client, &task.url, headers, !task.pick_code.is_empty()).await?;
     task.etag = range_info.etag;
+    task.last_modified = range_info.last_modified;
+    // 按记录大小分片而服务器实际长度不同，会在文件末尾留下空洞或截断，以服务器为准。
+    // 带服务端 SHA1 的任务大小不应变化，不一致说明远端文件已不是记录中的那个。
+    if let Some(content_length) = range_info.content_length
+        && content_length != task.file_size
+    {
+        if task.expected_sha1.is_some() {
+            return Err(DownloadError::VerificationFailed(format!(
+                "远端文件大小与记录不一致（{} → {}），请重新下载",
+                task.file_size, content_length
+            )));
+        }
+        warn!(
+            "[任务][{}] 服务器返回大小与记录不一致 记录={} 实际={}，按实际大小下载",
+            task.task_id, task.file_size, content_length
+        );
+        task.file_size = content_length;
+    }
+    FileWriter::check_disk_space(&task.save_path, task.file_size)?;
+    let host = cdn_limits::host_of(&task.url);
+    if let Some(host) = &host {
+        conn_controller.start_at_learned(&segment_semaphore, host);
+    }
 
     let split = if range_info.supports_range {
         config.split
@@ -1852,7 +2134,11 @@ pub async fn download_file(
         &task.url[..task.url.len().min(80)]
     );
 
-    let writer = FileWriter::create(&task.save_path, task.file_size)?;
+    let writer = FileWriter::create(
+        &part_file_path(&task.save_path),
+        task.file_size,
+        config.preallocation,
+    )?;
     db.save_task(
         &task.task_id,
         &task.file_name,
@@ -1860,6 +2146,8 @@ pub async fn download_file(
         &task.save_path,
         &task.url,
         task.etag.as_deref(),
+        task.last_modified.as_deref(),
+        range_info.content_length,
         &task.pick_code,
         task.expected_sha1.as_deref(),
         task.created_at,
@@ -1868,6 +2156,18 @@ pub async fn download_file(
 
     let (progress_tx, progress_rx) = mpsc::channel::<ProgressUpdate>(1024);
     let progress_snapshot: Arc<Mutex<HashMap<u16, u64>>> = Arc::new(Mutex::new(HashMap::new()));
+    let max_connections = task.segments.len() as u16;
+    let open = autotune::initial_connections(host.as_deref(), max_connections);
+    let task_slots = Arc::new(Semaphore::new(open as usize));
+    let _tuner = autotune::start(
+        &task.task_id,
+        host,
+        task_slots.clone(),
+        open,
+        max_connections,
+        progress_snapshot.clone(),
+        conn_controller.clone(),
+    );
     let mut flush_handle = spawn_flush_task(
         Arc::clone(db),
         app.clone(),
@@ -1876,7 +2176,7 @@ pub async fn download_file(
         task.file_name.clone(),
         Arc::clone(&progress_registry),
         progress_snapshot.clone(),
-        writer.clone(),
+        Some(writer.clone()),
         progress_rx,
     );
 
@@ -1892,11 +2192,8 @@ pub async fn download_file(
     let task_start_time = std::time::Instant::now();
     let mut ctx = DownloadContext {
         task_id: &task.task_id,
-        file_name: &task.file_name,
-        file_size: task.file_size,
         save_path: &task.save_path,
         pick_code: &task.pick_code,
-        expected_sha1: task.expected_sha1.as_deref(),
         supports_range: range_info.supports_range,
         segments: &mut task.segments,
         db,
@@ -1907,7 +2204,9 @@ pub async fn download_file(
         url_rx: &url_rx,
         url_refresh_requested: &url_refresh_requested,
         semaphore: &segment_semaphore,
+        task_slots: &task_slots,
         conn_controller: &conn_controller,
+        volume_root: config.volume_root.as_deref(),
     };
 
     let mut join_set: JoinSet<Result<(u16, u64), (Segment, DownloadError)>> = JoinSet::new();
@@ -1915,8 +2214,7 @@ pub async fn download_file(
         &mut ctx,
         &mut join_set,
         client,
-        token,
-        user_agent,
+        headers,
         &progress_tx,
         false,
     )
@@ -1925,28 +2223,91 @@ pub async fn download_file(
         &mut ctx,
         &mut join_set,
         client,
-        token,
-        user_agent,
+        headers,
         progress_tx,
         &mut flush_handle,
+        "task",
+    )
+    .await?;
+    drop(writer);
+
+    commit_download(
+        db,
+        &task.task_id,
+        &task.file_name,
+        task.file_size,
+        &task.save_path,
+        task.expected_sha1.as_deref(),
         task_start_time,
         "task",
     )
     .await
 }
 
+/// 续传校验结果。
+#[derive(Debug, PartialEq, Eq)]
+enum ResumeCheck {
+    /// 远端文件未变化，附带所用的校验依据。
+    Unchanged(&'static str),
+    /// 远端文件已变化，附带变化说明。
+    Changed(String),
+    /// 断点记录与当前响应没有共同的校验依据。
+    Unverified,
+}
+
+/// 续传前用可用的最强校验依据比较断点记录与当前远端响应。
+///
+/// Content-Length 与记录大小不一致直接判定为变化；其余依次尝试强 ETag、Last-Modified、
+/// 弱 ETag，取双方都有的第一项比较。部分 CDN 节点不返回 ETag 或返回弱 ETag，
+/// 此时退到下一项，而不是直接信任旧数据。
+fn check_resume_validity(
+    file_size: u64,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+    current: &RangeInfo,
+) -> ResumeCheck {
+    fn compare(validator: &'static str, stored: &str, current: &str) -> ResumeCheck {
+        if stored == current {
+            ResumeCheck::Unchanged(validator)
+        } else {
+            ResumeCheck::Changed(format!("{} {} → {}", validator, stored, current))
+        }
+    }
+    fn strong(etag: Option<&str>) -> Option<&str> {
+        etag.filter(|e| !e.starts_with("W/"))
+    }
+    fn weak(etag: Option<&str>) -> Option<&str> {
+        etag.map(|e| e.trim_start_matches("W/"))
+    }
+
+    if let Some(content_length) = current.content_length
+        && content_length != file_size
+    {
+        return ResumeCheck::Changed(format!("大小 {} → {}", file_size, content_length));
+    }
+    if let (Some(stored), Some(now)) = (strong(etag), strong(current.etag.as_deref())) {
+        return compare("ETag", stored, now);
+    }
+    if let (Some(stored), Some(now)) = (last_modified, current.last_modified.as_deref()) {
+        return compare("Last-Modified", stored, now);
+    }
+    if let (Some(stored), Some(now)) = (weak(etag), weak(current.etag.as_deref())) {
+        return compare("弱 ETag", stored, now);
+    }
+    ResumeCheck::Unverified
+}
+
 /// 恢复中断的下载任务
 ///
-/// 1. 从进度文件加载分片 → 2. HEAD + ETag 验证 →
-/// 3a. ETag 匹配：跳过已完成分片，从断点恢复 →
-/// 3b. ETag 不匹配：清除进度，从头重新下载
+/// 1. 从进度文件加载分片 → 2. HEAD 探测并按最强校验依据验证 →
+/// 3a. 校验一致：跳过已完成分片，从断点恢复 →
+/// 3b. 校验不一致：清除进度，从头重新下载
 pub async fn resume_download(
     client: &reqwest::Client,
     task_id: &str,
     url: &str,
     save_path: &str,
-    token: &str,
-    user_agent: &str,
+    headers: &HeaderMap,
     config: &DownloadConfig,
     db: &Arc<ProgressFile>,
     app: &AppHandle,
@@ -1964,20 +2325,38 @@ pub async fn resume_download(
             task_id, task_meta.task_id
         )));
     }
+    adopt_legacy_data_file(&task_meta.save_path)?;
 
-    let mut need_restart = false;
-    let range_info = detect_range_support(client, url, token, user_agent).await?;
+    let range_info =
+        detect_range_support(client, url, headers, !task_meta.pick_code.is_empty()).await?;
     let supports_range = range_info.supports_range;
-
-    if !supports_range {
-        need_restart = true;
+    let host = cdn_limits::host_of(url);
+    if let Some(host) = &host {
+        conn_controller.start_at_learned(&segment_semaphore, host);
     }
-    if let Some(ref stored_etag) = task_meta.etag {
-        match range_info.etag {
-            Some(ref server_etag) if server_etag == stored_etag => {}
-            _ => {
-                need_restart = true;
-            }
+
+    let mut need_restart = !supports_range;
+    match check_resume_validity(
+        task_meta.file_size,
+        task_meta.etag.as_deref(),
+        task_meta.last_modified.as_deref(),
+        &range_info,
+    ) {
+        ResumeCheck::Unchanged(validator) => {
+            debug!("[续传][{}] {}一致，继续断点", task_id, validator);
+        }
+        ResumeCheck::Changed(reason) => {
+            warn!(
+                "[续传][{}] 远端文件已变化（{}），清除进度重新下载",
+                task_id, reason
+            );
+            need_restart = true;
+        }
+        ResumeCheck::Unverified => {
+            warn!(
+                "[续传][{}] 服务器未返回可比较的校验信息，沿用旧断点",
+                task_id
+            );
         }
     }
 
@@ -1991,6 +2370,7 @@ pub async fn resume_download(
             url: url.to_string(),
             pick_code: task_meta.pick_code,
             etag: None,
+            last_modified: None,
             expected_sha1: task_meta.expected_sha1,
             segments: Vec::new(),
             status: TaskStatus::Pending,
@@ -1999,8 +2379,7 @@ pub async fn resume_download(
         return download_file(
             client,
             &mut fresh_task,
-            token,
-            user_agent,
+            headers,
             config,
             db,
             app,
@@ -2014,14 +2393,18 @@ pub async fn resume_download(
         .await;
     }
 
-    if !std::path::Path::new(&task_meta.save_path).exists() {
+    let part_path = part_file_path(&task_meta.save_path);
+    if !std::path::Path::new(&part_path).exists() {
+        if !save_dir_available(&task_meta.save_path, config.volume_root.as_deref()) {
+            return Err(DownloadError::VolumeUnavailable(task_meta.save_path));
+        }
         return Err(DownloadError::FileNotFound(format!(
             "Download file missing: {}",
-            task_meta.save_path
+            part_path
         )));
     }
 
-    let writer = FileWriter::open(&task_meta.save_path)?;
+    let writer = FileWriter::open(&part_path)?;
     let mut segments = task_meta.segments;
     let completed_count = segments
         .iter()
@@ -2054,6 +2437,21 @@ pub async fn resume_download(
             .map(|s| (s.index, s.downloaded))
             .collect(),
     ));
+    let max_connections = segments
+        .iter()
+        .filter(|s| s.status != SegmentStatus::Completed && s.status != SegmentStatus::Reallocated)
+        .count() as u16;
+    let open = autotune::initial_connections(host.as_deref(), max_connections);
+    let task_slots = Arc::new(Semaphore::new(open as usize));
+    let _tuner = autotune::start(
+        task_id,
+        host,
+        task_slots.clone(),
+        open,
+        max_connections,
+        progress_snapshot.clone(),
+        conn_controller.clone(),
+    );
     let mut flush_handle = spawn_flush_task(
         Arc::clone(db),
         app.clone(),
@@ -2062,18 +2460,15 @@ pub async fn resume_download(
         task_meta.file_name.clone(),
         Arc::clone(&progress_registry),
         progress_snapshot.clone(),
-        writer.clone(),
+        Some(writer.clone()),
         progress_rx,
     );
 
     let task_start_time = std::time::Instant::now();
     let mut ctx = DownloadContext {
         task_id,
-        file_name: &task_meta.file_name,
-        file_size: task_meta.file_size,
         save_path: &task_meta.save_path,
         pick_code: &task_meta.pick_code,
-        expected_sha1: task_meta.expected_sha1.as_deref(),
         supports_range,
         segments: &mut segments,
         db,
@@ -2084,30 +2479,114 @@ pub async fn resume_download(
         url_rx: &url_rx,
         url_refresh_requested: &url_refresh_requested,
         semaphore: &segment_semaphore,
+        task_slots: &task_slots,
         conn_controller: &conn_controller,
+        volume_root: config.volume_root.as_deref(),
     };
 
     let mut join_set: JoinSet<Result<(u16, u64), (Segment, DownloadError)>> = JoinSet::new();
-    spawn_segments_with_stagger(
-        &mut ctx,
-        &mut join_set,
-        client,
-        token,
-        user_agent,
-        &progress_tx,
-        true,
-    )
-    .await;
+    spawn_segments_with_stagger(&mut ctx, &mut join_set, client, headers, &progress_tx, true).await;
     collect_results(
         &mut ctx,
         &mut join_set,
         client,
-        token,
-        user_agent,
+        headers,
         progress_tx,
         &mut flush_handle,
+        "resume",
+    )
+    .await?;
+    drop(writer);
+
+    commit_download(
+        db,
+        task_id,
+        &task_meta.file_name,
+        task_meta.file_size,
+        &task_meta.save_path,
+        task_meta.expected_sha1.as_deref(),
         task_start_time,
         "resume",
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_source_headers_skip_token_and_let_custom_headers_win() {
+        let source = DownloadSource::Url {
+            url: "https://example.com/a.bin".to_string(),
+            headers: [("Referer".to_string(), "https://custom/".to_string())].into(),
+            cookies: Some("sid=1".to_string()),
+            referer: Some("https://example.com/".to_string()),
+            credentials_required: false,
+        };
+        let headers = request_headers(&source, "secret", "ua").unwrap();
+        assert!(headers.get("authorization").is_none());
+        assert_eq!(headers["cookie"], "sid=1");
+        assert_eq!(headers["referer"], "https://custom/");
+        assert_eq!(headers["user-agent"], "ua");
+
+        let pan115 = request_headers(&DownloadSource::Pan115, "secret", "ua").unwrap();
+        assert_eq!(pan115["authorization"], "Bearer secret");
+
+        // 凭据不写入数据库，重启后从数据库还原的任务不能不带凭据继续下载
+        let restored = DownloadSource::from_db(source.to_db().as_deref());
+        assert!(matches!(
+            request_headers(&restored, "", "ua"),
+            Err(DownloadError::CredentialsLost)
+        ));
+    }
+
+    #[test]
+    fn content_range_total_parses_complete_length() {
+        assert_eq!(content_range_total("bytes 0-0/12345"), Some(12345));
+        assert_eq!(content_range_total("bytes 0-0/*"), None);
+    }
+
+    #[test]
+    fn content_length_comes_from_header_not_body() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(header_content_length(&headers), None);
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("1048576"));
+        assert_eq!(header_content_length(&headers), Some(1048576));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("abc"));
+        assert_eq!(header_content_length(&headers), None);
+    }
+
+    #[test]
+    fn resume_check_prefers_strongest_shared_validator() {
+        let probe = |etag: Option<&str>, last_modified: Option<&str>, len: Option<u64>| RangeInfo {
+            supports_range: true,
+            etag: etag.map(str::to_string),
+            last_modified: last_modified.map(str::to_string),
+            content_length: len,
+        };
+        let lm = Some("Mon, 01 Jan 2024 00:00:00 GMT");
+
+        assert!(matches!(
+            check_resume_validity(10, Some("\"a\""), lm, &probe(None, lm, Some(11))),
+            ResumeCheck::Changed(_)
+        ));
+        assert_eq!(
+            check_resume_validity(10, Some("\"a\""), lm, &probe(Some("\"a\""), None, Some(10))),
+            ResumeCheck::Unchanged("ETag")
+        );
+        // CDN 节点省略 ETag 时退到 Last-Modified
+        assert!(matches!(
+            check_resume_validity(10, Some("\"a\""), lm, &probe(None, Some("Tue"), None)),
+            ResumeCheck::Changed(_)
+        ));
+        assert_eq!(
+            check_resume_validity(10, Some("W/\"a\""), None, &probe(Some("\"a\""), None, None)),
+            ResumeCheck::Unchanged("弱 ETag")
+        );
+        assert_eq!(
+            check_resume_validity(10, None, None, &probe(None, None, Some(10))),
+            ResumeCheck::Unverified
+        );
+    }
+}
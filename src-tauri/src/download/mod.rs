@@ -1,11 +1,28 @@
+pub mod autotune;
+pub mod cdn_limits;
+pub mod conflict;
+pub mod dedup;
+pub mod disk_watch;
 pub mod events;
+pub mod extract;
+pub mod file_times;
+pub mod hls;
+pub mod hooks;
 pub mod http;
+pub mod path_rules;
 pub mod persistence;
+pub mod plan;
 pub mod queue;
+pub mod repair;
+pub mod sanitize;
+pub mod schedule;
 pub mod segment;
+pub mod small_file;
 pub mod store;
 pub mod throttle;
 pub mod types;
+pub mod verify;
+pub mod volume;
 pub mod writer;
 
 use events::EventBridge;
@@ -13,7 +30,7 @@ use persistence::ProgressFile;
 use queue::TaskQueue;
 use std::sync::Arc;
 use store::DbHandle;
-use tauri::{App, Manager};
+use tauri::{App, AppHandle, Manager};
 
 #[derive(Debug, thiserror::Error)]
 pub enum DownloadInitError {
@@ -62,6 +79,8 @@ pub fn init(app: &App) -> Result<(), DownloadInitError> {
     let db_handle = DbHandle::new(db_path.to_string_lossy().to_string())?;
     let db_for_events = db_handle.clone();
     let db_for_queue = db_handle.clone();
+    // CDN 限流记忆：读回上次保存的状态并定期写回
+    cdn_limits::start(db_handle.clone());
     app.manage(db_handle);
 
     // 4. 文件夹进度聚合器
@@ -92,3 +111,18 @@ pub fn init(app: &App) -> Result<(), DownloadInitError> {
 
     Ok(())
 }
+
+/// 应用退出前调用 — 把尚未写入数据库的 CDN 限流与调优记录落盘
+pub fn shutdown(app: &AppHandle) {
+    if let Some(db) = app.try_state::<DbHandle>() {
+        tauri::async_runtime::block_on(cdn_limits::flush(&db));
+    }
+}
+
+/// 测试用临时目录，每次调用都是新的空目录，避免并行测试互相覆盖
+#[cfg(test)]
+pub(crate) fn test_dir(prefix: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}", prefix, uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,365 @@
+//! 保存路径规则 — 按扩展名、大小、文件名正则或来源文件夹自动分类。
+//!
+//! 规则由设置页通过 `download_set_path_rules` 下发，单文件入队时按顺序匹配，第一条命中的规则
+//! 把保存目录替换为渲染后的模板。模板支持 `~` 开头的主目录以及以下占位符：
+//!
+//! - `{date}`：入队日期 `YYYY-MM-DD`
+//! - `{ext}`：小写扩展名
+//! - `{parent}`：来源 115 文件夹名
+//! - `{name}`：不含扩展名的文件名
+//!
+//! 相对模板以原保存目录（通常是默认下载目录）为基准。文件夹下载保持云端目录结构，不参与分类。
+
+use std::sync::Mutex;
+
+use log::info;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::sanitize::{FilenameRules, sanitize_component};
+use super::store::DmError;
+
+/// 当前生效的规则 — 由设置页通过 `download_set_path_rules` 更新，入队时读取。
+static PATH_RULES: Mutex<Vec<CompiledRule>> = Mutex::new(Vec::new());
+
+/// 单条保存路径规则，所有已设置的条件同时满足才算命中。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathRule {
+    /// 规则名称，仅用于展示。
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 扩展名列表（不含点，不区分大小写）。
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    #[serde(default)]
+    pub min_size: Option<u64>,
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// 匹配文件名的正则表达式。
+    #[serde(default)]
+    pub name_pattern: Option<String>,
+    /// 来源 115 文件夹路径；文件位于该文件夹或其子文件夹中时命中。
+    #[serde(default)]
+    pub source_folder: Option<String>,
+    /// 保存目录模板。
+    pub target: String,
+}
+
+/// 待分类的文件。
+#[derive(Debug, Clone, Copy)]
+pub struct RuleInput<'a> {
+    pub name: &'a str,
+    pub size: u64,
+    /// 文件所在的 115 文件夹路径。
+    pub source_folder: Option<&'a str>,
+}
+
+/// 路径预览结果。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathPreview {
+    pub save_path: String,
+    /// 命中规则的序号；未命中时为空，保存路径保持不变。
+    pub matched_rule: Option<usize>,
+    pub rule_name: Option<String>,
+}
+
+struct CompiledRule {
+    rule: PathRule,
+    name_regex: Option<Regex>,
+}
+
+fn compile_rules(rules: Vec<PathRule>) -> Result<Vec<CompiledRule>, DmError> {
+    rules
+        .into_iter()
+        .enumerate()
+        .map(|(index, rule)| {
+            if rule.target.trim().is_empty() {
+                return Err(DmError::Internal(format!(
+                    "第 {} 条路径规则缺少目标目录",
+                    index + 1
+                )));
+            }
+            let name_regex = rule
+                .name_pattern
+                .as_deref()
+                .filter(|pattern| !pattern.is_empty())
+                .map(Regex::new)
+                .transpose()
+                .map_err(|e| {
+                    DmError::Internal(format!("第 {} 条路径规则的正则无效：{}", index + 1, e))
+                })?;
+            Ok(CompiledRule { rule, name_regex })
+        })
+        .collect()
+}
+
+/// 文件扩展名（小写，不含点）；以点开头的隐藏文件没有扩展名。
+fn extension_of(name: &str) -> &str {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => &name[dot + 1..],
+        _ => "",
+    }
+}
+
+fn stem_of(name: &str) -> &str {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => &name[..dot],
+        _ => name,
+    }
+}
+
+/// `path` 是否等于 `folder` 或位于其下，按路径分量比较。
+fn is_under(path: &str, folder: &str) -> bool {
+    let mut path = path.split('/').filter(|c| !c.is_empty());
+    folder
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .all(|c| path.next() == Some(c))
+}
+
+impl CompiledRule {
+    fn matches(&self, input: &RuleInput) -> bool {
+        let rule = &self.rule;
+        let ext = extension_of(input.name);
+        if !rule.extensions.is_empty()
+            && !rule
+                .extensions
+                .iter()
+                .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(ext))
+        {
+            return false;
+        }
+        if rule.min_size.is_some_and(|min| input.size < min)
+            || rule.max_size.is_some_and(|max| input.size > max)
+        {
+            return false;
+        }
+        if let Some(regex) = &self.name_regex
+            && !regex.is_match(input.name)
+        {
+            return false;
+        }
+        if let Some(folder) = rule.source_folder.as_deref().filter(|f| !f.is_empty())
+            && !input
+                .source_folder
+                .is_some_and(|path| is_under(path, folder))
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// 展开模板开头的 `~`。
+fn expand_home(template: &str) -> String {
+    let rest = match template.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => rest,
+        _ => return template.to_string(),
+    };
+    match std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")) {
+        Ok(home) => format!("{}{}", home.trim_end_matches(['/', '\\']), rest),
+        Err(_) => template.to_string(),
+    }
+}
+
+/// 渲染目录模板。占位符的值按 Windows 规则清理，避免引入路径分隔符或非法字符；
+/// 值为空的目录层级会被省略。分隔符统一为 `/`，UNC 路径（`\\nas\share`）保留双斜杠前缀。
+fn render_template(template: &str, input: &RuleInput, date: &str) -> String {
+    let value = |raw: &str| {
+        if raw.is_empty() {
+            String::new()
+        } else {
+            sanitize_component(raw, FilenameRules::Windows)
+        }
+    };
+    let parent = input
+        .source_folder
+        .and_then(|path| path.rsplit('/').find(|c| !c.is_empty()))
+        .unwrap_or("");
+    let rendered = expand_home(template)
+        .replace("{date}", date)
+        .replace("{ext}", &value(&extension_of(input.name).to_lowercase()))
+        .replace("{parent}", &value(parent))
+        .replace("{name}", &value(stem_of(input.name)));
+
+    let root = if rendered.starts_with("\\\\") || rendered.starts_with("//") {
+        "//"
+    } else if rendered.starts_with(['/', '\\']) {
+        "/"
+    } else {
+        ""
+    };
+    let joined = rendered
+        .split(['/', '\\'])
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("{}{}", root, joined)
+}
+
+/// 模板渲染结果是否为绝对路径（含 Windows 盘符）。
+fn is_absolute(path: &str) -> bool {
+    path.starts_with('/') || path.as_bytes().get(1) == Some(&b':')
+}
+
+fn evaluate(rules: &[CompiledRule], save_path: &str, input: &RuleInput) -> PathPreview {
+    let Some((index, rule)) = rules.iter().enumerate().find(|(_, r)| r.matches(input)) else {
+        return PathPreview {
+            save_path: save_path.to_string(),
+            matched_rule: None,
+            rule_name: None,
+        };
+    };
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let dir = render_template(&rule.rule.target, input, &date);
+    let dir = if is_absolute(&dir) {
+        dir
+    } else {
+        let base_end = save_path.rfind(['/', '\\']).unwrap_or(0);
+        format!("{}/{}", &save_path[..base_end], dir)
+    };
+    let file_name = save_path
+        .rsplit(['/', '\\'])
+        .next()
+        .filter(|n| !n.is_empty())
+        .unwrap_or(input.name);
+    PathPreview {
+        save_path: format!("{}/{}", dir.trim_end_matches('/'), file_name),
+        matched_rule: Some(index),
+        rule_name: rule.rule.name.clone(),
+    }
+}
+
+/// 按当前规则确定保存路径；没有规则命中时原样返回。
+pub fn apply_path_rules(save_path: &str, input: &RuleInput) -> String {
+    let rules = PATH_RULES.lock().unwrap();
+    let preview = evaluate(&rules, save_path, input);
+    if let Some(index) = preview.matched_rule {
+        info!(
+            "[路径规则] {} 命中第{}条规则 → {}",
+            input.name,
+            index + 1,
+            preview.save_path
+        );
+    }
+    preview.save_path
+}
+
+/// 更新保存路径规则，正则无效时整体拒绝，保留原规则。
+#[tauri::command]
+pub fn download_set_path_rules(rules: Vec<PathRule>) -> Result<(), DmError> {
+    let compiled = compile_rules(rules)?;
+    info!("[设置路径规则] 共{}条", compiled.len());
+    *PATH_RULES.lock().unwrap() = compiled;
+    Ok(())
+}
+
+/// 预览文件入队时的保存路径。
+///
+/// 传入 `rules` 时用这组规则试算（设置页编辑中尚未保存），否则使用当前生效的规则。
+#[tauri::command]
+pub fn download_preview_save_path(
+    name: String,
+    size: u64,
+    save_path: String,
+    source_folder: Option<String>,
+    rules: Option<Vec<PathRule>>,
+) -> Result<PathPreview, DmError> {
+    let input = RuleInput {
+        name: &name,
+        size,
+        source_folder: source_folder.as_deref(),
+    };
+    match rules {
+        Some(rules) => Ok(evaluate(&compile_rules(rules)?, &save_path, &input)),
+        None => Ok(evaluate(&PATH_RULES.lock().unwrap(), &save_path, &input)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(target: &str) -> PathRule {
+        PathRule {
+            target: target.to_string(),
+            ..PathRule::default()
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_renders_target_directory() {
+        let rules = compile_rules(vec![
+            PathRule {
+                extensions: vec!["mp4".into(), ".MKV".into()],
+                min_size: Some(100),
+                ..rule("/v/{date}/{parent}")
+            },
+            PathRule {
+                name_pattern: Some(r"\.(zip|7z)$".into()),
+                ..rule("archives/{ext}")
+            },
+        ])
+        .unwrap();
+        let input = |name, size, source_folder| RuleInput {
+            name,
+            size,
+            source_folder,
+        };
+
+        let video = evaluate(
+            &rules,
+            "/dl/a.mkv",
+            &input("a.mkv", 200, Some("/电影/2024")),
+        );
+        assert_eq!(video.matched_rule, Some(0));
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        assert_eq!(video.save_path, format!("/v/{}/2024/a.mkv", date));
+
+        let small = evaluate(&rules, "/dl/a.mkv", &input("a.mkv", 10, None));
+        assert_eq!(small.matched_rule, None);
+        assert_eq!(small.save_path, "/dl/a.mkv");
+
+        let archive = evaluate(&rules, "/dl/b.ZIP", &input("b.ZIP", 1, None));
+        assert_eq!(archive.matched_rule, None);
+        let archive = evaluate(&rules, "/dl/b.zip", &input("b.zip", 1, None));
+        assert_eq!(archive.save_path, "/dl/archives/zip/b.zip");
+
+        // UNC 与盘符目标保持为绝对路径
+        let unc = compile_rules(vec![rule(r"\\nas\share\Videos\{ext}")]).unwrap();
+        let on_nas = evaluate(&unc, "/dl/a.mkv", &input("a.mkv", 1, None));
+        assert_eq!(on_nas.save_path, "//nas/share/Videos/mkv/a.mkv");
+        let drive = compile_rules(vec![rule(r"D:\Videos")]).unwrap();
+        let on_drive = evaluate(&drive, "/dl/a.mkv", &input("a.mkv", 1, None));
+        assert_eq!(on_drive.save_path, "D:/Videos/a.mkv");
+    }
+
+    #[test]
+    fn source_folder_matches_by_path_component() {
+        let rules = compile_rules(vec![PathRule {
+            source_folder: Some("/照片".into()),
+            ..rule("/p/{name}")
+        }])
+        .unwrap();
+        let hit = RuleInput {
+            name: "x.jpg",
+            size: 1,
+            source_folder: Some("/照片/2024"),
+        };
+        let miss = RuleInput {
+            source_folder: Some("/照片备份"),
+            ..hit
+        };
+        assert_eq!(evaluate(&rules, "/dl/x.jpg", &hit).save_path, "/p/x/x.jpg");
+        assert_eq!(evaluate(&rules, "/dl/x.jpg", &miss).matched_rule, None);
+        assert!(
+            compile_rules(vec![PathRule {
+                name_pattern: Some("(".into()),
+                ..rule("/x")
+            }])
+            .is_err()
+        );
+    }
+}
@@ -29,10 +29,16 @@ pub struct IncompleteTask {
     pub save_path: String,
     pub url: String,
     pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// 首次下载时 HEAD 返回的 Content-Length。
+    pub content_length: Option<u64>,
     pub pick_code: String,
     pub expected_sha1: Option<String>,
     pub created_at: u64,
     pub segments: Vec<Segment>,
+    /// HLS 任务所下载清晰度的目标时长与画面高度，续传时用于确认仍是同一清晰度。
+    pub hls_target_duration: Option<u64>,
+    pub hls_height: Option<u32>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -50,11 +56,15 @@ struct OofpData {
     file_size: u64,
     url: String,
     etag: Option<String>,
+    last_modified: Option<String>,
+    content_length: Option<u64>,
     pick_code: String,
     expected_sha1: Option<String>,
     status: String,
     created_at: u64,
     segments: Vec<Segment>,
+    hls_target_duration: Option<u64>,
+    hls_height: Option<u32>,
 }
 
 fn status_to_str(status: &SegmentStatus) -> &'static str {
@@ -80,10 +90,44 @@ fn str_to_status(s: &str) -> SegmentStatus {
 }
 
 /// 计算 .oofp 进度文件路径。
-fn oofp_path(save_path: &str) -> String {
+pub(super) fn oofp_path(save_path: &str) -> String {
     format!("{}.oofp", save_path)
 }
 
+/// 计算下载中临时数据文件路径。
+///
+/// 下载期间数据写入 `<save_path>.part`，SHA1 校验通过后再原子 rename 到最终路径，
+/// 避免媒体库、同步工具提前拾取半成品文件。`.oofp` 仍以最终路径为键。
+pub(super) fn part_file_path(save_path: &str) -> String {
+    format!("{}.part", save_path)
+}
+
+/// 兼容旧布局：旧版本直接写入最终路径，续传前把未完成数据迁移到 `.part`。
+///
+/// 仅在 `.oofp` 存在时调用；`.part` 已存在说明已是新布局，不做处理。
+pub(super) fn adopt_legacy_data_file(save_path: &str) -> Result<(), DownloadError> {
+    let part_path = part_file_path(save_path);
+    if std::path::Path::new(&part_path).exists() || !std::path::Path::new(save_path).is_file() {
+        return Ok(());
+    }
+    std::fs::rename(save_path, &part_path)?;
+    log::info!("[断点] 旧布局数据文件已迁移为 {}", part_path);
+    Ok(())
+}
+
+/// 删除未完成的下载数据与断点文件，用于校验失败后强制从头下载。
+///
+/// 新布局只删 `.part`，不触碰最终路径；旧布局的数据仍在最终路径上，一并删除。
+pub(super) fn remove_partial_download(save_path: &str) {
+    let part_path = part_file_path(save_path);
+    if std::path::Path::new(&part_path).exists() {
+        let _ = std::fs::remove_file(&part_path);
+    } else {
+        let _ = std::fs::remove_file(save_path);
+    }
+    let _ = std::fs::remove_file(oofp_path(save_path));
+}
+
 /// 将 OofpData 序列化为 .oofp 文件内容。
 fn serialize_oofp(data: &OofpData) -> String {
     let mut buf = String::with_capacity(512);
@@ -93,11 +137,33 @@ fn serialize_oofp(data: &OofpData) -> String {
     buf.push_str(&format!("file_size={}\n", data.file_size));
     buf.push_str(&format!("url={}\n", data.url));
     buf.push_str(&format!("etag={}\n", data.etag.as_deref().unwrap_or("")));
+    buf.push_str(&format!(
+        "last_modified={}\n",
+        data.last_modified.as_deref().unwrap_or("")
+    ));
+    buf.push_str(&format!(
+        "content_length={}\n",
+        data.content_length
+            .map(|v| v.to_string())
+            .unwrap_or_default()
+    ));
     buf.push_str(&format!("pick_code={}\n", data.pick_code));
     buf.push_str(&format!(
         "expected_sha1={}\n",
         data.expected_sha1.as_deref().unwrap_or("")
     ));
+    if data.hls_target_duration.is_some() || data.hls_height.is_some() {
+        buf.push_str(&format!(
+            "hls_target_duration={}\n",
+            data.hls_target_duration
+                .map(|v| v.to_string())
+                .unwrap_or_default()
+        ));
+        buf.push_str(&format!(
+            "hls_height={}\n",
+            data.hls_height.map(|v| v.to_string()).unwrap_or_default()
+        ));
+    }
     buf.push_str(&format!("status={}\n", data.status));
     buf.push_str(&format!("created_at={}\n", data.created_at));
     buf.push_str("[segments]\n");
@@ -121,8 +187,12 @@ fn deserialize_oofp(content: &str) -> Result<OofpData, DownloadError> {
     let mut file_size: u64 = 0;
     let mut url = String::new();
     let mut etag: Option<String> = None;
+    let mut last_modified: Option<String> = None;
+    let mut content_length: Option<u64> = None;
     let mut pick_code = String::new();
     let mut expected_sha1: Option<String> = None;
+    let mut hls_target_duration: Option<u64> = None;
+    let mut hls_height: Option<u32> = None;
     let mut status = "active".to_string();
     let mut created_at: u64 = 0;
     let mut segments: Vec<Segment> = Vec::new();
@@ -164,6 +234,14 @@ fn deserialize_oofp(content: &str) -> Result<OofpData, DownloadError> {
                         Some(value.to_string())
                     }
                 }
+                "last_modified" => {
+                    last_modified = if value.is_empty() {
+                        None
+                    } else {
+                        Some(value.to_string())
+                    }
+                }
+                "content_length" => content_length = value.parse().ok(),
                 "pick_code" => pick_code = value.to_string(),
                 "expected_sha1" => {
                     expected_sha1 = if value.is_empty() {
@@ -172,6 +250,8 @@ fn deserialize_oofp(content: &str) -> Result<OofpData, DownloadError> {
                         Some(value.to_string())
                     }
                 }
+                "hls_target_duration" => hls_target_duration = value.parse().ok(),
+                "hls_height" => hls_height = value.parse().ok(),
                 "status" => status = value.to_string(),
                 "created_at" => created_at = value.parse().unwrap_or(0),
                 _ => {}
@@ -191,11 +271,15 @@ fn deserialize_oofp(content: &str) -> Result<OofpData, DownloadError> {
         file_size,
         url,
         etag,
+        last_modified,
+        content_length,
         pick_code,
         expected_sha1,
         status,
         created_at,
         segments,
+        hls_target_duration,
+        hls_height,
     })
 }
 
@@ -281,6 +365,8 @@ impl ProgressFile {
         save_path: &str,
         url: &str,
         etag: Option<&str>,
+        last_modified: Option<&str>,
+        content_length: Option<u64>,
         pick_code: &str,
         expected_sha1: Option<&str>,
         created_at: u64,
@@ -292,11 +378,15 @@ impl ProgressFile {
             file_size,
             url: url.to_string(),
             etag: etag.map(|s| s.to_string()),
+            last_modified: last_modified.map(|s| s.to_string()),
+            content_length,
             pick_code: pick_code.to_string(),
             expected_sha1: expected_sha1.map(|s| s.to_string()),
             status: "active".to_string(),
             created_at,
             segments: Vec::new(),
+            hls_target_duration: None,
+            hls_height: None,
         };
         let content = serialize_oofp(&data);
         // 写入缓存
@@ -378,6 +468,22 @@ impl ProgressFile {
         })
     }
 
+    /// 记录 HLS 任务所下载清晰度的目标时长与画面高度
+    pub fn update_hls_rendition(
+        &self,
+        task_id: &str,
+        target_duration: Option<u64>,
+        height: Option<u32>,
+    ) -> Result<(), DownloadError> {
+        let save_path = self
+            .get_save_path(task_id)
+            .ok_or_else(|| DownloadError::FileNotFound(format!("No path for task {}", task_id)))?;
+        self.update_cached(&save_path, |data| {
+            data.hls_target_duration = target_duration;
+            data.hls_height = height;
+        })
+    }
+
     /// 插入重分配产生的新子分片
     pub fn insert_segments(
         &self,
@@ -411,10 +517,14 @@ impl ProgressFile {
             save_path: save_path.to_string(),
             url: data.url.clone(),
             etag: data.etag.clone(),
+            last_modified: data.last_modified.clone(),
+            content_length: data.content_length,
             pick_code: data.pick_code.clone(),
             expected_sha1: data.expected_sha1.clone(),
             created_at: data.created_at,
             segments: data.segments.clone(),
+            hls_target_duration: data.hls_target_duration,
+            hls_height: data.hls_height,
         };
         self.cache
             .lock()
@@ -426,6 +536,7 @@ impl ProgressFile {
     /// 将现有 .oofp 绑定到新任务 ID，允许删除任务后重新添加时继续续传。
     ///
     /// 这使 `.oofp` 的行为更接近 `.aria2`：任务记录可以删除，但断点文件仍可复用。
+    /// 旧版本留下的断点数据位于最终路径，接管时一并迁移到 `.part`。
     pub fn rebind_task(
         &self,
         save_path: &str,
@@ -436,6 +547,7 @@ impl ProgressFile {
         pick_code: &str,
         expected_sha1: Option<&str>,
     ) -> Result<(), DownloadError> {
+        adopt_legacy_data_file(save_path)?;
         self.update_cached(save_path, |data| {
             data.task_id = new_task_id.to_string();
             data.file_name = file_name.to_string();
@@ -0,0 +1,238 @@
+//! 文件夹下载预演（dry-run）。
+//!
+//! 与 `download_enqueue_folder` 走同一套路径分配和冲突判断，但不创建任何任务，
+//! 只汇总总大小、冲突文件、需要改名或超出系统限制的路径和剩余空间，供用户确认后再真正入队。
+
+use std::path::Path;
+
+use log::info;
+use serde::Serialize;
+
+use super::conflict::{ConflictPolicy, ConflictResolution, SyncCompare};
+use super::disk_watch::available_space_for_dir;
+use super::persistence::oofp_path;
+use super::queue::{
+    FolderFileItem, FolderPathAllocator, TaskQueue, resolve_folder_children, validate_folder_files,
+};
+use super::sanitize::{
+    FilenameRules, MAX_NAME_LEN, PathIssueKind, SIDECAR_RESERVE, component_issue, name_len,
+    sanitize_relative_path, sanitize_save_path,
+};
+use super::store::DmError;
+
+/// 完整路径的长度上限：Windows 未开启长路径支持时为 260（含结尾 NUL），
+/// macOS 为 1024 字节，Linux 为 4096 字节。
+const MAX_PATH_LEN: usize = if cfg!(windows) {
+    259
+} else if cfg!(target_os = "macos") {
+    1023
+} else {
+    4095
+};
+
+/// 冲突文件的预计处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PlannedAction {
+    Overwrite,
+    Rename,
+    Skip,
+}
+
+/// 保存路径上已有文件的子项。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedConflict {
+    pub path: String,
+    pub action: PlannedAction,
+    /// 改名后的保存路径。
+    pub target_path: Option<String>,
+    /// 跳过原因。
+    pub reason: Option<String>,
+}
+
+/// 无法按原样创建的保存路径。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathIssue {
+    /// 按云端原始名称拼出的保存路径。
+    pub path: String,
+    pub kind: PathIssueKind,
+    /// 清理文件名后实际使用的保存路径；路径整体过长时无法自动处理，为空。
+    pub sanitized_path: Option<String>,
+}
+
+/// 文件夹下载预演报告。
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderPlan {
+    pub total_files: u64,
+    pub total_bytes: u64,
+    /// 实际需要下载的文件数与字节数（扣除跳过的文件）。
+    pub download_files: u64,
+    pub download_bytes: u64,
+    pub skipped_files: u64,
+    pub skipped_bytes: u64,
+    pub conflicts: Vec<PlannedConflict>,
+    pub path_issues: Vec<PathIssue>,
+    /// 保存位置所在卷的剩余空间；查询失败时为空。
+    pub available_space: Option<u64>,
+    /// 需要的空间：待下载字节数加磁盘预留值。覆盖的旧文件占用的空间不计入，结果偏保守。
+    pub required_space: u64,
+    pub space_sufficient: bool,
+}
+
+/// 检查原始相对路径的每一级名称，以及清理后完整保存路径的长度。
+///
+/// 文件名和完整路径都按加上附属文件后缀（[`SIDECAR_RESERVE`]）后的长度判断。
+pub(super) fn path_issue(
+    relative_path: &str,
+    save_path: &str,
+    rules: FilenameRules,
+) -> Option<PathIssueKind> {
+    let mut names = relative_path
+        .split(['/', '\\'])
+        .filter(|name| !name.is_empty())
+        .peekable();
+    while let Some(name) = names.next() {
+        if let Some(issue) = component_issue(name, rules) {
+            return Some(issue);
+        }
+        if names.peek().is_none() && name_len(name) + SIDECAR_RESERVE > MAX_NAME_LEN {
+            return Some(PathIssueKind::NameTooLong);
+        }
+    }
+    (name_len(save_path) + SIDECAR_RESERVE > MAX_PATH_LEN).then_some(PathIssueKind::PathTooLong)
+}
+
+/// 预演文件夹下载，不创建任何任务。
+///
+/// 参数与 `download_enqueue_folder` 相同，前端可直接复用同一组参数；与预演无关的参数会被忽略。
+/// 同步模式或 `SkipIfSameSha1` 策略下会计算本地文件 SHA1，大文件夹需要一些时间。
+#[tauri::command]
+pub async fn download_plan_folder(
+    parent_path: String,
+    files: Vec<FolderFileItem>,
+    conflict_policy: Option<ConflictPolicy>,
+    sync: Option<SyncCompare>,
+    queue: tauri::State<'_, TaskQueue>,
+) -> Result<FolderPlan, DmError> {
+    validate_folder_files(&files)?;
+    // 父文件夹名同样来自 115，需要一并清理
+    let parent_path = sanitize_save_path(&parent_path);
+    let conflict_policy = conflict_policy.unwrap_or_default();
+    let mut plan = FolderPlan::default();
+    let rules = FilenameRules::for_dir(&parent_path);
+    let mut paths = FolderPathAllocator::default();
+
+    let candidates: Vec<String> = files
+        .iter()
+        .map(|file| paths.candidate(&parent_path, &sanitize_relative_path(&file.path, rules)))
+        .collect();
+    let resolutions = resolve_folder_children(&files, &candidates, conflict_policy, sync).await?;
+
+    for ((file, candidate), resolution) in files.iter().zip(candidates).zip(resolutions) {
+        let size = file.size.max(0) as u64;
+        plan.total_files += 1;
+        plan.total_bytes += size;
+
+        if let Some(kind) = path_issue(&file.path, &candidate, rules) {
+            plan.path_issues.push(PathIssue {
+                path: format!("{}/{}", parent_path, file.path),
+                kind,
+                sanitized_path: (kind != PathIssueKind::PathTooLong).then(|| candidate.clone()),
+            });
+        }
+        let exists = Path::new(&candidate).is_file() && !Path::new(&oofp_path(&candidate)).exists();
+
+        match resolution {
+            ConflictResolution::Download(save_path) => {
+                let save_path = paths.reserve(&candidate, save_path);
+                plan.download_files += 1;
+                plan.download_bytes += size;
+                if save_path != candidate {
+                    plan.conflicts.push(PlannedConflict {
+                        path: candidate,
+                        action: PlannedAction::Rename,
+                        target_path: Some(save_path),
+                        reason: None,
+                    });
+                } else if exists {
+                    plan.conflicts.push(PlannedConflict {
+                        path: candidate,
+                        action: PlannedAction::Overwrite,
+                        target_path: None,
+                        reason: None,
+                    });
+                }
+            }
+            ConflictResolution::Skip(reason) => {
+                plan.skipped_files += 1;
+                plan.skipped_bytes += size;
+                plan.conflicts.push(PlannedConflict {
+                    path: candidate,
+                    action: PlannedAction::Skip,
+                    target_path: None,
+                    reason: Some(reason),
+                });
+            }
+        }
+    }
+
+    plan.available_space = available_space_for_dir(&parent_path);
+    plan.required_space = plan.download_bytes.saturating_add(queue.disk_reserve());
+    plan.space_sufficient = plan
+        .available_space
+        .is_none_or(|available| available >= plan.required_space);
+
+    info!(
+        "[预演] {} 共{}个文件 {:.1}MB，需下载{}个，冲突{}个，路径问题{}个",
+        parent_path,
+        plan.total_files,
+        plan.total_bytes as f64 / 1024.0 / 1024.0,
+        plan.download_files,
+        plan.conflicts.len(),
+        plan.path_issues.len()
+    );
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_issue_checks_each_component_and_total_length() {
+        let posix = FilenameRules::Posix;
+        let long_name = "a".repeat(MAX_NAME_LEN + 1);
+        assert_eq!(
+            path_issue(&long_name, &format!("/d/{}", long_name), posix),
+            Some(PathIssueKind::NameTooLong)
+        );
+        assert_eq!(path_issue("sub/file.txt", "/d/sub/file.txt", posix), None);
+        // 文件名本身未超限，但加上 .part 等后缀后会超限；目录名不创建附属文件
+        let near_limit = "a".repeat(MAX_NAME_LEN - 1);
+        assert_eq!(
+            path_issue(&near_limit, &format!("/d/{}", near_limit), posix),
+            Some(PathIssueKind::NameTooLong)
+        );
+        assert_eq!(
+            path_issue(
+                &format!("{}/file.txt", near_limit),
+                &format!("/d/{}/file.txt", near_limit),
+                posix
+            ),
+            None
+        );
+        assert_eq!(
+            path_issue("sub/a:b.txt", "/d/sub/a_b.txt", FilenameRules::Windows),
+            Some(PathIssueKind::InvalidCharacter)
+        );
+
+        let deep = vec!["dir"; MAX_PATH_LEN / 4 + 1].join("/");
+        assert_eq!(
+            path_issue(&deep, &format!("/d/{}", deep), posix),
+            Some(PathIssueKind::PathTooLong)
+        );
+    }
+}
@@ -1,21 +1,43 @@
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
 
+use futures_util::{StreamExt, TryStreamExt, stream};
 use log::{debug, error, info, warn};
 use tokio::sync::{Notify, Semaphore, mpsc, oneshot, watch};
 use tokio::task::JoinHandle;
 
 use tauri::AppHandle;
 
-use super::events::{EventBridge, FolderAggregator, ProgressRegistry, UrlResolver};
+use super::conflict::{
+    ConflictPolicy, ConflictResolution, SyncCompare, is_occupied, is_up_to_date, numbered_path,
+    resolve_conflict,
+};
+use super::disk_watch::{
+    DEFAULT_DISK_RESERVE, DISK_CHECK_INTERVAL, ParkReason, STATUS_PAUSED_DISK_FULL,
+    STATUS_WAITING_FOR_VOLUME, SpaceProbe, below_reserve, can_resume, is_disk_full_error,
+    mount_point, save_dir_available,
+};
+use super::events::{EventBridge, FolderAggregator, ProgressRegistry, UrlKind, UrlResolver};
+use super::extract;
+use super::file_times::{FolderDirItem, RemoteTimes, apply_dir_times, apply_times};
+use super::hooks::{self, HookEvent};
 use super::http::{ConnectionController, DownloadSignal};
-use super::persistence::ProgressFile;
+use super::path_rules::{RuleInput, apply_path_rules};
+use super::persistence::{ProgressFile, oofp_path, part_file_path, remove_partial_download};
+use super::sanitize::{FilenameRules, sanitize_relative_path, sanitize_save_path};
+use super::schedule::FairScheduler;
 use super::store::{DbHandle, DmError, DownloadTask as StoreDownloadTask, TaskUpdate};
-use super::types::{DownloadConfig, DownloadError, TaskAbortReason};
+use super::types::{
+    DownloadConfig, DownloadError, DownloadSource, PreallocationMode, TaskAbortReason,
+};
+use super::verify::VERIFY_CONCURRENCY;
+use super::volume;
 
 const ERR_QUEUE_CHANNEL_CLOSED: &str = "下载队列不可用：调度通道已关闭";
 const ERR_PAUSE_ALL_REPLY_DROPPED: &str = "下载队列不可用：暂停确认通道已断开";
+/// 一次合并写入的完成回报上限。
+const COMPLETION_BATCH_SIZE: usize = 64;
 
 // ==================== 类型定义 ====================
 
@@ -31,11 +53,20 @@ pub struct FolderFileItem {
     pub size: i64,
     /// 相对路径（文件夹内的子路径）
     pub path: String,
+    /// 云端 SHA1，用于完成后校验及 `SkipIfSameSha1` 冲突策略。
+    #[serde(default)]
+    pub sha1: Option<String>,
+    /// 115 的修改/创建时间（Unix 秒）。
+    #[serde(default)]
+    pub remote_mtime: Option<i64>,
+    #[serde(default)]
+    pub remote_ctime: Option<i64>,
 }
 
 /// 入队请求。
 ///
 /// 前端命令层和恢复逻辑都会构造该对象，交给主循环统一调度。
+#[derive(Clone)]
 pub struct EnqueueRequest {
     pub gid: String,
     #[allow(dead_code)] // 恢复或重试时需要保留，spawn_download_task 本身不直接读取。
@@ -50,6 +81,92 @@ pub struct EnqueueRequest {
     pub user_agent: String,
     pub split: u16,
     pub max_global_connections: u16,
+    /// 下载来源，决定使用普通分片下载还是 HLS 下载。
+    pub source: DownloadSource,
+    /// 修复模式：比对并重写校验失败文件中损坏的块，而不是重新下载。
+    pub repair: bool,
+    /// 115 的修改/创建时间，下载完成后写回本地文件。
+    pub remote_times: RemoteTimes,
+    /// 开始下载时记录的保存卷挂载点；新任务为空，首次启动时补记。
+    pub volume_root: Option<String>,
+}
+
+/// 直链任务的自定义请求头和 Cookie（gid → 凭据）。
+///
+/// 凭据不写入数据库，只在本次运行期间保存，供暂停、重试后重建请求；
+/// 应用重启后这些任务不带凭据继续下载，服务器拒绝时需重新添加。
+static URL_CREDENTIALS: LazyLock<Mutex<HashMap<String, UrlCredentials>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+struct UrlCredentials {
+    headers: BTreeMap<String, String>,
+    cookies: Option<String>,
+}
+
+/// 入队时记下直链任务的凭据。
+fn remember_url_credentials(gid: &str, source: &DownloadSource) {
+    if let DownloadSource::Url {
+        headers, cookies, ..
+    } = source
+        && (!headers.is_empty() || cookies.is_some())
+    {
+        URL_CREDENTIALS.lock().unwrap().insert(
+            gid.to_string(),
+            UrlCredentials {
+                headers: headers.clone(),
+                cookies: cookies.clone(),
+            },
+        );
+    }
+}
+
+/// 任务完成或删除后丢弃凭据。
+fn forget_url_credentials(gid: &str) {
+    URL_CREDENTIALS.lock().unwrap().remove(gid);
+}
+
+/// 把运行期间保存的凭据补回由数据库还原的来源。
+fn restore_url_credentials(gid: &str, source: &mut DownloadSource) {
+    if let DownloadSource::Url {
+        headers, cookies, ..
+    } = source
+        && let Some(saved) = URL_CREDENTIALS.lock().unwrap().get(gid)
+    {
+        *headers = saved.headers.clone();
+        *cookies = saved.cookies.clone();
+    }
+}
+
+impl EnqueueRequest {
+    /// 由数据库任务记录重建入队请求，供恢复和重试复用。
+    pub fn from_store_task(
+        task: StoreDownloadTask,
+        token: String,
+        user_agent: String,
+        split: u16,
+        max_global_connections: u16,
+    ) -> Self {
+        let mut source = DownloadSource::from_db(task.source.as_deref());
+        restore_url_credentials(&task.gid, &mut source);
+        Self {
+            source,
+            repair: false,
+            remote_times: RemoteTimes::new(task.remote_mtime, task.remote_ctime),
+            volume_root: task.volume_root,
+            gid: task.gid,
+            fid: task.fid,
+            name: task.name,
+            pick_code: task.pick_code,
+            size: task.size,
+            save_path: task.path.unwrap_or_default(),
+            expected_sha1: task.expected_sha1,
+            parent_gid: task.parent_gid,
+            token,
+            user_agent,
+            split,
+            max_global_connections,
+        }
+    }
 }
 
 /// 生命周期控制指令。
@@ -75,6 +192,11 @@ pub enum ControlCommand {
     CancelFolder {
         parent_gid: String,
     },
+    /// 调整文件夹同时下载的子任务上限
+    SetFolderMaxActive {
+        parent_gid: String,
+        max_active_children: Option<usize>,
+    },
     ResumeFolderChildren(Vec<EnqueueRequest>),
     RetryFolderChildren(Vec<EnqueueRequest>),
     /// 全部暂停 — 冻结队列 + 暂停所有活跃任务 (per CTL-05, D-01, D-03)
@@ -88,6 +210,13 @@ pub enum ControlCommand {
         split: u16,
         max_global_connections: u16,
     },
+    /// 前端下发当前的启动参数，供启动恢复时停放的任务自动继续
+    SetLaunchParams {
+        token: String,
+        user_agent: String,
+        split: u16,
+        max_global_connections: u16,
+    },
 }
 
 /// 任务完成回报。
@@ -104,6 +233,10 @@ pub enum TaskCompletion {
     Paused { gid: String },
     /// 用户取消 — 释放槽位，删除 DB 记录
     Cancelled { gid: String },
+    /// 磁盘空间不足被暂停 — 释放槽位，停放到空间恢复后自动继续
+    DiskFull { gid: String },
+    /// 保存卷不可用 — 释放槽位，停放到卷重新挂载后自动继续
+    VolumeUnavailable { gid: String },
 }
 
 impl TaskCompletion {
@@ -113,7 +246,9 @@ impl TaskCompletion {
             | Self::Failed { gid, .. }
             | Self::VerifyFailed { gid, .. }
             | Self::Paused { gid }
-            | Self::Cancelled { gid } => gid,
+            | Self::Cancelled { gid }
+            | Self::DiskFull { gid }
+            | Self::VolumeUnavailable { gid } => gid,
         }
     }
 }
@@ -136,6 +271,7 @@ pub struct TaskQueue {
     enqueue_tx: mpsc::Sender<EnqueueRequest>,
     control_tx: mpsc::Sender<ControlCommand>,
     max_concurrent: Arc<AtomicUsize>,
+    disk_reserve: Arc<AtomicU64>,
     wake_notify: Arc<Notify>,
     folder_aggregator: Arc<FolderAggregator>,
     #[allow(dead_code)] // 仅供 PauseAll/ResumeAll 控制流使用，对外不暴露读取。
@@ -158,6 +294,7 @@ impl TaskQueue {
         let (completion_tx, completion_rx) = mpsc::channel::<TaskCompletion>(256);
         let (control_tx, control_rx) = mpsc::channel::<ControlCommand>(64);
         let max_concurrent = Arc::new(AtomicUsize::new(3));
+        let disk_reserve = Arc::new(AtomicU64::new(DEFAULT_DISK_RESERVE));
         let wake_notify = Arc::new(Notify::new());
         let frozen = Arc::new(AtomicBool::new(false));
 
@@ -167,6 +304,7 @@ impl TaskQueue {
             completion_rx,
             control_rx,
             max_concurrent.clone(),
+            disk_reserve.clone(),
             wake_notify.clone(),
             app,
             db,
@@ -183,6 +321,7 @@ impl TaskQueue {
             enqueue_tx,
             control_tx,
             max_concurrent,
+            disk_reserve,
             wake_notify,
             folder_aggregator,
             frozen,
@@ -204,6 +343,21 @@ impl TaskQueue {
         self.wake_notify.notify_one();
     }
 
+    /// 唤醒主循环重新挑选任务，供并发相关设置变更后调用。
+    pub fn wake(&self) {
+        self.wake_notify.notify_one();
+    }
+
+    /// 修改磁盘预留空间（字节），看门狗下一轮检查生效；0 表示不主动暂停。
+    pub fn set_disk_reserve(&self, bytes: u64) {
+        self.disk_reserve.store(bytes, Ordering::SeqCst);
+    }
+
+    /// 当前磁盘预留空间（字节）。
+    pub fn disk_reserve(&self) -> u64 {
+        self.disk_reserve.load(Ordering::SeqCst)
+    }
+
     /// 请求暂停指定任务。
     pub async fn pause(&self, gid: String) -> Result<(), DmError> {
         self.control_tx
@@ -243,6 +397,20 @@ impl TaskQueue {
             .map_err(|_| DmError::Internal(ERR_QUEUE_CHANNEL_CLOSED.into()))
     }
 
+    pub async fn set_folder_max_active(
+        &self,
+        parent_gid: String,
+        max_active_children: Option<usize>,
+    ) -> Result<(), DmError> {
+        self.control_tx
+            .send(ControlCommand::SetFolderMaxActive {
+                parent_gid,
+                max_active_children,
+            })
+            .await
+            .map_err(|_| DmError::Internal(ERR_QUEUE_CHANNEL_CLOSED.into()))
+    }
+
     pub async fn cancel_folder(&self, parent_gid: String) -> Result<(), DmError> {
         self.control_tx
             .send(ControlCommand::CancelFolder { parent_gid })
@@ -300,6 +468,25 @@ impl TaskQueue {
             .await
             .map_err(|_| DmError::Internal(ERR_QUEUE_CHANNEL_CLOSED.into()))
     }
+
+    /// 更新停放任务重新入队时使用的启动参数。
+    pub async fn set_launch_params(
+        &self,
+        token: String,
+        user_agent: String,
+        split: u16,
+        max_global_connections: u16,
+    ) -> Result<(), DmError> {
+        self.control_tx
+            .send(ControlCommand::SetLaunchParams {
+                token,
+                user_agent,
+                split,
+                max_global_connections,
+            })
+            .await
+            .map_err(|_| DmError::Internal(ERR_QUEUE_CHANNEL_CLOSED.into()))
+    }
 }
 
 // ==================== 主循环 ====================
@@ -313,6 +500,7 @@ async fn queue_loop(
     mut completion_rx: mpsc::Receiver<TaskCompletion>,
     mut control_rx: mpsc::Receiver<ControlCommand>,
     max_concurrent: Arc<AtomicUsize>,
+    disk_reserve: Arc<AtomicU64>,
     wake_notify: Arc<Notify>,
     app: AppHandle,
     db: DbHandle,
@@ -330,6 +518,16 @@ async fn queue_loop(
     let mut child_to_parent: HashMap<String, String> = HashMap::new();
     let mut pause_all_waiters: Vec<oneshot::Sender<()>> = Vec::new();
     let mut pause_all_snapshot: Option<PauseAllSnapshot> = None;
+    // 运行中任务的入队请求副本，磁盘满暂停后据此重新入队。
+    let mut running: HashMap<String, EnqueueRequest> = HashMap::new();
+    // 看门狗已发出暂停信号、尚未回报的任务。
+    let mut disk_full_pending: HashSet<String> = HashSet::new();
+    // 因磁盘空间不足或保存卷不可用而停放的任务，条件恢复后重新入队。
+    let mut parked: Vec<ParkedTask> = Vec::new();
+    // 最近一次入队或前端下发的 token 等参数，供启动恢复时停放的任务重新入队。
+    let mut last_launch: Option<LaunchParams> = None;
+    let mut disk_check = tokio::time::interval(DISK_CHECK_INTERVAL);
+    let mut scheduler = FairScheduler::default();
 
     // 分片级并发控制器，替代旧的全局下载信号量。
     let mut current_segment_limit: usize = 0;
@@ -337,14 +535,76 @@ async fn queue_loop(
     let mut conn_controller: Arc<ConnectionController> = Arc::new(ConnectionController::new(0));
 
     // 启动恢复必须先完成，之后主循环才开始接收新请求。
-    recover_tasks(&db, &progress_file, &folder_aggregator, &state_sync_notify).await;
+    // 磁盘满停放和保存卷断开的任务直接停放，空间或卷恢复后自动继续。
+    for (task, reason) in
+        recover_tasks(&db, &progress_file, &folder_aggregator, &state_sync_notify).await
+    {
+        parked.push(ParkedTask {
+            req: EnqueueRequest::from_store_task(task, String::new(), String::new(), 0, 0),
+            reason,
+        });
+    }
 
     loop {
         // 尝试填补空位 — 出队 waiting 任务并 spawn 下载
         while active.len() < max_concurrent.load(Ordering::SeqCst) && !frozen.load(Ordering::SeqCst)
         {
-            if let Some(req) = waiting.pop_front() {
+            // 在各顶层作业间轮流挑选，跳过文件夹或保存卷已达并发上限的任务。
+            let next = scheduler.pick(&waiting, &running, &db).await;
+            if let Some(mut req) = next.and_then(|index| waiting.remove(index)) {
                 let gid = req.gid.clone();
+                if let Some(launch) = LaunchParams::from_request(&req) {
+                    last_launch = Some(launch);
+                }
+
+                // 目标卷剩余空间已低于预留值时不启动，直接停放等待空间恢复。
+                let reserve = disk_reserve.load(Ordering::SeqCst);
+                if let Some(available) = SpaceProbe::default().available(&req.save_path)
+                    && below_reserve(available, reserve)
+                {
+                    info!(
+                        "[磁盘] 剩余空间不足，暂缓启动 gid={} 可用={}MB",
+                        gid,
+                        available / 1024 / 1024
+                    );
+                    park_task(req, ParkReason::DiskFull, None, &db, &mut parked).await;
+                    state_sync_notify.notify_one();
+                    continue;
+                }
+
+                // 已写入过数据的任务保存目录消失，说明所在卷已断开，停放等待重新挂载；
+                // 全新任务的目录会在创建文件时建立，不在此列。
+                if !save_dir_available(&req.save_path, req.volume_root.as_deref())
+                    && let Ok(Some(task)) = db.get_task_by_gid(gid.clone()).await
+                    && task.progress > 0.0
+                {
+                    info!(
+                        "[队列] 保存位置不可用，暂缓启动 gid={} path={}",
+                        gid, req.save_path
+                    );
+                    park_task(req, ParkReason::VolumeUnavailable, None, &db, &mut parked).await;
+                    state_sync_notify.notify_one();
+                    continue;
+                }
+
+                // 首次启动时记下保存卷的挂载点，之后据此识别卷已卸载、只剩挂载点目录的情况。
+                if req.volume_root.is_none()
+                    && let Some(root) = mount_point(&req.save_path)
+                {
+                    if let Err(e) = db
+                        .update_task(
+                            gid.clone(),
+                            TaskUpdate {
+                                volume_root: Some(Some(root.clone())),
+                                ..TaskUpdate::default()
+                            },
+                        )
+                        .await
+                    {
+                        warn!("[队列] 记录保存卷失败 gid={}: {}", gid, e);
+                    }
+                    req.volume_root = Some(root);
+                }
 
                 // 如果任务级全局连接数变化，重建分片并发控制器。
                 let new_limit = req.max_global_connections as usize;
@@ -363,6 +623,8 @@ async fn queue_loop(
                 let (signal_tx, signal_rx) = watch::channel(DownloadSignal::Running);
                 signals.insert(gid.clone(), signal_tx);
 
+                running.insert(gid.clone(), req.clone());
+                volume::register_task(&gid, &req.save_path);
                 let handle = spawn_download_task(
                     req,
                     completion_tx.clone(),
@@ -393,17 +655,19 @@ async fn queue_loop(
                 match cmd {
                     ControlCommand::Pause { gid } => {
                         if let Some(tx) = signals.get(&gid) {
+                            // 用户主动暂停优先于看门狗暂停，回报时按普通暂停处理。
+                            disk_full_pending.remove(&gid);
                             // 通过内部信号注册表发送暂停，避免依赖全局静态表。
                             let _ = tx.send(DownloadSignal::Paused);
                             // active 清理和数据库更新由 completion_rx 的 Paused 分支统一处理。
                         } else if let Some(pos) = waiting.iter().position(|r| r.gid == gid) {
                             // 等待队列中的任务：直接移除 + 更新 DB
-                            waiting.remove(pos);
+                            let req = waiting.remove(pos);
                             if let Err(e) = db
                                 .update_task(
                                     gid.clone(),
                                     TaskUpdate {
-                                        status: Some("paused".to_string()),
+                                        status: Some(paused_status(req.as_ref()).to_string()),
                                         download_speed: Some(0),
                                         eta: Some(None),
                                         ..TaskUpdate::default()
@@ -414,11 +678,28 @@ async fn queue_loop(
                                 error!("[队列] 暂停等待中任务失败 {}: {}", gid, e);
                             }
                             state_sync_notify.notify_one();
+                        } else if let Some(pos) = parked.iter().position(|p| p.req.gid == gid) {
+                            // 停放中的任务：移出停放列表，改为普通暂停
+                            let parked_task = parked.remove(pos);
+                            if let Err(e) = db
+                                .update_task(
+                                    gid.clone(),
+                                    TaskUpdate {
+                                        status: Some(paused_status(Some(&parked_task.req)).to_string()),
+                                        ..TaskUpdate::default()
+                                    },
+                                )
+                                .await
+                            {
+                                error!("[队列] 暂停停放中任务失败 {}: {}", gid, e);
+                            }
+                            state_sync_notify.notify_one();
                         } else {
                             warn!("[队列] 暂停失败，未找到任务 gid={}", gid);
                         }
                     }
                     ControlCommand::Cancel { gid } => {
+                        parked.retain(|p| p.req.gid != gid);
                         if let Some(tx) = signals.get(&gid) {
                             let _ = tx.send(DownloadSignal::Cancelled);
                         } else if let Some(pos) = waiting.iter().position(|r| r.gid == gid) {
@@ -445,25 +726,32 @@ async fn queue_loop(
                     }
                     ControlCommand::Resume(req) => {
                         debug!("[队列] 恢复 gid={}", req.gid);
+                        parked.retain(|p| p.req.gid != req.gid);
                         waiting.push_front(req);
                     }
                     ControlCommand::Retry(req) => {
                         debug!("[队列] 重试 gid={}", req.gid);
                         waiting.push_back(req);
                     }
+                    ControlCommand::SetFolderMaxActive { parent_gid, max_active_children } => {
+                        info!("[队列] 文件夹子任务上限 gid={} max={:?}", parent_gid, max_active_children);
+                        scheduler.set_folder_limit(&parent_gid, max_active_children);
+                    }
                     ControlCommand::PauseFolder { parent_gid } => {
                         info!("[队列] 暂停文件夹 gid={}", parent_gid);
 
                         // 1. 从等待队列移除该文件夹下尚未启动的子任务。
                         let mut paused_waiting_gids = Vec::new();
-                        waiting.retain(|req| {
+                        let mut take_child = |req: &EnqueueRequest| {
                             if req.parent_gid.as_deref() == Some(parent_gid.as_str()) {
                                 paused_waiting_gids.push(req.gid.clone());
                                 false
                             } else {
                                 true
                             }
-                        });
+                        };
+                        waiting.retain(&mut take_child);
+                        parked.retain(|p| take_child(&p.req));
 
                         // 2. 将刚移除的等待中子任务状态写回数据库为 paused。
                         for gid in &paused_waiting_gids {
@@ -526,6 +814,9 @@ async fn queue_loop(
                         waiting.retain(|req| {
                             req.parent_gid.as_deref() != Some(parent_gid.as_str())
                         });
+                        parked.retain(|p| {
+                            p.req.parent_gid.as_deref() != Some(parent_gid.as_str())
+                        });
 
                         // 2. 给仍在运行的子任务发送取消信号。
                         let active_children: Vec<String> = child_to_parent
@@ -604,7 +895,8 @@ async fn queue_loop(
 
                         // 将等待中的任务迁出内存队列并写回 paused，避免 resume_all 之后重复入队。
                         // frozen 只是内存态标记，若不持久化会影响崩溃恢复判断。
-                        let paused_waiting: Vec<EnqueueRequest> = waiting.drain(..).collect();
+                        let paused_waiting: Vec<EnqueueRequest> =
+                            waiting.drain(..).chain(parked.drain(..).map(|p| p.req)).collect();
                         for req in &paused_waiting {
                             if let Err(e) = db.update_task(
                                 req.gid.clone(),
@@ -654,6 +946,9 @@ async fn queue_loop(
                             active_count, paused_waiting.len(), parent_gids.len()
                         );
                     }
+                    ControlCommand::SetLaunchParams { token, user_agent, split, max_global_connections } => {
+                        last_launch = LaunchParams::new(token, user_agent, split, max_global_connections);
+                    }
                     ControlCommand::ResumeAll { token, user_agent, split, max_global_connections } => {
                         info!("[队列] 全部恢复");
                         frozen.store(false, Ordering::SeqCst);
@@ -703,20 +998,13 @@ async fn queue_loop(
                                 }
                                 for child in paused_children {
                                     child_to_parent.insert(child.gid.clone(), task.gid.clone());
-                                    resume_requests.push(EnqueueRequest {
-                                        gid: child.gid,
-                                        fid: child.fid,
-                                        name: child.name,
-                                        pick_code: child.pick_code,
-                                        size: child.size,
-                                        save_path: child.path.unwrap_or_default(),
-                                        expected_sha1: None,
-                                        parent_gid: child.parent_gid,
-                                        token: token.clone(),
-                                        user_agent: user_agent.clone(),
+                                    resume_requests.push(EnqueueRequest::from_store_task(
+                                        child,
+                                        token.clone(),
+                                        user_agent.clone(),
                                         split,
                                         max_global_connections,
-                                    });
+                                    ));
                                 }
                             } else {
                                 // 单文件任务直接重建为 EnqueueRequest。
@@ -724,20 +1012,13 @@ async fn queue_loop(
                                     status: Some("waiting".to_string()),
                                     ..TaskUpdate::default()
                                 }).await;
-                                resume_requests.push(EnqueueRequest {
-                                    gid: task.gid,
-                                    fid: task.fid,
-                                    name: task.name,
-                                    pick_code: task.pick_code,
-                                    size: task.size,
-                                    save_path: task.path.unwrap_or_default(),
-                                    expected_sha1: None,
-                                    parent_gid: None,
-                                    token: token.clone(),
-                                    user_agent: user_agent.clone(),
+                                resume_requests.push(EnqueueRequest::from_store_task(
+                                    task,
+                                    token.clone(),
+                                    user_agent.clone(),
                                     split,
                                     max_global_connections,
-                                });
+                                ));
                             }
                         }
 
@@ -752,217 +1033,331 @@ async fn queue_loop(
                     }
                 }
             }
-            Some(completion) = completion_rx.recv() => {
-                let gid = completion.gid().to_string();
-                active.remove(&gid);
-                signals.remove(&gid);
-
-                match completion {
-                    TaskCompletion::Completed { ref gid } => {
-                        info!("[队列] 任务完成 gid={}", gid);
-                        let now_ms = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap_or_default()
-                            .as_millis() as i64;
-                        if let Err(e) = db
-                            .update_task(
-                                gid.clone(),
-                                TaskUpdate {
-                                    status: Some("complete".to_string()),
-                                    completed_at: Some(Some(now_ms)),
-                                    progress: Some(100.0),
-                                    download_speed: Some(0),
-                                    eta: Some(None),
-                                    ..TaskUpdate::default()
-                                },
-                            )
-                            .await
-                        {
-                            error!("[队列] 更新已完成任务失败 {}: {}", gid, e);
-                        }
-                    }
-                    TaskCompletion::Failed { ref gid, ref error } => {
-                        warn!("[队列] 任务失败 gid={}: {}", gid, error);
-                        if let Err(e) = db
-                            .update_task(
-                                gid.clone(),
-                                TaskUpdate {
-                                    status: Some("error".to_string()),
-                                    error_message: Some(Some(error.clone())),
-                                    download_speed: Some(0),
-                                    eta: Some(None),
-                                    ..TaskUpdate::default()
-                                },
-                            )
-                            .await
-                        {
-                            error!("[队列] 更新失败任务失败 {}: {}", gid, e);
-                        }
-                    }
-                    TaskCompletion::VerifyFailed {
-                        ref gid,
-                        ref message,
-                    } => {
-                        warn!("[队列] 任务校验失败 gid={}: {}", gid, message);
-                        if let Err(e) = db
-                            .update_task(
-                                gid.clone(),
-                                TaskUpdate {
-                                    status: Some("verify_failed".to_string()),
-                                    error_message: Some(Some(message.clone())),
-                                    download_speed: Some(0),
-                                    eta: Some(None),
-                                    ..TaskUpdate::default()
-                                },
-                            )
-                            .await
-                        {
-                            error!("[队列] 更新校验失败任务失败 {}: {}", gid, e);
-                        }
-                    }
-                    TaskCompletion::Paused { ref gid } => {
-                        info!("[队列] 任务暂停 gid={}", gid);
-                        let paused_progress = match progress_file.get_task_progress(gid) {
-                            Ok(Some(snapshot)) => {
-                                debug!(
-                                    "[队列] 暂停任务进度 gid={} downloaded={}/{} ({:.2}%)",
-                                    gid,
-                                    snapshot.downloaded_bytes,
-                                    snapshot.total_bytes,
-                                    snapshot.progress,
-                                );
-                                Some(snapshot.progress)
-                            }
-                            Ok(None) => None,
-                            Err(e) => {
-                                warn!("[队列] 读取暂停任务进度失败 {}: {}", gid, e);
-                                None
-                            }
-                        };
-                        if let Err(e) = db
-                            .update_task(
-                                gid.clone(),
-                                TaskUpdate {
-                                    status: Some("paused".to_string()),
-                                    progress: paused_progress,
-                                    download_speed: Some(0),
-                                    eta: Some(None),
-                                    ..TaskUpdate::default()
-                                },
-                            )
-                            .await
-                        {
-                            error!("[队列] 更新暂停任务失败 {}: {}", gid, e);
-                        }
-                    }
-                    TaskCompletion::Cancelled { ref gid } => {
-                        info!("[队列] 任务取消 gid={} (DB记录已删除, 本地文件与.oofp保留)", gid);
-                        if let Err(e) = db.delete_task(gid.clone()).await {
-                            error!("[队列] 删除取消任务失败 {}: {}", gid, e);
+            Some(first) = completion_rx.recv() => {
+                // 已回报的完成一起处理：完成状态与父文件夹完成数合并到一个事务写入，
+                // 大量小文件同时完成时不再逐个提交。
+                let mut batch = vec![first];
+                while batch.len() < COMPLETION_BATCH_SIZE
+                    && let Ok(next) = completion_rx.try_recv()
+                {
+                    batch.push(next);
+                }
+                let completed: Vec<(String, Option<String>)> = batch
+                    .iter()
+                    .filter_map(|completion| match completion {
+                        TaskCompletion::Completed { gid } => {
+                            Some((gid.clone(), child_to_parent.get(gid).cloned()))
                         }
+                        _ => None,
+                    })
+                    .collect();
+                if !completed.is_empty() {
+                    let now_ms = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as i64;
+                    let count = completed.len();
+                    if let Err(e) = db.complete_tasks(completed, now_ms).await {
+                        error!("[队列] 更新已完成任务失败（{}个）: {}", count, e);
                     }
                 }
 
-                // 即时推送单任务状态变更，弥补 state-sync 150ms 去抖延迟
-                if !matches!(completion, TaskCompletion::Cancelled { .. }) {
-                    super::events::emit_download_task_status(&app, &db, &gid).await;
-                }
-
-                // Phase 5: Folder counter update + final status determination
-                if let Some(parent_gid) = child_to_parent.remove(&gid) {
-                    let child_task = db.get_task_by_gid(gid.clone()).await.ok().flatten();
-
-                    match &completion {
-                        TaskCompletion::Completed { .. } => {
+                for completion in batch {
+                    let gid = completion.gid().to_string();
+                    active.remove(&gid);
+                    signals.remove(&gid);
+                    let mut running_req = running.remove(&gid);
+                    volume::unregister_task(&gid);
+                    // 看门狗发出的暂停按磁盘满处理
+                    let was_disk_paused = disk_full_pending.remove(&gid);
+                    let completion = match completion {
+                        TaskCompletion::Paused { gid } if was_disk_paused => TaskCompletion::DiskFull { gid },
+                        other => other,
+                    };
+
+                    match completion {
+                        TaskCompletion::Completed { ref gid } => {
+                            info!("[队列] 任务完成 gid={}", gid);
+                            forget_url_credentials(gid);
+                            // 带预期 SHA1 的任务已通过校验，记入索引供之后的任务复用。
+                            if let Some(req) = running_req.as_ref()
+                                && let Some(sha1) = req.expected_sha1.as_deref()
+                            {
+                                super::dedup::index_file(&db, sha1, &req.save_path).await;
+                            }
+                            hooks::dispatch(HookEvent::FileComplete, gid, &db, &http_client);
+                            extract::on_file_complete(gid, &db, &app);
+                        }
+                        TaskCompletion::Failed { ref gid, ref error } => {
+                            warn!("[队列] 任务失败 gid={}: {}", gid, error);
                             if let Err(e) = db
-                                .increment_folder_counter(parent_gid.clone(), "completed_files".to_string(), 1)
+                                .update_task(
+                                    gid.clone(),
+                                    TaskUpdate {
+                                        status: Some("error".to_string()),
+                                        error_message: Some(Some(error.clone())),
+                                        download_speed: Some(0),
+                                        eta: Some(None),
+                                        ..TaskUpdate::default()
+                                    },
+                                )
                                 .await
                             {
-                                error!("[队列] 递增文件夹完成数失败 {}: {}", parent_gid, e);
+                                error!("[队列] 更新失败任务失败 {}: {}", gid, e);
                             }
-                            let child_size = child_task.as_ref().map(task_total_bytes).unwrap_or(0);
-                            folder_aggregator.increment_completed(&gid, child_size);
+                            hooks::dispatch(HookEvent::TaskFailed, gid, &db, &http_client);
                         }
-                        TaskCompletion::Failed { .. } | TaskCompletion::VerifyFailed { .. } => {
+                        TaskCompletion::VerifyFailed {
+                            ref gid,
+                            ref message,
+                        } => {
+                            warn!("[队列] 任务校验失败 gid={}: {}", gid, message);
                             if let Err(e) = db
-                                .increment_folder_counter(parent_gid.clone(), "failed_files".to_string(), 1)
+                                .update_task(
+                                    gid.clone(),
+                                    TaskUpdate {
+                                        status: Some("verify_failed".to_string()),
+                                        error_message: Some(Some(message.clone())),
+                                        download_speed: Some(0),
+                                        eta: Some(None),
+                                        ..TaskUpdate::default()
+                                    },
+                                )
                                 .await
                             {
-                                error!("[队列] 递增文件夹失败数失败 {}: {}", parent_gid, e);
+                                error!("[队列] 更新校验失败任务失败 {}: {}", gid, e);
                             }
-                            if let Some(task) = child_task.as_ref() {
-                                folder_aggregator.update_child_progress(
-                                    &gid,
-                                    estimate_task_downloaded_bytes(progress_file.as_ref(), task),
-                                );
+                            hooks::dispatch(HookEvent::TaskFailed, gid, &db, &http_client);
+                        }
+                        TaskCompletion::Paused { ref gid }
+                        | TaskCompletion::DiskFull { ref gid }
+                        | TaskCompletion::VolumeUnavailable { ref gid } => {
+                            let park_reason = match completion {
+                                TaskCompletion::DiskFull { .. } => Some(ParkReason::DiskFull),
+                                TaskCompletion::VolumeUnavailable { .. } => Some(ParkReason::VolumeUnavailable),
+                                _ => None,
+                            };
+                            info!("[队列] 任务暂停 gid={} 停放原因={:?}", gid, park_reason);
+                            let paused_progress = match progress_file.get_task_progress(gid) {
+                                Ok(Some(snapshot)) => {
+                                    debug!(
+                                        "[队列] 暂停任务进度 gid={} downloaded={}/{} ({:.2}%)",
+                                        gid,
+                                        snapshot.downloaded_bytes,
+                                        snapshot.total_bytes,
+                                        snapshot.progress,
+                                    );
+                                    Some(snapshot.progress)
+                                }
+                                Ok(None) => None,
+                                Err(e) => {
+                                    warn!("[队列] 读取暂停任务进度失败 {}: {}", gid, e);
+                                    None
+                                }
+                            };
+                            match (running_req.take(), park_reason) {
+                                (Some(req), Some(reason)) => {
+                                    park_task(req, reason, paused_progress, &db, &mut parked).await;
+                                }
+                                (req, _) => {
+                                    if let Err(e) = db
+                                        .update_task(
+                                            gid.clone(),
+                                            TaskUpdate {
+                                                status: Some(paused_status(req.as_ref()).to_string()),
+                                                progress: paused_progress,
+                                                download_speed: Some(0),
+                                                eta: Some(None),
+                                                ..TaskUpdate::default()
+                                            },
+                                        )
+                                        .await
+                                    {
+                                        error!("[队列] 更新暂停任务失败 {}: {}", gid, e);
+                                    }
+                                }
                             }
-                            folder_aggregator.increment_failed(&gid);
                         }
-                        TaskCompletion::Paused { .. } => {
-                            if let Some(task) = child_task.as_ref() {
-                                folder_aggregator.update_child_progress(
-                                    &gid,
-                                    estimate_task_downloaded_bytes(progress_file.as_ref(), task),
-                                );
+                        TaskCompletion::Cancelled { ref gid } => {
+                            info!("[队列] 任务取消 gid={} (DB记录已删除, 本地文件与.oofp保留)", gid);
+                            forget_url_credentials(gid);
+                            if let Err(e) = db.delete_task(gid.clone()).await {
+                                error!("[队列] 删除取消任务失败 {}: {}", gid, e);
                             }
                         }
-                        TaskCompletion::Cancelled { .. } => {}
                     }
 
-                    if matches!(completion, TaskCompletion::Completed { .. } | TaskCompletion::Cancelled { .. }) {
-                        folder_aggregator.remove_child(&gid);
+                    // 即时推送单任务状态变更，弥补 state-sync 150ms 去抖延迟
+                    if !matches!(completion, TaskCompletion::Cancelled { .. }) {
+                        super::events::emit_download_task_status(&app, &db, &gid).await;
                     }
 
-                    persist_folder_progress(&db, &folder_aggregator, &parent_gid).await;
+                    // Phase 5: Folder counter update + final status determination
+                    if let Some(parent_gid) = child_to_parent.remove(&gid) {
+                        let child_task = db.get_task_by_gid(gid.clone()).await.ok().flatten();
 
-                    if matches!(completion, TaskCompletion::Completed { .. } | TaskCompletion::Failed { .. } | TaskCompletion::VerifyFailed { .. }) {
-                        let has_active_children = child_to_parent.values().any(|p| p == &parent_gid);
-                        let has_waiting_children = waiting.iter().any(|r| {
-                            r.parent_gid.as_deref() == Some(&parent_gid)
-                        });
+                        match &completion {
+                            TaskCompletion::Completed { .. } => {
+                                let child_size = child_task.as_ref().map(task_total_bytes).unwrap_or(0);
+                                folder_aggregator.increment_completed(&gid, child_size);
+                            }
+                            TaskCompletion::Failed { .. } | TaskCompletion::VerifyFailed { .. } => {
+                                if let Err(e) = db
+                                    .increment_folder_counter(parent_gid.clone(), "failed_files".to_string(), 1)
+                                    .await
+                                {
+                                    error!("[队列] 递增文件夹失败数失败 {}: {}", parent_gid, e);
+                                }
+                                if let Some(task) = child_task.as_ref() {
+                                    folder_aggregator.update_child_progress(
+                                        &gid,
+                                        estimate_task_downloaded_bytes(progress_file.as_ref(), task),
+                                    );
+                                }
+                                folder_aggregator.increment_failed(&gid);
+                            }
+                            TaskCompletion::Paused { .. }
+                            | TaskCompletion::DiskFull { .. }
+                            | TaskCompletion::VolumeUnavailable { .. } => {
+                                if let Some(task) = child_task.as_ref() {
+                                    folder_aggregator.update_child_progress(
+                                        &gid,
+                                        estimate_task_downloaded_bytes(progress_file.as_ref(), task),
+                                    );
+                                }
+                            }
+                            TaskCompletion::Cancelled { .. } => {}
+                        }
+
+                        if matches!(completion, TaskCompletion::Completed { .. } | TaskCompletion::Cancelled { .. }) {
+                            folder_aggregator.remove_child(&gid);
+                        }
+
+                        persist_folder_progress(&db, &folder_aggregator, &parent_gid).await;
+
+                        if matches!(completion, TaskCompletion::Completed { .. } | TaskCompletion::Failed { .. } | TaskCompletion::VerifyFailed { .. }) {
+                            let has_active_children = child_to_parent.values().any(|p| p == &parent_gid);
+                            let has_waiting_children = waiting.iter().chain(parked.iter().map(|p| &p.req)).any(|r| {
+                                r.parent_gid.as_deref() == Some(&parent_gid)
+                            });
 
-                        if !has_active_children && !has_waiting_children {
-                            if let Ok(Some(parent)) = db.get_task_by_gid(parent_gid.clone()).await {
-                                if parent.status != "paused" {
-                                    let completed = parent.completed_files.unwrap_or(0);
-                                    let failed = parent.failed_files.unwrap_or(0);
-                                    let total = parent.total_files.unwrap_or(0);
-
-                                    if let Some(final_status) = determine_folder_final_status(completed, failed, total) {
-                                        info!(
-                                            "[队列] 文件夹{}最终状态: {} (成功={}, 失败={}, 总计={})",
-                                            parent_gid, final_status, completed, failed, total
-                                        );
-                                        let now_ms = std::time::SystemTime::now()
-                                            .duration_since(std::time::UNIX_EPOCH)
-                                            .unwrap_or_default()
-                                            .as_millis() as i64;
-                                        if let Err(e) = db
-                                            .update_task(
-                                                parent_gid.clone(),
-                                                TaskUpdate {
-                                                    status: Some(final_status.to_string()),
-                                                    progress: Some(100.0),
-                                                    download_speed: Some(0),
-                                                    eta: Some(None),
-                                                    completed_at: Some(Some(now_ms)),
-                                                    ..TaskUpdate::default()
-                                                },
-                                            )
-                                            .await
-                                        {
-                                            error!("[队列] 设置文件夹{}最终状态失败: {}", parent_gid, e);
+                            if !has_active_children && !has_waiting_children {
+                                if let Ok(Some(parent)) = db.get_task_by_gid(parent_gid.clone()).await {
+                                    if parent.status != "paused" {
+                                        let completed = parent.completed_files.unwrap_or(0);
+                                        let failed = parent.failed_files.unwrap_or(0);
+                                        let total = parent.total_files.unwrap_or(0);
+
+                                        if let Some(final_status) = determine_folder_final_status(completed, failed, total) {
+                                            info!(
+                                                "[队列] 文件夹{}最终状态: {} (成功={}, 失败={}, 总计={})",
+                                                parent_gid, final_status, completed, failed, total
+                                            );
+                                            let now_ms = std::time::SystemTime::now()
+                                                .duration_since(std::time::UNIX_EPOCH)
+                                                .unwrap_or_default()
+                                                .as_millis() as i64;
+                                            if let Err(e) = db
+                                                .update_task(
+                                                    parent_gid.clone(),
+                                                    TaskUpdate {
+                                                        status: Some(final_status.to_string()),
+                                                        progress: Some(100.0),
+                                                        download_speed: Some(0),
+                                                        eta: Some(None),
+                                                        completed_at: Some(Some(now_ms)),
+                                                        ..TaskUpdate::default()
+                                                    },
+                                                )
+                                                .await
+                                            {
+                                                error!("[队列] 设置文件夹{}最终状态失败: {}", parent_gid, e);
+                                            }
+                                            apply_folder_times(&parent);
+                                            hooks::dispatch(
+                                                HookEvent::FolderComplete,
+                                                &parent_gid,
+                                                &db,
+                                                &http_client,
+                                            );
+                                            folder_aggregator.remove_folder(&parent_gid);
                                         }
-                                        folder_aggregator.remove_folder(&parent_gid);
                                     }
                                 }
                             }
                         }
                     }
+
+                    progress_registry.remove(&gid);
+                    state_sync_notify.notify_one();
+                }
+            }
+            _ = disk_check.tick() => {
+                let reserve = disk_reserve.load(Ordering::SeqCst);
+                let mut probe = SpaceProbe::default();
+
+                // 1. 活跃任务所在卷低于预留值时发送暂停信号，回报后按磁盘满停放。
+                for (gid, req) in &running {
+                    if disk_full_pending.contains(gid) {
+                        continue;
+                    }
+                    let Some(available) = probe.available(&req.save_path) else {
+                        continue;
+                    };
+                    if below_reserve(available, reserve)
+                        && let Some(tx) = signals.get(gid)
+                    {
+                        warn!(
+                            "[磁盘] 剩余空间低于预留值，暂停任务 gid={} 可用={}MB 预留={}MB",
+                            gid,
+                            available / 1024 / 1024,
+                            reserve / 1024 / 1024
+                        );
+                        let _ = tx.send(DownloadSignal::Paused);
+                        disk_full_pending.insert(gid.clone());
+                    }
                 }
 
-                progress_registry.remove(&gid);
-                state_sync_notify.notify_one();
+                // 2. 条件恢复的停放任务重新入队，保持原有顺序插入队列头部。
+                //    启动恢复时停放的任务没有启动参数（split 为 0），由前端初始化时下发的参数补齐。
+                let (ready, still_parked): (Vec<_>, Vec<_>) = std::mem::take(&mut parked)
+                    .into_iter()
+                    .partition(|p| {
+                        let condition_met = match p.reason {
+                            ParkReason::DiskFull => probe
+                                .available(&p.req.save_path)
+                                .is_some_and(|available| can_resume(available, reserve)),
+                            ParkReason::VolumeUnavailable => {
+                                save_dir_available(&p.req.save_path, p.req.volume_root.as_deref())
+                            }
+                        };
+                        condition_met && (p.req.split > 0 || last_launch.is_some())
+                    });
+                parked = still_parked;
+                if !ready.is_empty() {
+                    info!("[磁盘] 停放条件已解除，{}个任务重新入队", ready.len());
+                    for ParkedTask { mut req, .. } in ready.into_iter().rev() {
+                        if req.split == 0
+                            && let Some(ref launch) = last_launch
+                        {
+                            launch.apply(&mut req);
+                        }
+                        if let Err(e) = db
+                            .update_task(
+                                req.gid.clone(),
+                                TaskUpdate {
+                                    status: Some("waiting".to_string()),
+                                    ..TaskUpdate::default()
+                                },
+                            )
+                            .await
+                        {
+                            error!("[磁盘] 恢复停放任务失败 {}: {}", req.gid, e);
+                        }
+                        waiting.push_front(req);
+                    }
+                    state_sync_notify.notify_one();
+                }
             }
             _ = wake_notify.notified() => {
                 // 被 set_max_concurrent 唤醒，下一轮循环检查出队
@@ -973,6 +1368,91 @@ async fn queue_loop(
     }
 }
 
+/// 暂停后写入数据库的状态。
+///
+/// 修复中的任务退回 `verify_failed`：修复标记只在内存中，若记为 `paused`，
+/// 之后恢复会按普通续传处理，沿用损坏的 `.part`。
+fn paused_status(req: Option<&EnqueueRequest>) -> &'static str {
+    if req.is_some_and(|req| req.repair) {
+        "verify_failed"
+    } else {
+        "paused"
+    }
+}
+
+/// 停放中的任务。
+struct ParkedTask {
+    req: EnqueueRequest,
+    reason: ParkReason,
+}
+
+/// 任务重新入队所需的前端参数。
+struct LaunchParams {
+    token: String,
+    user_agent: String,
+    split: u16,
+    max_global_connections: u16,
+}
+
+impl LaunchParams {
+    /// 没有 token（未登录）时返回空，停放的任务继续等待。
+    fn new(
+        token: String,
+        user_agent: String,
+        split: u16,
+        max_global_connections: u16,
+    ) -> Option<Self> {
+        (!token.is_empty() && split > 0).then_some(Self {
+            token,
+            user_agent,
+            split,
+            max_global_connections,
+        })
+    }
+
+    fn from_request(req: &EnqueueRequest) -> Option<Self> {
+        Self::new(
+            req.token.clone(),
+            req.user_agent.clone(),
+            req.split,
+            req.max_global_connections,
+        )
+    }
+
+    fn apply(&self, req: &mut EnqueueRequest) {
+        req.token = self.token.clone();
+        req.user_agent = self.user_agent.clone();
+        req.split = self.split;
+        req.max_global_connections = self.max_global_connections;
+    }
+}
+
+/// 将任务标记为停放状态并移入停放列表，等待看门狗在条件恢复后重新入队。
+async fn park_task(
+    req: EnqueueRequest,
+    reason: ParkReason,
+    progress: Option<f64>,
+    db: &DbHandle,
+    parked: &mut Vec<ParkedTask>,
+) {
+    if let Err(e) = db
+        .update_task(
+            req.gid.clone(),
+            TaskUpdate {
+                status: Some(reason.status().to_string()),
+                progress,
+                download_speed: Some(0),
+                eta: Some(None),
+                ..TaskUpdate::default()
+            },
+        )
+        .await
+    {
+        error!("[磁盘] 标记停放状态失败 {}: {}", req.gid, e);
+    }
+    parked.push(ParkedTask { req, reason });
+}
+
 fn try_finish_pause_all(
     active: &HashMap<String, JoinHandle<()>>,
     waiters: &mut Vec<oneshot::Sender<()>>,
@@ -1002,6 +1482,30 @@ fn try_finish_pause_all(
 
 // ==================== Helpers ====================
 
+/// 文件夹全部子任务结束后写回各级目录的云端时间，失败只记录日志。
+///
+/// 目录很多或目标是网络盘时逐个设置可能很慢，放到阻塞线程池执行，不占用调度循环。
+fn apply_folder_times(parent: &StoreDownloadTask) {
+    let Some(parent_path) = parent.path.clone() else {
+        return;
+    };
+    let times = RemoteTimes::new(parent.remote_mtime, parent.remote_ctime);
+    let dirs: Vec<FolderDirItem> = parent
+        .dir_times
+        .as_deref()
+        .and_then(|json| serde_json::from_str(json).ok())
+        .unwrap_or_default();
+    if times.is_empty() && dirs.is_empty() {
+        return;
+    }
+    let gid = parent.gid.clone();
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = apply_dir_times(&parent_path, times, &dirs) {
+            warn!("[队列] 设置文件夹时间失败 {}: {}", gid, e);
+        }
+    });
+}
+
 /// 根据子任务完成情况推导文件夹最终状态。
 fn determine_folder_final_status(
     completed_files: i64,
@@ -1132,10 +1636,84 @@ fn spawn_download_task(
 ) -> JoinHandle<()> {
     tokio::spawn(async move {
         let gid = req.gid.clone();
+        let url_kind = match req.source {
+            DownloadSource::Pan115 => Some(UrlKind::File),
+            DownloadSource::Hls { .. } => Some(UrlKind::Hls),
+            // 直链任务没有 pick_code，地址失效后无法刷新。
+            DownloadSource::Url { .. } => None,
+        };
+
+        // 0. 构造请求头。直链的自定义请求头已在入队时校验，这里失败说明数据库记录已损坏，
+        //    或重启后只保存在内存中的请求头和 Cookie 已丢失。
+        let headers = match super::http::request_headers(&req.source, &req.token, &req.user_agent) {
+            Ok(headers) => headers,
+            Err(e) => {
+                error!("[队列] 构造请求头失败 gid={}: {}", gid, e);
+                let _ = db
+                    .update_task(
+                        gid.clone(),
+                        TaskUpdate {
+                            status: Some("error".to_string()),
+                            error_message: Some(Some(e.to_string())),
+                            ..TaskUpdate::default()
+                        },
+                    )
+                    .await;
+                state_sync_notify.notify_one();
+                let _ = completion_tx
+                    .send(TaskCompletion::Failed {
+                        gid,
+                        error: e.to_string(),
+                    })
+                    .await;
+                return;
+            }
+        };
+
+        // 0.5 本地已有相同 SHA1 的文件时直接复用，不再请求下载地址。
+        if !req.repair
+            && !matches!(req.source, DownloadSource::Hls { .. })
+            && let Some(source_path) = super::dedup::try_reuse(
+                &db,
+                &gid,
+                req.expected_sha1.as_deref(),
+                req.size,
+                &req.save_path,
+                req.remote_times,
+            )
+            .await
+        {
+            if let Err(e) = db
+                .update_task(
+                    gid.clone(),
+                    TaskUpdate {
+                        reused_from: Some(Some(source_path)),
+                        ..TaskUpdate::default()
+                    },
+                )
+                .await
+            {
+                error!("[队列] 记录复用来源失败 gid={}: {}", gid, e);
+            }
+            let _ = completion_tx.send(TaskCompletion::Completed { gid }).await;
+            return;
+        }
 
         // 1. 获取下载地址，同时监听暂停或取消信号。
+        //    HLS 任务首次直接使用入队时的播放列表地址，失效后再走前端刷新；直链任务直接使用原地址。
+        let initial_url = async {
+            match req.source {
+                DownloadSource::Hls {
+                    ref playlist_url, ..
+                } => Ok(playlist_url.clone()),
+                DownloadSource::Url { ref url, .. } => Ok(url.clone()),
+                DownloadSource::Pan115 => {
+                    url_resolver.request_url(&app, &gid, &req.pick_code).await
+                }
+            }
+        };
         let url = tokio::select! {
-            result = url_resolver.request_url(&app, &gid, &req.pick_code) => {
+            result = initial_url => {
                 match result {
                     Ok(url) => url,
                     Err(e) => {
@@ -1177,33 +1755,43 @@ fn spawn_download_task(
             }
         };
 
+        // 小文件且没有可续传的 .oofp 时走快速通道，省去单独的 active 状态写入。
+        let small_file =
+            super::small_file::qualifies(&req) && progress_file.load_task(&req.save_path).is_err();
+
         // 2. 将数据库状态切换为 active。
-        if let Err(e) = db
-            .update_task(
-                gid.clone(),
-                TaskUpdate {
-                    status: Some("active".to_string()),
-                    ..TaskUpdate::default()
-                },
-            )
-            .await
-        {
-            error!("[队列] 设置活跃状态失败 gid={}: {}", gid, e);
+        if !small_file {
+            if let Err(e) = db
+                .update_task(
+                    gid.clone(),
+                    TaskUpdate {
+                        status: Some("active".to_string()),
+                        ..TaskUpdate::default()
+                    },
+                )
+                .await
+            {
+                error!("[队列] 设置活跃状态失败 gid={}: {}", gid, e);
+            }
+            state_sync_notify.notify_one();
         }
-        state_sync_notify.notify_one();
 
         // 3. 创建任务私有的 URL 广播通道，供后续地址刷新复用。
         let (url_tx, url_rx) = watch::channel(url.clone());
         let url_refresh_requested = Arc::new(AtomicBool::new(false));
 
         // 4. 启动 URL 刷新监控任务。
-        let url_monitor = {
+        let url_monitor = url_kind.map(|url_kind| {
             let flag = url_refresh_requested.clone();
             let resolver = url_resolver.clone();
             let app_clone = app.clone();
             let gid_clone = gid.clone();
             let pick_code = req.pick_code.clone();
             let url_tx = url_tx.clone();
+            let max_height = match req.source {
+                DownloadSource::Hls { max_height, .. } => max_height,
+                _ => None,
+            };
             let pf = progress_file.clone();
             tokio::spawn(async move {
                 loop {
@@ -1211,7 +1799,9 @@ fn spawn_download_task(
                     if flag.load(Ordering::SeqCst) {
                         debug!("[队列] URL刷新触发 gid={}", gid_clone);
                         match resolver
-                            .request_url(&app_clone, &gid_clone, &pick_code)
+                            .request_url_for(
+                                &app_clone, &gid_clone, &pick_code, url_kind, max_height,
+                            )
                             .await
                         {
                             Ok(new_url) => {
@@ -1223,6 +1813,11 @@ fn spawn_download_task(
                                 flag.store(false, Ordering::SeqCst);
                                 debug!("[队列] URL刷新成功 gid={}", gid_clone);
                             }
+                            Err(e @ DmError::NotFound(_)) => {
+                                // 前端明确无法换取地址：退出监控并关闭地址通道，等待新地址的下载随即失败。
+                                error!("[队列] URL刷新失败 gid={}: {}", gid_clone, e);
+                                break;
+                            }
                             Err(e) => {
                                 error!("[队列] URL刷新失败 gid={}: {}", gid_clone, e);
                                 // 保留刷新标志，下次轮询继续尝试。
@@ -1231,60 +1826,89 @@ fn spawn_download_task(
                     }
                 }
             })
-        };
+        });
+        // 有刷新监控时由它持有唯一的发送端，监控退出即关闭地址通道。
+        let _url_tx = url_monitor.is_none().then_some(url_tx);
 
         // 5. 根据 .oofp 是否存在决定走新下载还是断点续传。
         let config = DownloadConfig {
             split: req.split,
             speed_limit: 0,
+            preallocation: super::writer::preallocation_mode(),
+            volume_root: req.volume_root.clone(),
         };
 
-        let download_result = match progress_file.load_task(&req.save_path) {
-            Ok(existing) if existing.task_id == gid => {
-                // .oofp 存在且 task_id 匹配，按断点续传处理。
-                info!("[队列] 从.oofp恢复下载 gid={}", gid);
-                super::http::resume_download(
+        let download_result = match req.source {
+            DownloadSource::Hls { max_height, .. } => {
+                info!("[队列] 启动HLS下载 gid={}", gid);
+                super::hls::download_hls(
                     &http_client,
                     &gid,
-                    &url,
+                    &req.name,
                     &req.save_path,
+                    &req.pick_code,
+                    max_height,
                     &req.token,
                     &req.user_agent,
-                    &config,
+                    req.size.max(0) as u64,
                     &progress_file,
                     &app,
                     signal_rx,
                     url_rx,
                     url_refresh_requested,
                     segment_semaphore,
-                    conn_controller,
                     progress_registry.clone(),
                 )
                 .await
             }
-            Ok(existing) => {
-                // .oofp 存在但 task_id 不匹配：接管旧断点，允许删除后重新添加继续续传。
-                info!(
-                    "[队列] 发现旧.oofp gid={} 旧task_id={} — 复用断点继续下载",
-                    gid, existing.task_id
-                );
-                match progress_file.rebind_task(
-                    &req.save_path,
-                    &existing.task_id,
+            DownloadSource::Pan115 | DownloadSource::Url { .. } if req.repair => {
+                info!("[队列] 启动修复 gid={}", gid);
+                super::repair::repair_download(
+                    &http_client,
                     &gid,
-                    &req.name,
                     &url,
-                    &req.pick_code,
-                    req.expected_sha1.as_deref(),
-                ) {
-                    Ok(()) => {
+                    &req.save_path,
+                    &headers,
+                    req.split,
+                    &progress_file,
+                    &app,
+                    signal_rx,
+                    segment_semaphore,
+                    progress_registry.clone(),
+                )
+                .await
+            }
+            DownloadSource::Pan115 | DownloadSource::Url { .. } if small_file => {
+                debug!("[队列] 启动小文件下载 gid={}", gid);
+                super::small_file::download_small_file(
+                    &http_client,
+                    &super::small_file::SmallFile {
+                        task_id: &gid,
+                        file_name: &req.name,
+                        size: req.size as u64,
+                        save_path: &req.save_path,
+                        expected_sha1: req.expected_sha1.as_deref(),
+                        refreshable: matches!(req.source, DownloadSource::Pan115),
+                    },
+                    &headers,
+                    &progress_registry,
+                    signal_rx,
+                    url_rx,
+                    &url_refresh_requested,
+                )
+                .await
+            }
+            DownloadSource::Pan115 | DownloadSource::Url { .. } => {
+                match progress_file.load_task(&req.save_path) {
+                    Ok(existing) if existing.task_id == gid => {
+                        // .oofp 存在且 task_id 匹配，按断点续传处理。
+                        info!("[队列] 从.oofp恢复下载 gid={}", gid);
                         super::http::resume_download(
                             &http_client,
                             &gid,
                             &url,
                             &req.save_path,
-                            &req.token,
-                            &req.user_agent,
+                            &headers,
                             &config,
                             &progress_file,
                             &app,
@@ -1297,54 +1921,100 @@ fn spawn_download_task(
                         )
                         .await
                     }
-                    Err(e) => {
-                        error!("[队列] 接管旧.oofp失败 gid={}: {}", gid, e);
-                        Err(e)
+                    Ok(existing) => {
+                        // .oofp 存在但 task_id 不匹配：接管旧断点，允许删除后重新添加继续续传。
+                        info!(
+                            "[队列] 发现旧.oofp gid={} 旧task_id={} — 复用断点继续下载",
+                            gid, existing.task_id
+                        );
+                        match progress_file.rebind_task(
+                            &req.save_path,
+                            &existing.task_id,
+                            &gid,
+                            &req.name,
+                            &url,
+                            &req.pick_code,
+                            req.expected_sha1.as_deref(),
+                        ) {
+                            Ok(()) => {
+                                super::http::resume_download(
+                                    &http_client,
+                                    &gid,
+                                    &url,
+                                    &req.save_path,
+                                    &headers,
+                                    &config,
+                                    &progress_file,
+                                    &app,
+                                    signal_rx,
+                                    url_rx,
+                                    url_refresh_requested,
+                                    segment_semaphore,
+                                    conn_controller,
+                                    progress_registry.clone(),
+                                )
+                                .await
+                            }
+                            Err(e) => {
+                                error!("[队列] 接管旧.oofp失败 gid={}: {}", gid, e);
+                                Err(e)
+                            }
+                        }
+                    }
+                    Err(_) => {
+                        // 没有 .oofp，按全新下载处理。
+                        info!("[队列] 启动新下载 gid={}", gid);
+                        let mut task = super::types::DownloadTask {
+                            task_id: gid.clone(),
+                            file_name: req.name.clone(),
+                            file_size: req.size as u64,
+                            save_path: req.save_path.clone(),
+                            url: url.clone(),
+                            pick_code: req.pick_code.clone(),
+                            etag: None,
+                            last_modified: None,
+                            expected_sha1: req.expected_sha1.clone(),
+                            segments: Vec::new(),
+                            status: super::types::TaskStatus::Pending,
+                            created_at: std::time::SystemTime::now()
+                                .duration_since(std::time::UNIX_EPOCH)
+                                .unwrap_or_default()
+                                .as_millis() as u64,
+                        };
+                        super::http::download_file(
+                            &http_client,
+                            &mut task,
+                            &headers,
+                            &config,
+                            &progress_file,
+                            &app,
+                            signal_rx,
+                            url_rx,
+                            url_refresh_requested,
+                            segment_semaphore,
+                            conn_controller,
+                            progress_registry.clone(),
+                        )
+                        .await
                     }
                 }
             }
-            Err(_) => {
-                // 没有 .oofp，按全新下载处理。
-                info!("[队列] 启动新下载 gid={}", gid);
-                let mut task = super::types::DownloadTask {
-                    task_id: gid.clone(),
-                    file_name: req.name.clone(),
-                    file_size: req.size as u64,
-                    save_path: req.save_path.clone(),
-                    url: url.clone(),
-                    pick_code: req.pick_code.clone(),
-                    etag: None,
-                    expected_sha1: req.expected_sha1.clone(),
-                    segments: Vec::new(),
-                    status: super::types::TaskStatus::Pending,
-                    created_at: std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_millis() as u64,
-                };
-                super::http::download_file(
-                    &http_client,
-                    &mut task,
-                    &req.token,
-                    &req.user_agent,
-                    &config,
-                    &progress_file,
-                    &app,
-                    signal_rx,
-                    url_rx,
-                    url_refresh_requested,
-                    segment_semaphore,
-                    conn_controller,
-                    progress_registry.clone(),
-                )
-                .await
-            }
         };
 
         // === 6. 终止 URL 监控 ===
-        url_monitor.abort();
+        if let Some(monitor) = url_monitor {
+            monitor.abort();
+        }
 
-        // === 7. 映射下载结果到 TaskCompletion ===
+        // === 7. 写回云端文件时间，失败不影响下载结果 ===
+        if download_result.is_ok()
+            && !req.remote_times.is_empty()
+            && let Err(e) = apply_times(std::path::Path::new(&req.save_path), req.remote_times)
+        {
+            warn!("[队列] 设置文件时间失败 gid={}: {}", gid, e);
+        }
+
+        // === 8. 映射下载结果到 TaskCompletion ===
         let completion = match download_result {
             Ok(()) => TaskCompletion::Completed { gid },
             Err(DownloadError::TaskAborted(TaskAbortReason::Paused)) => {
@@ -1357,6 +2027,17 @@ fn spawn_download_task(
                 warn!("[队列] 文件校验失败 gid={}: {}", gid, message);
                 TaskCompletion::VerifyFailed { gid, message }
             }
+            Err(e) if is_disk_full_error(&e) => {
+                warn!("[队列] 磁盘已满，任务暂停 gid={}", gid);
+                TaskCompletion::DiskFull { gid }
+            }
+            Err(DownloadError::VolumeUnavailable(path)) => {
+                warn!(
+                    "[队列] 保存位置不可用，等待重新挂载 gid={} path={}",
+                    gid, path
+                );
+                TaskCompletion::VolumeUnavailable { gid }
+            }
             Err(e) => {
                 error!("[队列] 下载失败 gid={}: {}", gid, e);
                 TaskCompletion::Failed {
@@ -1370,11 +2051,36 @@ fn spawn_download_task(
     })
 }
 
+/// 按保存路径规则调整单文件任务的保存路径，`use_path_rules` 为 false 时保持原样。
+fn rule_save_path(
+    save_path: String,
+    name: &str,
+    size: i64,
+    source_folder: Option<&str>,
+    use_path_rules: Option<bool>,
+) -> String {
+    if !use_path_rules.unwrap_or(true) {
+        return save_path;
+    }
+    apply_path_rules(
+        &save_path,
+        &RuleInput {
+            name,
+            size: size.max(0) as u64,
+            source_folder,
+        },
+    )
+}
+
 // ==================== Tauri 命令 ====================
 
 /// 入队单文件下载任务。
 ///
-/// 依次创建数据库记录、压入内存队列，并触发一次状态同步。
+/// 依次处理保存路径冲突、创建数据库记录、压入内存队列，并触发一次状态同步。
+/// `conflict_policy` 缺省为覆盖；被跳过的任务直接记为完成。
+/// `remote_mtime`/`remote_ctime` 为 115 的修改/创建时间（Unix 秒），下载完成后写回本地文件。
+/// `use_path_rules` 缺省为 true，按保存路径规则重新分类；用户明确选择了保存位置时应传 false。
+/// `source_folder` 为文件所在的 115 文件夹路径，供按来源文件夹匹配的规则使用。
 #[tauri::command]
 pub async fn download_enqueue_file(
     fid: String,
@@ -1388,64 +2094,315 @@ pub async fn download_enqueue_file(
     user_agent: String,
     split: u16,
     max_global_connections: u16,
+    conflict_policy: Option<ConflictPolicy>,
+    remote_mtime: Option<i64>,
+    remote_ctime: Option<i64>,
+    source_folder: Option<String>,
+    use_path_rules: Option<bool>,
     queue: tauri::State<'_, TaskQueue>,
     db: tauri::State<'_, DbHandle>,
     event_bridge: tauri::State<'_, EventBridge>,
 ) -> Result<String, DmError> {
-    let gid = uuid::Uuid::new_v4().to_string();
+    let save_path = rule_save_path(
+        save_path,
+        &name,
+        size,
+        source_folder.as_deref(),
+        use_path_rules,
+    );
+    enqueue_single(
+        EnqueueRequest {
+            gid: uuid::Uuid::new_v4().to_string(),
+            fid,
+            name,
+            pick_code,
+            size,
+            save_path,
+            expected_sha1,
+            parent_gid,
+            token,
+            user_agent,
+            split,
+            max_global_connections,
+            source: DownloadSource::Pan115,
+            repair: false,
+            remote_times: RemoteTimes::new(remote_mtime, remote_ctime),
+            volume_root: None,
+        },
+        conflict_policy.unwrap_or_default(),
+        &queue,
+        &db,
+        &event_bridge,
+    )
+    .await
+}
+
+/// 入队 HLS 视频下载任务。
+///
+/// `playlist_url` 为前端通过视频播放接口换取的 m3u8 地址，`size` 仅用于进度展示；
+/// 地址失效时通过 `download:url-needed`（kind = hls）请求前端重新换取。
+/// 保存路径规则的参数同 [`download_enqueue_file`]。
+#[tauri::command]
+pub async fn download_enqueue_hls(
+    fid: String,
+    name: String,
+    pick_code: String,
+    size: i64,
+    save_path: String,
+    playlist_url: String,
+    max_height: Option<u32>,
+    parent_gid: Option<String>,
+    token: String,
+    user_agent: String,
+    max_global_connections: u16,
+    conflict_policy: Option<ConflictPolicy>,
+    source_folder: Option<String>,
+    use_path_rules: Option<bool>,
+    queue: tauri::State<'_, TaskQueue>,
+    db: tauri::State<'_, DbHandle>,
+    event_bridge: tauri::State<'_, EventBridge>,
+) -> Result<String, DmError> {
+    let save_path = rule_save_path(
+        save_path,
+        &name,
+        size,
+        source_folder.as_deref(),
+        use_path_rules,
+    );
+    enqueue_single(
+        EnqueueRequest {
+            gid: uuid::Uuid::new_v4().to_string(),
+            fid,
+            name,
+            pick_code,
+            size,
+            save_path,
+            expected_sha1: None,
+            parent_gid,
+            token,
+            user_agent,
+            split: 1,
+            max_global_connections,
+            source: DownloadSource::Hls {
+                playlist_url,
+                max_height,
+            },
+            repair: false,
+            remote_times: RemoteTimes::default(),
+            volume_root: None,
+        },
+        conflict_policy.unwrap_or_default(),
+        &queue,
+        &db,
+        &event_bridge,
+    )
+    .await
+}
+
+/// 入队任意 HTTP(S) 直链下载任务。
+///
+/// 与 115 文件共用分片引擎和 `.oofp` 断点续传。`size` 缺省时先用 HEAD 探测 Content-Length；
+/// 直链没有 pick_code，地址失效后任务直接失败，不会请求前端刷新。
+/// `use_path_rules` 同 [`download_enqueue_file`]；直链没有来源文件夹。
+#[tauri::command]
+pub async fn download_enqueue_url(
+    url: String,
+    save_path: String,
+    name: Option<String>,
+    size: Option<i64>,
+    headers: Option<BTreeMap<String, String>>,
+    cookies: Option<String>,
+    referer: Option<String>,
+    parent_gid: Option<String>,
+    user_agent: String,
+    split: u16,
+    max_global_connections: u16,
+    conflict_policy: Option<ConflictPolicy>,
+    use_path_rules: Option<bool>,
+    queue: tauri::State<'_, TaskQueue>,
+    db: tauri::State<'_, DbHandle>,
+    event_bridge: tauri::State<'_, EventBridge>,
+    client: tauri::State<'_, reqwest::Client>,
+) -> Result<String, DmError> {
+    let parsed =
+        reqwest::Url::parse(&url).map_err(|e| DmError::Internal(format!("下载链接无效：{}", e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(DmError::Internal(format!(
+            "仅支持 HTTP(S) 链接：{}",
+            parsed.scheme()
+        )));
+    }
+
+    let source = DownloadSource::Url {
+        url,
+        headers: headers.unwrap_or_default(),
+        cookies,
+        referer,
+        credentials_required: false,
+    };
+    // 入队前校验请求头，避免任务启动后才因非法请求头失败。
+    let request_headers = super::http::request_headers(&source, "", &user_agent)
+        .map_err(|e| DmError::Internal(e.to_string()))?;
+
+    let size = match size {
+        Some(size) => size,
+        None => super::http::probe_file_size(&client, parsed.as_str(), &request_headers)
+            .await
+            .map_err(|e| DmError::Internal(format!("探测文件大小失败：{}", e)))?
+            as i64,
+    };
+    let name = name.unwrap_or_else(|| {
+        std::path::Path::new(&save_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    let save_path = rule_save_path(save_path, &name, size, None, use_path_rules);
+
+    enqueue_single(
+        EnqueueRequest {
+            gid: uuid::Uuid::new_v4().to_string(),
+            fid: String::new(),
+            name,
+            pick_code: String::new(),
+            size,
+            save_path,
+            expected_sha1: None,
+            parent_gid,
+            token: String::new(),
+            user_agent,
+            split,
+            max_global_connections,
+            source,
+            repair: false,
+            remote_times: RemoteTimes::default(),
+            volume_root: None,
+        },
+        conflict_policy.unwrap_or_default(),
+        &queue,
+        &db,
+        &event_bridge,
+    )
+    .await
+}
+
+/// 单任务入队的公共流程：写入 waiting 记录 → 推入等待队列 → 通知前端。
+pub(super) async fn enqueue_single(
+    mut req: EnqueueRequest,
+    conflict_policy: ConflictPolicy,
+    queue: &TaskQueue,
+    db: &DbHandle,
+    event_bridge: &EventBridge,
+) -> Result<String, DmError> {
+    let gid = req.gid.clone();
     let now_ms = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
         .as_millis() as i64;
 
-    // 1. 先创建 waiting 状态的数据库记录。
+    // 1. 清理文件名中目标文件系统不支持的字符，再按冲突策略确定最终保存路径；
+    //    跳过的任务直接记为完成。
+    let sanitized = sanitize_save_path(&req.save_path);
+    let original_path = (sanitized != req.save_path).then(|| {
+        std::path::Path::new(&req.save_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    if let Some(original) = &original_path {
+        info!(
+            "[入队] 文件名已清理 gid={} {} → {}",
+            gid, original, sanitized
+        );
+        req.save_path = sanitized;
+    }
+    match resolve_conflict(
+        &req.save_path,
+        conflict_policy,
+        req.expected_sha1.as_deref(),
+    )
+    .await
+    .map_err(|e| DmError::Internal(format!("检查保存路径冲突失败：{}", e)))?
+    {
+        ConflictResolution::Download(save_path) => req.save_path = save_path,
+        ConflictResolution::Skip(reason) => {
+            info!("[入队] 跳过 gid={} path={}: {}", gid, req.save_path, reason);
+            db.insert_task(StoreDownloadTask {
+                original_path,
+                ..skipped_store_task(&req, now_ms, reason)
+            })
+            .await?;
+            event_bridge.notify_state_change();
+            return Ok(gid);
+        }
+    }
+
+    // 2. 先创建 waiting 状态的数据库记录，直链凭据只留在内存中。
+    remember_url_credentials(&gid, &req.source);
     db.insert_task(StoreDownloadTask {
-        gid: gid.clone(),
-        fid: fid.clone(),
-        name: name.clone(),
-        pick_code: pick_code.clone(),
-        size,
+        original_path,
+        ..waiting_store_task(&req, now_ms)
+    })
+    .await?;
+
+    // 3. 再加入内存等待队列。
+    queue.enqueue(req).await?;
+
+    // 4. 触发一次状态同步，让前端立即看到新任务。
+    event_bridge.notify_state_change();
+
+    info!("[入队] 已入队 gid={}", gid);
+    Ok(gid)
+}
+
+/// 由入队请求构造 waiting 状态的数据库记录。
+fn waiting_store_task(req: &EnqueueRequest, created_at: i64) -> StoreDownloadTask {
+    StoreDownloadTask {
+        gid: req.gid.clone(),
+        fid: req.fid.clone(),
+        name: req.name.clone(),
+        pick_code: req.pick_code.clone(),
+        size: req.size,
         status: "waiting".to_string(),
         progress: 0.0,
-        path: Some(save_path.clone()),
+        path: Some(req.save_path.clone()),
         download_speed: 0,
         eta: None,
         error_message: None,
         error_code: None,
-        created_at: Some(now_ms),
+        created_at: Some(created_at),
         completed_at: None,
         is_folder: false,
         is_collecting: false,
-        parent_gid: parent_gid.clone(),
+        parent_gid: req.parent_gid.clone(),
         total_files: None,
         completed_files: None,
         failed_files: None,
-    })
-    .await?;
-
-    // 2. 再加入内存等待队列。
-    queue
-        .enqueue(EnqueueRequest {
-            gid: gid.clone(),
-            fid,
-            name,
-            pick_code,
-            size,
-            save_path,
-            expected_sha1,
-            parent_gid,
-            token,
-            user_agent,
-            split,
-            max_global_connections,
-        })
-        .await?;
-
-    // 3. 触发一次状态同步，让前端立即看到新任务。
-    event_bridge.notify_state_change();
+        source: req.source.to_db(),
+        skip_reason: None,
+        skipped_files: None,
+        original_path: None,
+        remote_mtime: req.remote_times.modified,
+        remote_ctime: req.remote_times.created,
+        dir_times: None,
+        extract_status: None,
+        extract_error: None,
+        reused_from: None,
+        max_active_children: None,
+        volume_root: req.volume_root.clone(),
+        expected_sha1: req.expected_sha1.clone(),
+    }
+}
 
-    info!("[入队] 已入队 gid={}", gid);
-    Ok(gid)
+/// 按冲突策略跳过的任务：直接记为完成并保存原因，不进入队列。
+fn skipped_store_task(req: &EnqueueRequest, now_ms: i64, reason: String) -> StoreDownloadTask {
+    StoreDownloadTask {
+        status: "complete".to_string(),
+        progress: 100.0,
+        completed_at: Some(now_ms),
+        skip_reason: Some(reason),
+        ..waiting_store_task(req, now_ms)
+    }
 }
 
 /// 文件夹下载入队。
@@ -1487,6 +2444,19 @@ pub async fn download_create_folder_task(
         total_files: Some(0),
         completed_files: Some(0),
         failed_files: Some(0),
+        source: None,
+        skip_reason: None,
+        skipped_files: None,
+        original_path: None,
+        remote_mtime: None,
+        remote_ctime: None,
+        dir_times: None,
+        extract_status: None,
+        extract_error: None,
+        reused_from: None,
+        max_active_children: None,
+        volume_root: None,
+        expected_sha1: None,
     })
     .await?;
 
@@ -1517,6 +2487,7 @@ pub async fn download_restart_folder_collection(
             total_files: Some(Some(0)),
             completed_files: Some(Some(0)),
             failed_files: Some(Some(0)),
+            skipped_files: Some(None),
             ..TaskUpdate::default()
         },
     )
@@ -1559,6 +2530,113 @@ pub async fn download_fail_folder_collection(
     Ok(())
 }
 
+/// 校验文件夹批量入队参数：数量上限及子路径不得逃逸出父目录。
+pub(super) fn validate_folder_files(files: &[FolderFileItem]) -> Result<(), DmError> {
+    if files.len() > 50_000 {
+        return Err(DmError::Internal(format!(
+            "文件夹内文件过多：{}，上限为 50000 个",
+            files.len()
+        )));
+    }
+    for f in files {
+        if f.path.contains("..") || f.path.starts_with('/') || f.path.starts_with('\\') {
+            return Err(DmError::Internal(format!("检测到非法子路径：{}", f.path)));
+        }
+    }
+    Ok(())
+}
+
+/// 文件夹子任务的保存路径分配：处理同批次内的同名文件及冲突改名后的撞名。
+#[derive(Default)]
+pub(super) struct FolderPathAllocator {
+    seen_paths: HashMap<String, usize>,
+    reserved_paths: HashSet<String>,
+}
+
+/// 从序号 `from` 起找出本地不存在的带序号路径，返回所用序号与路径。
+fn next_free_numbered(base: &str, from: usize) -> (usize, String) {
+    (from..)
+        .map(|n| (n, numbered_path(base, n)))
+        .find(|(_, path)| !is_occupied(path))
+        .expect("序号后缀无上限，必然能找到空闲路径")
+}
+
+impl FolderPathAllocator {
+    /// 子文件的初始保存路径；同批次重名时在扩展名前插入序号，如 "photo.jpg" → "photo (2).jpg"。
+    ///
+    /// 生成的序号路径会跳过本地已有的文件，不会覆盖与本次下载无关的文件。
+    pub(super) fn candidate(&mut self, parent_path: &str, relative_path: &str) -> String {
+        let base = format!("{}/{}", parent_path, relative_path);
+        match self.seen_paths.get_mut(&base) {
+            Some(count) => {
+                let (n, path) = next_free_numbered(&base, *count + 1);
+                *count = n;
+                path
+            }
+            None => {
+                self.seen_paths.insert(base.clone(), 1);
+                base
+            }
+        }
+    }
+
+    /// 登记最终保存路径；与同批次其他文件撞名时继续递增序号，跳过本地已有的文件。
+    pub(super) fn reserve(&mut self, candidate: &str, mut save_path: String) -> String {
+        let mut n = 1;
+        while self.reserved_paths.contains(&save_path) {
+            (n, save_path) = next_free_numbered(candidate, n + 1);
+        }
+        self.reserved_paths.insert(save_path.clone());
+        save_path
+    }
+}
+
+/// 决定文件夹子文件是否下载：同步模式按本地比对结果，否则按冲突策略。
+async fn resolve_folder_child(
+    save_path: &str,
+    file: &FolderFileItem,
+    conflict_policy: ConflictPolicy,
+    sync: Option<SyncCompare>,
+) -> Result<ConflictResolution, DmError> {
+    let expected_sha1 = file.sha1.as_deref();
+    match sync {
+        Some(compare) => {
+            let up_to_date = is_up_to_date(save_path, file.size, expected_sha1, compare)
+                .await
+                .map_err(|e| DmError::Internal(format!("比对本地文件失败：{}", e)))?;
+            Ok(if up_to_date {
+                ConflictResolution::Skip("本地文件已是最新，跳过下载".to_string())
+            } else {
+                ConflictResolution::Download(save_path.to_string())
+            })
+        }
+        None => resolve_conflict(save_path, conflict_policy, expected_sha1)
+            .await
+            .map_err(|e| DmError::Internal(format!("检查保存路径冲突失败：{}", e))),
+    }
+}
+
+/// 逐个决定文件夹子文件是否下载，`candidates` 为各文件的初始保存路径。
+///
+/// 同步模式和按 SHA1 跳过的冲突策略需要计算本地文件哈希，按有限并发比对；结果顺序与输入一致。
+pub(super) async fn resolve_folder_children(
+    files: &[FolderFileItem],
+    candidates: &[String],
+    conflict_policy: ConflictPolicy,
+    sync: Option<SyncCompare>,
+) -> Result<Vec<ConflictResolution>, DmError> {
+    stream::iter(files.iter().zip(candidates))
+        .map(|(file, candidate)| resolve_folder_child(candidate, file, conflict_policy, sync))
+        .buffered(VERIFY_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+/// 文件夹下载批量入队。
+///
+/// 传入 `sync` 时为同步下载：本地对应路径已有大小（及可选 SHA1）一致的文件则跳过，
+/// 缺失或有变化的文件直接覆盖下载，不再套用冲突策略。
+/// `parent_remote_*` 与 `dirs` 为文件夹及各级子目录的 115 时间，全部子任务结束后写回本地目录。
 #[tauri::command]
 pub async fn download_enqueue_folder(
     parent_gid: String,
@@ -1571,21 +2649,20 @@ pub async fn download_enqueue_folder(
     user_agent: String,
     split: u16,
     max_global_connections: u16,
+    conflict_policy: Option<ConflictPolicy>,
+    sync: Option<SyncCompare>,
+    parent_remote_mtime: Option<i64>,
+    parent_remote_ctime: Option<i64>,
+    dirs: Option<Vec<FolderDirItem>>,
+    max_active_children: Option<u32>,
     queue: tauri::State<'_, TaskQueue>,
     db: tauri::State<'_, DbHandle>,
     event_bridge: tauri::State<'_, EventBridge>,
 ) -> Result<String, DmError> {
-    if files.len() > 50_000 {
-        return Err(DmError::Internal(format!(
-            "文件夹内文件过多：{}，上限为 50000 个",
-            files.len()
-        )));
-    }
-    for f in &files {
-        if f.path.contains("..") || f.path.starts_with('/') || f.path.starts_with('\\') {
-            return Err(DmError::Internal(format!("检测到非法子路径：{}", f.path)));
-        }
-    }
+    validate_folder_files(&files)?;
+    // 父文件夹名同样来自 115，需要一并清理
+    let parent_path = sanitize_save_path(&parent_path);
+    let max_active_children = max_active_children.filter(|&n| n > 0).map(i64::from);
 
     let now_ms = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -1600,6 +2677,22 @@ pub async fn download_enqueue_folder(
     };
     let final_progress = if files.is_empty() { 100.0 } else { 0.0 };
     let final_completed_at = if files.is_empty() { Some(now_ms) } else { None };
+    let rules = FilenameRules::for_dir(&parent_path);
+    // 子目录时间按清理后的实际目录名记录
+    let dir_times = dirs
+        .filter(|dirs| !dirs.is_empty())
+        .map(|dirs| {
+            let dirs: Vec<FolderDirItem> = dirs
+                .into_iter()
+                .map(|dir| FolderDirItem {
+                    path: sanitize_relative_path(&dir.path, rules),
+                    ..dir
+                })
+                .collect();
+            serde_json::to_string(&dirs)
+        })
+        .transpose()
+        .map_err(|e| DmError::Internal(format!("序列化目录时间失败：{}", e)))?;
 
     // 1. 先创建或更新父文件夹任务。
     if db.get_task_by_gid(parent_gid.clone()).await?.is_some() {
@@ -1623,6 +2716,14 @@ pub async fn download_enqueue_folder(
                 total_files: Some(Some(total_files)),
                 completed_files: Some(Some(0)),
                 failed_files: Some(Some(0)),
+                skipped_files: Some(Some(0)),
+                remote_mtime: Some(parent_remote_mtime),
+                remote_ctime: Some(parent_remote_ctime),
+                dir_times: Some(dir_times.clone()),
+                extract_status: None,
+                extract_error: None,
+                reused_from: None,
+                max_active_children: Some(max_active_children),
                 ..TaskUpdate::default()
             },
         )
@@ -1649,6 +2750,19 @@ pub async fn download_enqueue_folder(
             total_files: Some(total_files),
             completed_files: Some(0),
             failed_files: Some(0),
+            source: None,
+            skip_reason: None,
+            skipped_files: Some(0),
+            original_path: None,
+            remote_mtime: parent_remote_mtime,
+            remote_ctime: parent_remote_ctime,
+            dir_times: dir_times.clone(),
+            extract_status: None,
+            extract_error: None,
+            reused_from: None,
+            max_active_children,
+            volume_root: None,
+            expected_sha1: None,
         })
         .await?;
     }
@@ -1656,6 +2770,12 @@ pub async fn download_enqueue_folder(
     if files.is_empty() {
         std::fs::create_dir_all(&parent_path)
             .map_err(|e| DmError::Internal(format!("创建空文件夹失败: {}", e)))?;
+        if let Err(e) = apply_times(
+            std::path::Path::new(&parent_path),
+            RemoteTimes::new(parent_remote_mtime, parent_remote_ctime),
+        ) {
+            warn!("[入队] 设置空文件夹时间失败 {}: {}", parent_path, e);
+        }
         event_bridge.notify_state_change();
         info!(
             "[入队] 空文件夹已完成 gid={} path={}",
@@ -1665,74 +2785,110 @@ pub async fn download_enqueue_folder(
     }
 
     // 2. 批量构造子任务记录和对应的入队请求。
+    let conflict_policy = conflict_policy.unwrap_or_default();
     let mut child_tasks = Vec::with_capacity(files.len());
     let mut enqueue_requests = Vec::with_capacity(files.len());
-    let mut seen_paths: HashMap<String, usize> = HashMap::new();
-
-    for file in &files {
-        let child_gid = uuid::Uuid::new_v4().to_string();
-        // 同名文件去重: 检测 save_path 冲突并添加后缀 (1), (2), ...
-        let base_save_path = format!("{}/{}", parent_path, file.path);
-        let save_path = match seen_paths.get_mut(&base_save_path) {
-            Some(count) => {
-                *count += 1;
-                // 在扩展名前插入后缀, 如 "photo.jpg" → "photo (2).jpg"
-                let path = &file.path;
-                if let Some(dot_pos) = path.rfind('.') {
-                    let stem = &path[..dot_pos];
-                    let ext = &path[dot_pos..];
-                    format!("{}/{} ({}){}", parent_path, stem, *count, ext)
-                } else {
-                    format!("{}/{} ({})", parent_path, path, *count)
-                }
-            }
-            None => {
-                seen_paths.insert(base_save_path, 1);
-                format!("{}/{}", parent_path, file.path)
-            }
-        };
+    let mut paths = FolderPathAllocator::default();
+    let mut skipped_files: i64 = 0;
+    let mut skipped_bytes: u64 = 0;
+    let mut renamed_files: usize = 0;
 
-        child_tasks.push(StoreDownloadTask {
-            gid: child_gid.clone(),
-            fid: file.fid.clone(),
-            name: file.name.clone(),
-            pick_code: file.pick_code.clone(),
-            size: file.size,
-            status: "waiting".to_string(),
-            progress: 0.0,
-            path: Some(save_path.clone()),
-            download_speed: 0,
-            eta: None,
-            error_message: None,
-            error_code: None,
-            created_at: Some(now_ms),
-            completed_at: None,
-            is_folder: false,
-            is_collecting: false,
-            parent_gid: Some(parent_gid.clone()),
-            total_files: None,
-            completed_files: None,
-            failed_files: None,
-        });
+    // 按目标文件系统清理每一级名称，改名后的撞名由路径分配器追加序号
+    let relative_paths: Vec<String> = files
+        .iter()
+        .map(|file| sanitize_relative_path(&file.path, rules))
+        .collect();
+    let candidates: Vec<String> = relative_paths
+        .iter()
+        .map(|relative_path| paths.candidate(&parent_path, relative_path))
+        .collect();
+    let resolutions = resolve_folder_children(&files, &candidates, conflict_policy, sync).await?;
 
-        enqueue_requests.push(EnqueueRequest {
-            gid: child_gid,
+    for (((file, relative_path), save_path), resolution) in files
+        .iter()
+        .zip(relative_paths)
+        .zip(candidates)
+        .zip(resolutions)
+    {
+        let original_path = (relative_path != file.path).then(|| file.path.clone());
+        renamed_files += original_path.is_some() as usize;
+        let mut req = EnqueueRequest {
+            gid: uuid::Uuid::new_v4().to_string(),
             fid: file.fid.clone(),
             name: file.name.clone(),
             pick_code: file.pick_code.clone(),
             size: file.size,
             save_path,
-            expected_sha1: None,
+            expected_sha1: file.sha1.clone(),
             parent_gid: Some(parent_gid.clone()),
             token: token.clone(),
             user_agent: user_agent.clone(),
             split,
             max_global_connections,
+            source: DownloadSource::Pan115,
+            repair: false,
+            remote_times: RemoteTimes::new(file.remote_mtime, file.remote_ctime),
+            volume_root: None,
+        };
+
+        match resolution {
+            ConflictResolution::Download(save_path) => {
+                req.save_path = paths.reserve(&req.save_path, save_path);
+            }
+            ConflictResolution::Skip(reason) => {
+                skipped_files += 1;
+                skipped_bytes += file.size.max(0) as u64;
+                child_tasks.push(StoreDownloadTask {
+                    original_path,
+                    ..skipped_store_task(&req, now_ms, reason)
+                });
+                continue;
+            }
+        }
+
+        child_tasks.push(StoreDownloadTask {
+            original_path,
+            ..waiting_store_task(&req, now_ms)
         });
+        enqueue_requests.push(req);
+    }
+    if renamed_files > 0 {
+        info!(
+            "[入队] 文件夹 gid={} 有{}个文件因名称不受目标文件系统支持已改名",
+            parent_gid, renamed_files
+        );
     }
 
     db.batch_insert_tasks(child_tasks).await?;
 
+    // 跳过的子任务直接计入父任务完成数；全部跳过时父任务立即完成。
+    if skipped_files > 0 {
+        let all_skipped = enqueue_requests.is_empty();
+        db.update_task(
+            parent_gid.clone(),
+            TaskUpdate {
+                completed_files: Some(Some(skipped_files)),
+                skipped_files: Some(Some(skipped_files)),
+                status: all_skipped.then(|| "complete".to_string()),
+                progress: all_skipped.then_some(100.0),
+                completed_at: all_skipped.then_some(Some(now_ms)),
+                ..TaskUpdate::default()
+            },
+        )
+        .await?;
+        info!(
+            "[入队] 文件夹 gid={} 跳过{}个本地已有的子任务",
+            parent_gid, skipped_files
+        );
+        if all_skipped {
+            if let Some(parent) = db.get_task_by_gid(parent_gid.clone()).await? {
+                apply_folder_times(&parent);
+            }
+            event_bridge.notify_state_change();
+            return Ok(parent_gid);
+        }
+    }
+
     // 3. 在 FolderAggregator 中注册父文件夹。
     queue.folder_aggregator.register_folder(
         &parent_gid,
@@ -1740,6 +2896,9 @@ pub async fn download_enqueue_folder(
         total_files,
         total_size as u64,
     );
+    queue
+        .folder_aggregator
+        .restore_counters(&parent_gid, skipped_files, 0, skipped_bytes);
 
     // 4. 注册子任务并逐个入队。
     for req in enqueue_requests {
@@ -1779,6 +2938,32 @@ pub async fn download_cancel_folder(
     queue.cancel_folder(parent_gid).await
 }
 
+/// 调整文件夹同时下载的子任务上限，0 或空表示只受全局并发限制。
+#[tauri::command]
+pub async fn download_set_folder_max_active(
+    parent_gid: String,
+    max_active_children: Option<u32>,
+    queue: tauri::State<'_, TaskQueue>,
+    db: tauri::State<'_, DbHandle>,
+) -> Result<(), DmError> {
+    let max_active_children = max_active_children.filter(|&n| n > 0);
+    db.update_task(
+        parent_gid.clone(),
+        TaskUpdate {
+            max_active_children: Some(max_active_children.map(i64::from)),
+            ..TaskUpdate::default()
+        },
+    )
+    .await?;
+    info!(
+        "[文件夹子任务上限] parent_gid={} max={:?}",
+        parent_gid, max_active_children
+    );
+    queue
+        .set_folder_max_active(parent_gid, max_active_children.map(|n| n as usize))
+        .await
+}
+
 /// 恢复文件夹下载 — 查询暂停子任务并批量恢复 (per FLD-06)
 #[tauri::command]
 pub async fn download_resume_folder(
@@ -1841,19 +3026,14 @@ pub async fn download_resume_folder(
 
     let children: Vec<EnqueueRequest> = paused_children
         .into_iter()
-        .map(|child| EnqueueRequest {
-            gid: child.gid,
-            fid: child.fid,
-            name: child.name,
-            pick_code: child.pick_code,
-            size: child.size,
-            save_path: child.path.unwrap_or_default(),
-            expected_sha1: None,
-            parent_gid: child.parent_gid,
-            token: token.clone(),
-            user_agent: user_agent.clone(),
-            split,
-            max_global_connections,
+        .map(|child| {
+            EnqueueRequest::from_store_task(
+                child,
+                token.clone(),
+                user_agent.clone(),
+                split,
+                max_global_connections,
+            )
         })
         .collect();
 
@@ -1920,11 +3100,10 @@ pub async fn download_retry_folder(
     let retry_count = failed_children.len();
 
     for child in &failed_children {
-        // SHA1 校验失败的子任务：删除已下载文件和 .oofp，强制从头下载
+        // SHA1 校验失败的子任务：删除已下载数据和 .oofp，强制从头下载
         if child.status == "verify_failed" {
             if let Some(ref path) = child.path {
-                let _ = std::fs::remove_file(path);
-                let _ = std::fs::remove_file(format!("{}.oofp", path));
+                remove_partial_download(path);
             }
         }
 
@@ -1959,19 +3138,14 @@ pub async fn download_retry_folder(
 
     let children: Vec<EnqueueRequest> = failed_children
         .into_iter()
-        .map(|child| EnqueueRequest {
-            gid: child.gid,
-            fid: child.fid,
-            name: child.name,
-            pick_code: child.pick_code,
-            size: child.size,
-            save_path: child.path.unwrap_or_default(),
-            expected_sha1: None,
-            parent_gid: child.parent_gid,
-            token: token.clone(),
-            user_agent: user_agent.clone(),
-            split,
-            max_global_connections,
+        .map(|child| {
+            EnqueueRequest::from_store_task(
+                child,
+                token.clone(),
+                user_agent.clone(),
+                split,
+                max_global_connections,
+            )
         })
         .collect();
 
@@ -2015,6 +3189,20 @@ pub fn download_set_speed_limit(bytes_per_sec: u64) {
     info!("[设置限速] 设置为{} 字节/秒", bytes_per_sec);
 }
 
+/// 设置磁盘预留空间（字节）；所在卷剩余空间低于该值时暂停下载，0 表示关闭看门狗。
+#[tauri::command]
+pub fn download_set_disk_reserve(bytes: u64, queue: tauri::State<'_, TaskQueue>) {
+    queue.set_disk_reserve(bytes);
+    info!("[设置磁盘预留] 设置为{} 字节", bytes);
+}
+
+/// 设置新建下载文件的空间预分配策略。
+#[tauri::command]
+pub fn download_set_preallocation(mode: PreallocationMode) {
+    super::writer::set_preallocation_mode(mode);
+    info!("[设置预分配] 设置为 {:?}", mode);
+}
+
 /// 全部暂停。
 ///
 /// 暂停所有运行中任务，并冻结等待队列，避免继续出队。
@@ -2041,6 +3229,22 @@ pub async fn download_resume_all(
         .await
 }
 
+/// 下发当前的 token、UA 和分片设置。
+///
+/// 启动恢复时因保存卷未挂载而停放的任务没有这些参数，卷重新挂载后据此自动继续。
+#[tauri::command]
+pub async fn download_set_launch_params(
+    token: String,
+    user_agent: String,
+    split: u16,
+    max_global_connections: u16,
+    queue: tauri::State<'_, TaskQueue>,
+) -> Result<(), DmError> {
+    queue
+        .set_launch_params(token, user_agent, split, max_global_connections)
+        .await
+}
+
 /// 暂停单个下载任务。
 ///
 /// 活跃任务：发送暂停信号给引擎，引擎停止后释放并发槽位。
@@ -2089,7 +3293,10 @@ pub async fn download_resume_task(
         .await?
         .ok_or_else(|| DmError::NotFound(format!("未找到下载任务 gid={}", gid)))?;
 
-    if task.status != "paused" {
+    if !matches!(
+        task.status.as_str(),
+        "paused" | STATUS_PAUSED_DISK_FULL | STATUS_WAITING_FOR_VOLUME
+    ) {
         return Err(DmError::Internal(format!(
             "下载任务 gid={} 当前状态为 '{}'，只有 paused 才能恢复",
             gid, task.status
@@ -2105,20 +3312,8 @@ pub async fn download_resume_task(
     )
     .await?;
 
-    let req = EnqueueRequest {
-        gid: task.gid,
-        fid: task.fid,
-        name: task.name,
-        pick_code: task.pick_code,
-        size: task.size,
-        save_path: task.path.unwrap_or_default(),
-        expected_sha1: None,
-        parent_gid: task.parent_gid,
-        token,
-        user_agent,
-        split,
-        max_global_connections,
-    };
+    let req =
+        EnqueueRequest::from_store_task(task, token, user_agent, split, max_global_connections);
 
     queue.resume(req).await
 }
@@ -2150,12 +3345,11 @@ pub async fn download_retry_task(
         )));
     }
 
-    // SHA1 校验失败时删除已下载文件和 .oofp，强制从头下载。
+    // SHA1 校验失败时删除已下载数据和 .oofp，强制从头下载。
     // 否则 resume_download 会发现分片全完成，直接再次校验并重复失败。
     if task.status == "verify_failed" {
         if let Some(ref path) = task.path {
-            let _ = std::fs::remove_file(path);
-            let _ = std::fs::remove_file(format!("{}.oofp", path));
+            remove_partial_download(path);
         }
     }
 
@@ -2173,20 +3367,68 @@ pub async fn download_retry_task(
     )
     .await?;
 
-    let req = EnqueueRequest {
-        gid: task.gid,
-        fid: task.fid,
-        name: task.name,
-        pick_code: task.pick_code,
-        size: task.size,
-        save_path: task.path.unwrap_or_default(),
-        expected_sha1: None,
-        parent_gid: task.parent_gid,
-        token,
-        user_agent,
-        split,
-        max_global_connections,
-    };
+    let req =
+        EnqueueRequest::from_store_task(task, token, user_agent, split, max_global_connections);
+
+    queue.retry(req).await
+}
+
+/// 修复 SHA1 校验失败的下载任务。
+///
+/// 保留已下载数据，重新拉取远端内容逐块比对，只重写损坏的块后再次校验。
+/// 没有 `.oofp` 或 `.part`（如走快速通道的小文件）时无从修复，改为重新下载。
+#[tauri::command]
+pub async fn download_repair_task(
+    gid: String,
+    token: String,
+    user_agent: String,
+    split: u16,
+    max_global_connections: u16,
+    queue: tauri::State<'_, TaskQueue>,
+    db: tauri::State<'_, DbHandle>,
+) -> Result<(), DmError> {
+    info!("[修复任务] gid={}", gid);
+
+    let task = db
+        .get_task_by_gid(gid.clone())
+        .await?
+        .ok_or_else(|| DmError::NotFound(format!("未找到下载任务 gid={}", gid)))?;
+
+    if task.status != "verify_failed" {
+        return Err(DmError::Internal(format!(
+            "下载任务 gid={} 当前状态为 '{}'，只有 verify_failed 才能修复",
+            gid, task.status
+        )));
+    }
+    if matches!(
+        DownloadSource::from_db(task.source.as_deref()),
+        DownloadSource::Hls { .. }
+    ) {
+        return Err(DmError::Internal(
+            "HLS 任务不支持修复，请重新下载".to_string(),
+        ));
+    }
+
+    db.update_task(
+        gid.clone(),
+        TaskUpdate {
+            status: Some("waiting".to_string()),
+            error_message: Some(None),
+            error_code: Some(None),
+            download_speed: Some(0),
+            eta: Some(None),
+            ..TaskUpdate::default()
+        },
+    )
+    .await?;
+
+    let mut req =
+        EnqueueRequest::from_store_task(task, token, user_agent, split, max_global_connections);
+    req.repair = std::path::Path::new(&oofp_path(&req.save_path)).exists()
+        && std::path::Path::new(&part_file_path(&req.save_path)).exists();
+    if !req.repair {
+        info!("[修复任务] gid={} 没有可修复的断点数据，改为重新下载", gid);
+    }
 
     queue.retry(req).await
 }
@@ -2196,24 +3438,25 @@ pub async fn download_retry_task(
 /// 应用启动恢复流程。
 ///
 /// 扫描未完成任务、检查 .oofp、修正数据库状态并重建文件夹映射。
+/// 返回需要重新停放的文件任务及停放原因：退出前已停放的任务，以及保存卷断开、等待重新挂载的任务。
 async fn recover_tasks(
     db: &DbHandle,
     progress_file: &ProgressFile,
     folder_aggregator: &FolderAggregator,
     state_sync_notify: &Notify,
-) {
+) -> Vec<(StoreDownloadTask, ParkReason)> {
     // 1. 查询所有可恢复任务，包括文件夹、子任务和单文件任务。
     let tasks = match db.get_recoverable_tasks().await {
         Ok(t) => t,
         Err(e) => {
             error!("[恢复] 查询可恢复任务失败: {e}");
-            return;
+            return Vec::new();
         }
     };
 
     if tasks.is_empty() {
         info!("[恢复] 没有未完成的任务需要恢复");
-        return;
+        return Vec::new();
     }
 
     info!("[恢复] 发现{}个未完成任务需要恢复", tasks.len());
@@ -2223,6 +3466,7 @@ async fn recover_tasks(
 
     // 2. 逐个检查文件任务的 .oofp，并据此修正恢复状态。
     let mut task_statuses: HashMap<String, String> = HashMap::new();
+    let mut parked = Vec::new();
 
     for task in &file_tasks {
         let oofp_ok = task
@@ -2230,8 +3474,34 @@ async fn recover_tasks(
             .as_ref()
             .map(|p| progress_file.load_task(p).is_ok())
             .unwrap_or(false);
+        // .oofp 读不到且保存目录已消失：卷未挂载，而不是断点损坏。
+        let volume_missing = !oofp_ok
+            && task.progress > 0.0
+            && task
+                .path
+                .as_deref()
+                .is_some_and(|p| !save_dir_available(p, task.volume_root.as_deref()));
+
+        // 退出前已停放的任务保持停放，条件恢复后仍自动继续；尚未写入数据的任务没有 .oofp。
+        let parked_reason = match task.status.as_str() {
+            STATUS_PAUSED_DISK_FULL => Some(ParkReason::DiskFull),
+            STATUS_WAITING_FOR_VOLUME => Some(ParkReason::VolumeUnavailable),
+            _ => None,
+        };
+        let resumable = oofp_ok || (parked_reason.is_some() && task.progress <= 0.0);
 
-        let new_status = if oofp_ok { "paused" } else { "error" };
+        let new_status = match parked_reason {
+            Some(reason) if resumable => {
+                parked.push((task.clone(), reason));
+                reason.status()
+            }
+            None if resumable => "paused",
+            _ if volume_missing => {
+                parked.push((task.clone(), ParkReason::VolumeUnavailable));
+                STATUS_WAITING_FOR_VOLUME
+            }
+            _ => "error",
+        };
         task_statuses.insert(task.gid.clone(), new_status.to_string());
 
         if let Err(e) = db
@@ -2324,5 +3594,9 @@ async fn recover_tasks(
     let file_count = file_tasks.len();
     let folder_count = folder_tasks.len();
     let error_count = task_statuses.values().filter(|s| *s == "error").count();
-    info!("[恢复] 完成: {file_count}个文件任务 ({error_count}个错误), {folder_count}个文件夹任务");
+    info!(
+        "[恢复] 完成: {file_count}个文件任务 ({error_count}个错误, {}个停放), {folder_count}个文件夹任务",
+        parked.len()
+    );
+    parked
 }
@@ -0,0 +1,309 @@
+//! SHA1 校验失败后的分片级修复。
+//!
+//! 校验失败时 `.part` 与 `.oofp` 会保留下来。修复模式按分片并发重新拉取远端数据，
+//! 以固定大小的块与本地字节比对，只重写不一致的块，最后重新校验 SHA1 并提交。
+//! 没有远端分块哈希可用，网络流量仍是整个文件；但本地数据不会被清空，
+//! 写盘量只有损坏的块，修复中途失败也不会比修复前更糟。
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use futures_util::StreamExt;
+use log::{info, warn};
+use reqwest::header::HeaderMap;
+use tauri::AppHandle;
+use tokio::sync::{Semaphore, watch};
+use tokio::task::JoinSet;
+use tokio::time::{Duration, interval};
+
+use super::events::{
+    DownloadTaskEvent, ProgressItem, ProgressRegistry, SpeedCalculator, emit_task_status,
+};
+use super::http::{DownloadSignal, commit_download, detect_range_support};
+use super::persistence::{ProgressFile, part_file_path};
+use super::segment::compute_segments;
+use super::throttle::get_throttle;
+use super::types::{DownloadError, Segment, TaskAbortReason, TaskStatus};
+use super::volume;
+use super::writer::FileWriter;
+
+/// 比对块大小。块越小，单个翻转字节导致的重写量越小，但读盘次数越多。
+const REPAIR_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// 修复过程中的统计，跨分片共享。
+#[derive(Default)]
+struct RepairStats {
+    checked_bytes: AtomicU64,
+    repaired_blocks: AtomicU64,
+    repaired_bytes: AtomicU64,
+}
+
+/// 修复校验失败的下载：逐块比对并重写损坏区域，校验通过后重命名为最终文件。
+pub async fn repair_download(
+    client: &reqwest::Client,
+    task_id: &str,
+    url: &str,
+    save_path: &str,
+    headers: &HeaderMap,
+    split: u16,
+    db: &Arc<ProgressFile>,
+    app: &AppHandle,
+    signal_rx: watch::Receiver<DownloadSignal>,
+    segment_semaphore: Arc<Semaphore>,
+    progress_registry: Arc<ProgressRegistry>,
+) -> Result<(), DownloadError> {
+    let task_meta = db.load_task(save_path)?;
+    if task_meta.expected_sha1.is_none() {
+        return Err(DownloadError::VerificationFailed(
+            "缺少服务端 SHA1，无法修复".to_string(),
+        ));
+    }
+    let part_path = part_file_path(save_path);
+    if !std::path::Path::new(&part_path).exists() {
+        return Err(DownloadError::FileNotFound(format!(
+            "Download file missing: {}",
+            part_path
+        )));
+    }
+
+    let range_info =
+        detect_range_support(client, url, headers, !task_meta.pick_code.is_empty()).await?;
+    if !range_info.supports_range {
+        return Err(DownloadError::VerificationFailed(
+            "服务器不支持分段请求，无法修复".to_string(),
+        ));
+    }
+    if let Some(content_length) = range_info.content_length
+        && content_length != task_meta.file_size
+    {
+        return Err(DownloadError::VerificationFailed(format!(
+            "远端文件大小已变化（{} → {}），请重新下载",
+            task_meta.file_size, content_length
+        )));
+    }
+
+    info!(
+        "[修复][{}] 开始修复 文件={} 大小={:.1}MB",
+        task_id,
+        task_meta.file_name,
+        task_meta.file_size as f64 / 1024.0 / 1024.0
+    );
+    db.update_task_status(task_id, "active")?;
+    emit_task_status(
+        app,
+        &DownloadTaskEvent {
+            task_id: task_id.to_string(),
+            status: TaskStatus::Active,
+        },
+    );
+
+    let task_start_time = std::time::Instant::now();
+    let writer = FileWriter::open(&part_path)?;
+    let stats = Arc::new(RepairStats::default());
+    let reporter = spawn_progress_reporter(
+        task_id.to_string(),
+        task_meta.file_name.clone(),
+        task_meta.file_size,
+        stats.clone(),
+        progress_registry.clone(),
+    );
+
+    let mut join_set = JoinSet::new();
+    for range in compute_segments(task_meta.file_size, split) {
+        let client = client.clone();
+        let task_id = task_id.to_string();
+        let url = url.to_string();
+        let headers = headers.clone();
+        let writer = writer.clone();
+        let stats = stats.clone();
+        let signal_rx = signal_rx.clone();
+        let semaphore = segment_semaphore.clone();
+        join_set.spawn(async move {
+            let _permit = semaphore
+                .acquire_owned()
+                .await
+                .map_err(|_| DownloadError::TaskAborted(TaskAbortReason::SemaphoreClosed))?;
+            repair_range(
+                &client, &task_id, &url, &headers, &range, &writer, &stats, signal_rx,
+            )
+            .await
+        });
+    }
+
+    let mut result = Ok(());
+    while let Some(joined) = join_set.join_next().await {
+        let outcome = joined
+            .map_err(|e| DownloadError::JoinError(e.to_string()))
+            .and_then(|r| r);
+        if let Err(e) = outcome {
+            join_set.abort_all();
+            result = Err(e);
+            break;
+        }
+    }
+    reporter.abort();
+    progress_registry.remove(task_id);
+    if let Err(e) = result {
+        warn!("[修复][{}] 修复中断: {}", task_id, e);
+        let status = if e.is_user_abort() {
+            "paused"
+        } else {
+            "verify_failed"
+        };
+        let _ = db.update_task_status(task_id, status);
+        return Err(e);
+    }
+    writer.sync_data()?;
+    drop(writer);
+
+    info!(
+        "[修复][{}] 比对完成 重写{}块 共{:.1}MB",
+        task_id,
+        stats.repaired_blocks.load(Ordering::Relaxed),
+        stats.repaired_bytes.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0
+    );
+    commit_download(
+        db,
+        task_id,
+        &task_meta.file_name,
+        task_meta.file_size,
+        save_path,
+        task_meta.expected_sha1.as_deref(),
+        task_start_time,
+        "repair",
+    )
+    .await
+}
+
+/// 拉取单个分片范围，按块与本地数据比对并重写不一致的块。
+async fn repair_range(
+    client: &reqwest::Client,
+    task_id: &str,
+    url: &str,
+    headers: &HeaderMap,
+    range: &Segment,
+    writer: &FileWriter,
+    stats: &RepairStats,
+    mut signal_rx: watch::Receiver<DownloadSignal>,
+) -> Result<(), DownloadError> {
+    let resp = client
+        .get(url)
+        .headers(headers.clone())
+        .header("Range", format!("bytes={}-{}", range.start, range.end))
+        .send()
+        .await?;
+    if resp.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(DownloadError::HttpStatus {
+            status: resp.status().as_u16(),
+            message: format!(
+                "修复请求期望返回 206 Partial Content，实际收到 {}",
+                resp.status().as_u16()
+            ),
+        });
+    }
+
+    let range_len = range.end - range.start + 1;
+    let mut stream = resp.bytes_stream();
+    let mut block: Vec<u8> = Vec::with_capacity(REPAIR_BLOCK_SIZE);
+    let mut block_offset = range.start;
+    let mut received: u64 = 0;
+
+    while let Some(chunk) = stream.next().await {
+        if signal_rx.has_changed().unwrap_or(false) {
+            match *signal_rx.borrow_and_update() {
+                DownloadSignal::Paused => {
+                    return Err(DownloadError::TaskAborted(TaskAbortReason::Paused));
+                }
+                DownloadSignal::Cancelled => {
+                    return Err(DownloadError::TaskAborted(TaskAbortReason::Cancelled));
+                }
+                DownloadSignal::Running => {}
+            }
+        }
+        let chunk = chunk?;
+        get_throttle().consume(chunk.len()).await;
+        volume::consume(task_id, chunk.len()).await;
+        // 防止服务器返回超范围数据覆盖相邻分片
+        let take = (chunk.len() as u64).min(range_len - received) as usize;
+        let mut data = &chunk[..take];
+        received += take as u64;
+        while !data.is_empty() {
+            let room = REPAIR_BLOCK_SIZE - block.len();
+            let n = room.min(data.len());
+            block.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if block.len() == REPAIR_BLOCK_SIZE {
+                block = compare_and_rewrite(writer, block_offset, block, stats).await?;
+                block_offset += REPAIR_BLOCK_SIZE as u64;
+            }
+        }
+        if received == range_len {
+            break;
+        }
+    }
+    if received < range_len {
+        return Err(DownloadError::HttpStatus {
+            status: 206,
+            message: format!("修复数据不完整：期望 {} 字节，收到 {}", range_len, received),
+        });
+    }
+    if !block.is_empty() {
+        compare_and_rewrite(writer, block_offset, block, stats).await?;
+    }
+    Ok(())
+}
+
+/// 与本地同偏移的字节比对，不一致时整块重写；返回清空的缓冲区供复用。
+async fn compare_and_rewrite(
+    writer: &FileWriter,
+    offset: u64,
+    remote: Vec<u8>,
+    stats: &RepairStats,
+) -> Result<Vec<u8>, DownloadError> {
+    let len = remote.len() as u64;
+    let local = writer.read_at(offset, remote.len()).await?;
+    let buffer = if local == remote {
+        let mut remote = remote;
+        remote.clear();
+        remote
+    } else {
+        warn!("[修复] 块不一致，重写 offset={} len={}", offset, len);
+        stats.repaired_blocks.fetch_add(1, Ordering::Relaxed);
+        stats.repaired_bytes.fetch_add(len, Ordering::Relaxed);
+        writer.write_at(offset, remote).await?
+    };
+    stats.checked_bytes.fetch_add(len, Ordering::Relaxed);
+    Ok(buffer)
+}
+
+/// 定期把比对进度写入进度注册表，前端按普通下载进度展示。
+fn spawn_progress_reporter(
+    task_id: String,
+    file_name: String,
+    file_size: u64,
+    stats: Arc<RepairStats>,
+    progress_registry: Arc<ProgressRegistry>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(Duration::from_millis(500));
+        let mut speed_calc = SpeedCalculator::new(0.3);
+        loop {
+            ticker.tick().await;
+            let checked = stats.checked_bytes.load(Ordering::Relaxed);
+            let speed = speed_calc.update(checked);
+            progress_registry.update(ProgressItem {
+                task_id: task_id.clone(),
+                downloaded_bytes: checked,
+                total_bytes: file_size,
+                speed,
+                eta_secs: speed_calc.eta(file_size.saturating_sub(checked)),
+                status: "active".to_string(),
+                name: file_name.clone(),
+                is_folder: false,
+                completed_files: None,
+                failed_files: None,
+                total_files: None,
+            });
+        }
+    })
+}
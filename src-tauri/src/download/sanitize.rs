@@ -0,0 +1,247 @@
+//! 跨平台文件名清理。
+//!
+//! 115 上的文件名可以包含 `:`、`?`、`*`、结尾的点、`CON` 之类的保留名，或超过 255 字节的名称，
+//! 在 Windows 以及 Linux 上的 exFAT/NTFS 卷中无法创建。拼接保存路径前按目标文件系统逐级清理，
+//! 清理前的原始路径记录在任务的 `original_path` 中，供用户查看哪些文件被改了名。
+
+use std::path::Path;
+
+use serde::Serialize;
+
+/// 单个路径分量的长度上限 — 常见文件系统（ext4、NTFS、exFAT、APFS）均为 255。
+pub const MAX_NAME_LEN: usize = 255;
+
+/// 截断名称时预留的长度：下载期间会在文件旁创建 `.part`、`.oofp`、`.oofdedup`、`.hls`
+/// 等附属文件，冲突改名还会追加 ` (n)`。按最长的 `.oofdedup`（9）加 ` (9999)`（7）预留。
+pub const SIDECAR_RESERVE: usize = 16;
+
+/// Windows 保留的设备名，带扩展名同样不可用（如 `CON.txt`）。
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// 按 Windows 命名规则处理的文件系统类型（Linux `/proc/self/mounts` 中的名称）。
+/// `fuseblk` 通常是 ntfs-3g 或 exfat-fuse。
+#[cfg(target_os = "linux")]
+const WINDOWS_FILESYSTEMS: &[&str] = &["vfat", "msdos", "exfat", "ntfs", "ntfs3", "fuseblk"];
+
+/// 保留扩展名时允许的最长扩展名，更长的视为文件名的一部分一起截断。
+const MAX_KEPT_EXTENSION: usize = 16;
+
+/// 目标文件系统的命名规则。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilenameRules {
+    /// 只禁止 NUL。
+    Posix,
+    /// 禁止 `<>:"|?*` 与控制字符、结尾的点和空格以及设备保留名。
+    Windows,
+}
+
+impl FilenameRules {
+    /// 按保存目录所在卷推断命名规则：Windows 上始终使用 Windows 规则，
+    /// Linux 上的 FAT/exFAT/NTFS 卷同样使用 Windows 规则。
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub fn for_dir(dir: &str) -> Self {
+        if cfg!(windows) {
+            return Self::Windows;
+        }
+        #[cfg(target_os = "linux")]
+        if mount_fs_type(dir).is_some_and(|fs| WINDOWS_FILESYSTEMS.contains(&fs.as_str())) {
+            return Self::Windows;
+        }
+        Self::Posix
+    }
+
+    fn is_invalid_char(self, c: char) -> bool {
+        match self {
+            Self::Posix => c == '\0',
+            Self::Windows => matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*') || c.is_control(),
+        }
+    }
+}
+
+/// 路径分量不满足命名规则的原因。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PathIssueKind {
+    /// 某一级文件/目录名过长。
+    NameTooLong,
+    /// 完整路径过长。
+    PathTooLong,
+    /// 含有目标文件系统不允许的字符。
+    InvalidCharacter,
+    /// 使用了设备保留名或以点、空格结尾。
+    ReservedName,
+}
+
+/// Linux 上查找目录所在挂载点的文件系统类型。
+#[cfg(target_os = "linux")]
+fn mount_fs_type(dir: &str) -> Option<String> {
+    let dir = Path::new(dir)
+        .ancestors()
+        .find(|d| d.is_dir())?
+        .canonicalize()
+        .ok()?;
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let _device = fields.next()?;
+            // 挂载点中的空格被转义为 \040
+            let mount_point = fields.next()?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            Some((mount_point, fs_type.to_string()))
+        })
+        .filter(|(mount_point, _)| dir.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .map(|(_, fs_type)| fs_type)
+}
+
+/// 路径长度 — Windows 按 UTF-16 单元计，其他系统按字节计（内核 NAME_MAX 按字节限制）。
+pub fn name_len(name: &str) -> usize {
+    if cfg!(windows) {
+        name.encode_utf16().count()
+    } else {
+        name.len()
+    }
+}
+
+/// 是否为设备保留名，只看第一个点之前的部分。
+fn is_reserved_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name);
+    WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+/// 检查单个路径分量是否可以按原样创建。
+pub fn component_issue(name: &str, rules: FilenameRules) -> Option<PathIssueKind> {
+    if name_len(name) > MAX_NAME_LEN {
+        return Some(PathIssueKind::NameTooLong);
+    }
+    if name.chars().any(|c| rules.is_invalid_char(c)) {
+        return Some(PathIssueKind::InvalidCharacter);
+    }
+    if rules == FilenameRules::Windows && (name.ends_with(['.', ' ']) || is_reserved_name(name)) {
+        return Some(PathIssueKind::ReservedName);
+    }
+    None
+}
+
+/// 截断过长的名称，尽量保留扩展名，并为附属文件后缀留出 [`SIDECAR_RESERVE`]。
+fn truncate_name(name: &mut String) {
+    let limit = MAX_NAME_LEN - SIDECAR_RESERVE;
+    if name_len(name) <= limit {
+        return;
+    }
+    let ext = match name.rfind('.') {
+        Some(dot) if dot > 0 && name.len() - dot <= MAX_KEPT_EXTENSION => name.split_off(dot),
+        _ => String::new(),
+    };
+    let budget = limit - name_len(&ext);
+    while name_len(name) > budget {
+        name.pop();
+    }
+    name.push_str(&ext);
+}
+
+/// 按命名规则清理单个路径分量：非法字符替换为 `_`，去掉结尾的点和空格，
+/// 保留名后追加 `_`，过长时截断。
+pub fn sanitize_component(name: &str, rules: FilenameRules) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| if rules.is_invalid_char(c) { '_' } else { c })
+        .collect();
+    truncate_name(&mut out);
+    if rules == FilenameRules::Windows {
+        out.truncate(out.trim_end_matches(['.', ' ']).len());
+        if is_reserved_name(&out) {
+            let stem_len = out.find('.').unwrap_or(out.len());
+            out.insert(stem_len, '_');
+            truncate_name(&mut out);
+        }
+    }
+    if out.is_empty() || out == "." || out == ".." {
+        out = "_".to_string();
+    }
+    out
+}
+
+/// 逐级清理相对路径，统一以 `/` 连接。
+pub fn sanitize_relative_path(path: &str, rules: FilenameRules) -> String {
+    path.split(['/', '\\'])
+        .filter(|name| !name.is_empty())
+        .map(|name| sanitize_component(name, rules))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 清理完整保存路径的文件名部分，目录部分由用户选择，保持不变。
+pub fn sanitize_file_name(save_path: &str, rules: FilenameRules) -> String {
+    let name_start = save_path.rfind(['/', '\\']).map(|pos| pos + 1).unwrap_or(0);
+    let (dir, name) = save_path.split_at(name_start);
+    if name.is_empty() {
+        return save_path.to_string();
+    }
+    format!("{}{}", dir, sanitize_component(name, rules))
+}
+
+/// 按保存路径的父目录推断规则并清理文件名。
+pub fn sanitize_save_path(save_path: &str) -> String {
+    let rules = Path::new(save_path)
+        .parent()
+        .and_then(Path::to_str)
+        .map(FilenameRules::for_dir)
+        .unwrap_or(FilenameRules::Posix);
+    sanitize_file_name(save_path, rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::conflict::numbered_path;
+    use crate::download::persistence::{oofp_path, part_file_path};
+
+    #[test]
+    fn windows_rules_replace_trim_and_escape_reserved_names() {
+        let w = FilenameRules::Windows;
+        assert_eq!(sanitize_component("a:b?c*.txt", w), "a_b_c_.txt");
+        assert_eq!(sanitize_component("notes. . ", w), "notes");
+        assert_eq!(sanitize_component("CON", w), "CON_");
+        assert_eq!(sanitize_component("con.tar.gz", w), "con_.tar.gz");
+        assert_eq!(sanitize_component("...", w), "_");
+        assert_eq!(
+            sanitize_component("a:b?c*.txt", FilenameRules::Posix),
+            "a:b?c*.txt"
+        );
+        assert_eq!(
+            sanitize_relative_path("dir:1/CON/file?.txt", w),
+            "dir_1/CON_/file_.txt"
+        );
+        assert_eq!(sanitize_file_name("/d/x/a|b.mp4", w), "/d/x/a_b.mp4");
+    }
+
+    #[test]
+    fn long_names_are_truncated_keeping_extension() {
+        let long = format!("{}.mkv", "a".repeat(300));
+        let out = sanitize_component(&long, FilenameRules::Posix);
+        assert_eq!(name_len(&out), MAX_NAME_LEN - SIDECAR_RESERVE);
+        assert!(out.ends_with(".mkv"));
+        // 附属文件和冲突改名后的名称同样不能超限
+        let renamed = numbered_path(&out, 9999);
+        for name in [
+            out.clone(),
+            part_file_path(&out),
+            oofp_path(&out),
+            format!("{}.oofdedup", renamed),
+        ] {
+            assert_eq!(component_issue(&name, FilenameRules::Posix), None);
+        }
+        assert_eq!(
+            component_issue(&long, FilenameRules::Posix),
+            Some(PathIssueKind::NameTooLong)
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! 等待队列的公平调度。
+//!
+//! 每个顶层作业（单文件任务，或文件夹任务及其全部子任务）轮流获得空位：挑选下一个任务时优先
+//! 选择最久未被调度的作业，同一作业内保持入队顺序。这样即使一个文件夹排入上万个子任务，
+//! 之后加入的单文件也只需等待当前的一个空位。
+//!
+//! 文件夹可以设置同时下载的子任务上限（`max_active_children`），保存卷并发限制同样在这里生效。
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use log::{info, warn};
+
+use super::queue::EnqueueRequest;
+use super::store::DbHandle;
+use super::volume::{self, VolumeSlots};
+
+/// 挑选任务时需要的候选信息。
+#[derive(Clone, Copy)]
+struct Candidate<'a> {
+    job: &'a str,
+    parent: Option<&'a str>,
+    save_path: &'a str,
+}
+
+/// 任务所属的顶层作业：文件夹子任务归属父文件夹，单文件自成一个作业。
+fn job_of(req: &EnqueueRequest) -> &str {
+    req.parent_gid.as_deref().unwrap_or(&req.gid)
+}
+
+fn candidate(req: &EnqueueRequest) -> Candidate<'_> {
+    Candidate {
+        job: job_of(req),
+        parent: req.parent_gid.as_deref(),
+        save_path: &req.save_path,
+    }
+}
+
+/// 队列主循环持有的调度状态。
+#[derive(Default)]
+pub struct FairScheduler {
+    /// 作业 → 最近一次被调度的序号，越小越久未被调度。
+    served: HashMap<String, u64>,
+    tick: u64,
+    /// 文件夹 gid → 子任务并发上限，首次遇到时从数据库读取。
+    folder_limits: HashMap<String, Option<usize>>,
+}
+
+impl FairScheduler {
+    /// 更新文件夹的子任务并发上限，0 或空表示不限制。
+    pub fn set_folder_limit(&mut self, parent_gid: &str, max_active_children: Option<usize>) {
+        self.folder_limits.insert(
+            parent_gid.to_string(),
+            max_active_children.filter(|&max| max > 0),
+        );
+    }
+
+    /// 挑选下一个要启动的任务，返回其在等待队列中的序号；所有候选都受限时返回空。
+    pub async fn pick(
+        &mut self,
+        waiting: &VecDeque<EnqueueRequest>,
+        running: &HashMap<String, EnqueueRequest>,
+        db: &DbHandle,
+    ) -> Option<usize> {
+        self.prune(waiting, running);
+        self.load_folder_limits(waiting, db).await;
+
+        let mut active_children: HashMap<&str, usize> = HashMap::new();
+        for req in running.values() {
+            if let Some(parent) = req.parent_gid.as_deref() {
+                *active_children.entry(parent).or_default() += 1;
+            }
+        }
+        let slots = volume::slots(running.values().map(|req| req.save_path.as_str()));
+
+        let index = pick_index(
+            waiting.iter().map(candidate),
+            &active_children,
+            &self.folder_limits,
+            &self.served,
+            &slots,
+        )?;
+        self.tick += 1;
+        self.served
+            .insert(job_of(&waiting[index]).to_string(), self.tick);
+        Some(index)
+    }
+
+    /// 丢弃已不在队列中的作业和文件夹，避免状态无限增长；文件夹再次出现时重新读取上限。
+    fn prune(
+        &mut self,
+        waiting: &VecDeque<EnqueueRequest>,
+        running: &HashMap<String, EnqueueRequest>,
+    ) {
+        let jobs: HashSet<&str> = waiting.iter().chain(running.values()).map(job_of).collect();
+        self.served.retain(|job, _| jobs.contains(job.as_str()));
+        self.folder_limits
+            .retain(|parent, _| jobs.contains(parent.as_str()));
+    }
+
+    async fn load_folder_limits(&mut self, waiting: &VecDeque<EnqueueRequest>, db: &DbHandle) {
+        let missing: HashSet<&str> = waiting
+            .iter()
+            .filter_map(|req| req.parent_gid.as_deref())
+            .filter(|parent| !self.folder_limits.contains_key(*parent))
+            .collect();
+        for parent in missing {
+            let limit = match db.get_task_by_gid(parent.to_string()).await {
+                Ok(task) => task
+                    .and_then(|task| task.max_active_children)
+                    .map(|max| max as usize),
+                Err(e) => {
+                    warn!("[调度] 读取文件夹子任务上限失败 gid={}: {}", parent, e);
+                    None
+                }
+            };
+            if let Some(max) = limit {
+                info!("[调度] 文件夹 gid={} 最多同时下载 {} 个子任务", parent, max);
+            }
+            self.set_folder_limit(parent, limit);
+        }
+    }
+}
+
+/// 在可启动的候选中选出最久未被调度的作业的第一个任务，同等情况下先入队者优先。
+fn pick_index<'a>(
+    candidates: impl Iterator<Item = Candidate<'a>>,
+    active_children: &HashMap<&str, usize>,
+    folder_limits: &HashMap<String, Option<usize>>,
+    served: &HashMap<String, u64>,
+    slots: &VolumeSlots,
+) -> Option<usize> {
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut best: Option<(usize, u64)> = None;
+    for (index, candidate) in candidates.enumerate() {
+        if seen.contains(candidate.job) {
+            continue;
+        }
+        let folder_full = candidate.parent.is_some_and(|parent| {
+            folder_limits
+                .get(parent)
+                .copied()
+                .flatten()
+                .is_some_and(|max| active_children.get(parent).copied().unwrap_or(0) >= max)
+        });
+        if folder_full || !slots.allows(candidate.save_path) {
+            continue;
+        }
+        seen.insert(candidate.job);
+        let last_served = served.get(candidate.job).copied().unwrap_or(0);
+        if best.is_none_or(|(_, best_served)| last_served < best_served) {
+            best = Some((index, last_served));
+        }
+        if last_served == 0 {
+            break;
+        }
+    }
+    best.map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(job: &'static str, parent: bool) -> Candidate<'static> {
+        Candidate {
+            job,
+            parent: parent.then_some(job),
+            save_path: "/data/file",
+        }
+    }
+
+    #[test]
+    fn rotates_between_jobs_and_respects_folder_limit() {
+        let waiting = [
+            file("folder", true),
+            file("folder", true),
+            file("single", false),
+        ];
+        let slots = volume::slots(std::iter::empty());
+        let mut served = HashMap::new();
+        let mut folder_limits = HashMap::new();
+        let pick = |served: &HashMap<String, u64>,
+                    folder_limits: &HashMap<String, Option<usize>>,
+                    active: &HashMap<&str, usize>| {
+            pick_index(
+                waiting.iter().copied(),
+                active,
+                folder_limits,
+                served,
+                &slots,
+            )
+        };
+
+        // 都未被调度过时按入队顺序
+        assert_eq!(pick(&served, &folder_limits, &HashMap::new()), Some(0));
+
+        // 文件夹刚被调度过，后加入的单文件优先
+        served.insert("folder".to_string(), 1);
+        assert_eq!(pick(&served, &folder_limits, &HashMap::new()), Some(2));
+        served.insert("single".to_string(), 2);
+        assert_eq!(pick(&served, &folder_limits, &HashMap::new()), Some(0));
+
+        // 文件夹子任务达到上限后跳过
+        folder_limits.insert("folder".to_string(), Some(1));
+        let active = HashMap::from([("folder", 1)]);
+        assert_eq!(pick(&served, &folder_limits, &active), Some(2));
+    }
+}
@@ -0,0 +1,303 @@
+//! 小文件快速通道。
+//!
+//! 文件夹里成千上万的小文件，耗时主要花在每个任务的固定开销上。低于 [`SMALL_FILE_THRESHOLD`]
+//! 的新任务改走这里：
+//!
+//! - 不发 HEAD 探测 Range，直接整文件 GET，作为单个分片下载；
+//! - 不创建 `.oofp`，暂停或取消时丢弃 `.part`，之后从头下载；
+//! - 边下载边计算 SHA1，不再回读文件校验；
+//! - 不单独写入 active 状态，完成时子任务状态与文件夹计数在同一事务中更新；
+//! - 响应体读完后连接回到共享客户端的连接池，后续任务直接复用。
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use futures_util::StreamExt;
+use log::{debug, info, warn};
+use reqwest::header::HeaderMap;
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+
+use super::events::{ProgressItem, ProgressRegistry};
+use super::http::{
+    DownloadSignal, MAX_SEGMENT_RETRIES, RETRY_BASE_DELAY_MS, is_retryable_error, is_url_expired,
+};
+use super::persistence::part_file_path;
+use super::queue::EnqueueRequest;
+use super::throttle::get_throttle;
+use super::types::{DownloadError, DownloadSource, TaskAbortReason};
+use super::volume;
+use super::writer::FileWriter;
+
+/// 走快速通道的文件大小上限。
+pub const SMALL_FILE_THRESHOLD: i64 = 4 * 1024 * 1024;
+
+/// 进度快照的最短更新间隔。
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 任务是否适合走快速通道（调用方还需确认没有可续传的 `.oofp`）。
+pub fn qualifies(req: &EnqueueRequest) -> bool {
+    !req.repair
+        && matches!(
+            req.source,
+            DownloadSource::Pan115 | DownloadSource::Url { .. }
+        )
+        && (0..=SMALL_FILE_THRESHOLD).contains(&req.size)
+}
+
+/// 下载中的小文件。
+pub struct SmallFile<'a> {
+    pub task_id: &'a str,
+    pub file_name: &'a str,
+    pub size: u64,
+    pub save_path: &'a str,
+    pub expected_sha1: Option<&'a str>,
+    /// 地址失效后能否换取新地址；直链任务不能。
+    pub refreshable: bool,
+}
+
+/// 以单个 GET 下载小文件，校验通过后改名为最终文件。
+///
+/// 下载地址失效时请求刷新并等待新地址，网络错误和 5xx 按分片的退避策略重试。
+pub async fn download_small_file(
+    client: &reqwest::Client,
+    file: &SmallFile<'_>,
+    headers: &HeaderMap,
+    progress_registry: &ProgressRegistry,
+    mut signal_rx: watch::Receiver<DownloadSignal>,
+    mut url_rx: watch::Receiver<String>,
+    url_refresh_requested: &AtomicBool,
+) -> Result<(), DownloadError> {
+    let part_path = part_file_path(file.save_path);
+    let start = Instant::now();
+    let mut retry_count = 0;
+    let result = loop {
+        let url = url_rx.borrow_and_update().clone();
+        let err = match fetch(
+            client,
+            &url,
+            file,
+            headers,
+            progress_registry,
+            &mut signal_rx,
+            &part_path,
+        )
+        .await
+        {
+            Ok(sha1) => break Ok(sha1),
+            Err(e) => e,
+        };
+        if retry_count >= MAX_SEGMENT_RETRIES {
+            break Err(err);
+        }
+        retry_count += 1;
+        match err {
+            DownloadError::UrlExpired { status, .. } if file.refreshable => {
+                debug!(
+                    "[小文件][{}] 下载地址失效 HTTP {}，等待刷新",
+                    file.task_id, status
+                );
+                url_refresh_requested.store(true, Ordering::SeqCst);
+                if let Err(e) = wait_for_new_url(&mut url_rx, &mut signal_rx).await {
+                    break Err(e);
+                }
+            }
+            e if is_retryable_error(&e) => {
+                let delay = RETRY_BASE_DELAY_MS * 2u64.pow(retry_count - 1);
+                warn!(
+                    "[小文件][{}] 下载失败，{}ms 后重试#{}: {}",
+                    file.task_id, delay, retry_count, e
+                );
+                tokio::select! {
+                    biased;
+                    _ = signal_rx.changed() => {
+                        if let Some(reason) = abort_reason(&signal_rx) {
+                            break Err(DownloadError::TaskAborted(reason));
+                        }
+                    }
+                    _ = tokio::time::sleep(Duration::from_millis(delay)) => {}
+                }
+            }
+            e => break Err(e),
+        }
+    };
+    progress_registry.remove(file.task_id);
+
+    let sha1 = match result {
+        Ok(sha1) => sha1,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return Err(e);
+        }
+    };
+    if let Some(expected) = file.expected_sha1
+        && !sha1.eq_ignore_ascii_case(expected)
+    {
+        let _ = tokio::fs::remove_file(&part_path).await;
+        return Err(DownloadError::VerificationFailed(
+            "SHA1 与服务端返回值不一致".to_string(),
+        ));
+    }
+    tokio::fs::rename(&part_path, file.save_path).await?;
+    info!(
+        "[小文件][{}] 下载完成 文件={} 大小={}KB 耗时={}ms",
+        file.task_id,
+        file.file_name,
+        file.size / 1024,
+        start.elapsed().as_millis()
+    );
+    Ok(())
+}
+
+/// 暂停或取消信号对应的中止原因。
+fn abort_reason(signal_rx: &watch::Receiver<DownloadSignal>) -> Option<TaskAbortReason> {
+    match *signal_rx.borrow() {
+        DownloadSignal::Running => None,
+        DownloadSignal::Paused => Some(TaskAbortReason::Paused),
+        DownloadSignal::Cancelled => Some(TaskAbortReason::Cancelled),
+    }
+}
+
+/// 等待 URL 监控任务送来新地址，期间收到暂停或取消则中止。
+async fn wait_for_new_url(
+    url_rx: &mut watch::Receiver<String>,
+    signal_rx: &mut watch::Receiver<DownloadSignal>,
+) -> Result<(), DownloadError> {
+    loop {
+        tokio::select! {
+            biased;
+            result = signal_rx.changed() => {
+                if result.is_err() {
+                    return Err(DownloadError::TaskAborted(TaskAbortReason::SignalChannelClosed));
+                }
+                if let Some(reason) = abort_reason(signal_rx) {
+                    return Err(DownloadError::TaskAborted(reason));
+                }
+            }
+            result = url_rx.changed() => {
+                return result.map_err(|_| {
+                    DownloadError::TaskAborted(TaskAbortReason::SignalChannelClosed)
+                });
+            }
+        }
+    }
+}
+
+/// 单次整文件 GET，写入 `.part` 并返回内容的 SHA1（大写十六进制）。
+async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+    file: &SmallFile<'_>,
+    headers: &HeaderMap,
+    progress_registry: &ProgressRegistry,
+    signal_rx: &mut watch::Receiver<DownloadSignal>,
+    part_path: &str,
+) -> Result<String, DownloadError> {
+    let resp = client.get(url).headers(headers.clone()).send().await?;
+    let status = resp.status().as_u16();
+    if is_url_expired(status) {
+        return Err(DownloadError::UrlExpired {
+            status,
+            message: format!("下载地址已失效（HTTP {}）", status),
+        });
+    }
+    if !resp.status().is_success() {
+        return Err(DownloadError::HttpStatus {
+            status,
+            message: format!("小文件下载失败（HTTP {}）", status),
+        });
+    }
+    let total = resp.content_length().unwrap_or(file.size);
+
+    if let Some(parent) = std::path::Path::new(part_path).parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    FileWriter::check_disk_space(part_path, total)?;
+    let mut writer = BufWriter::new(tokio::fs::File::create(part_path).await?);
+    let mut hasher = Sha1::new();
+    let mut stream = resp.bytes_stream();
+    let mut downloaded: u64 = 0;
+    let started = Instant::now();
+    let mut last_report = started;
+
+    while let Some(chunk) = stream.next().await {
+        if signal_rx.has_changed().unwrap_or(false) {
+            signal_rx.borrow_and_update();
+            if let Some(reason) = abort_reason(signal_rx) {
+                return Err(DownloadError::TaskAborted(reason));
+            }
+        }
+        let chunk = chunk?;
+        get_throttle().consume(chunk.len()).await;
+        volume::consume(file.task_id, chunk.len()).await;
+        hasher.update(&chunk);
+        writer.write_all(&chunk).await?;
+        downloaded += chunk.len() as u64;
+
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            last_report = Instant::now();
+            let speed = downloaded as f64 / started.elapsed().as_secs_f64();
+            progress_registry.update(ProgressItem {
+                task_id: file.task_id.to_string(),
+                downloaded_bytes: downloaded,
+                total_bytes: total,
+                speed,
+                eta_secs: (speed > 0.0).then(|| total.saturating_sub(downloaded) as f64 / speed),
+                status: "active".to_string(),
+                name: file.file_name.to_string(),
+                is_folder: false,
+                completed_files: None,
+                failed_files: None,
+                total_files: None,
+            });
+        }
+    }
+    writer.flush().await?;
+    writer.get_ref().sync_data().await?;
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_new_small_downloads_qualify() {
+        let req = |size: i64, source: DownloadSource, repair: bool| EnqueueRequest {
+            gid: "g".into(),
+            fid: String::new(),
+            name: "a.txt".into(),
+            pick_code: String::new(),
+            size,
+            save_path: "/tmp/a.txt".into(),
+            expected_sha1: None,
+            parent_gid: None,
+            token: String::new(),
+            user_agent: String::new(),
+            split: 4,
+            max_global_connections: 16,
+            source,
+            repair,
+            remote_times: Default::default(),
+            volume_root: None,
+        };
+        assert!(qualifies(&req(1024, DownloadSource::Pan115, false)));
+        assert!(qualifies(&req(
+            SMALL_FILE_THRESHOLD,
+            DownloadSource::Pan115,
+            false
+        )));
+        assert!(!qualifies(&req(
+            SMALL_FILE_THRESHOLD + 1,
+            DownloadSource::Pan115,
+            false
+        )));
+        assert!(!qualifies(&req(1024, DownloadSource::Pan115, true)));
+    }
+}
@@ -61,6 +61,59 @@ pub struct DownloadTask {
     pub total_files: Option<i64>,
     pub completed_files: Option<i64>,
     pub failed_files: Option<i64>,
+    /// 下载来源（JSON 编码的 `DownloadSource`）；为空表示普通 115 文件。
+    pub source: Option<String>,
+    /// 按冲突策略跳过下载的原因；为空表示正常下载。
+    pub skip_reason: Option<String>,
+    /// 文件夹任务中因本地已有文件（同步比对一致或冲突策略）而跳过的子任务数，已计入 `completed_files`。
+    pub skipped_files: Option<i64>,
+    /// 清理非法字符前的原始名称（文件夹子任务为相对路径）；为空表示未改名。
+    pub original_path: Option<String>,
+    /// 115 的修改/创建时间（Unix 秒），下载完成后写回本地文件或目录。
+    pub remote_mtime: Option<i64>,
+    pub remote_ctime: Option<i64>,
+    /// 文件夹任务的子目录时间（JSON 编码的 `Vec<FolderDirItem>`）。
+    pub dir_times: Option<String>,
+    /// 自动解压阶段：extracting / extracted / failed；为空表示未解压。
+    pub extract_status: Option<String>,
+    /// 自动解压失败的原因。
+    pub extract_error: Option<String>,
+    /// 复用的本地文件路径；为空表示从网络下载。
+    pub reused_from: Option<String>,
+    /// 文件夹任务同时下载的子任务上限；为空表示只受全局并发限制。
+    pub max_active_children: Option<i64>,
+    /// 开始下载时保存路径所在卷的挂载点，用于判断卷是否已断开。
+    pub volume_root: Option<String>,
+    /// 115 返回的 SHA1，暂停、重试或重启后重新下载时仍按它校验。
+    pub expected_sha1: Option<String>,
+}
+
+/// CDN 主机学到的安全连接数。
+#[derive(Debug, Clone)]
+pub struct HostLimit {
+    pub host: String,
+    pub max_connections: u16,
+    /// 最近一次限流或恢复的时间（Unix 毫秒）。
+    pub updated_at: i64,
+}
+
+/// CDN 主机测得的最佳分片连接数。
+#[derive(Debug, Clone)]
+pub struct HostTuning {
+    pub host: String,
+    pub connections: u16,
+    /// 最近一次调优完成的时间（Unix 毫秒）。
+    pub updated_at: i64,
+}
+
+/// SHA1 索引中的已完成文件。记录时的大小和修改时间用于判断文件是否仍然有效。
+#[derive(Debug, Clone)]
+pub struct IndexedFile {
+    pub sha1: String,
+    pub path: String,
+    pub size: i64,
+    /// 修改时间（Unix 毫秒）。
+    pub mtime: i64,
 }
 
 #[derive(Debug, Clone, Default, serde::Deserialize)]
@@ -95,6 +148,24 @@ pub struct TaskUpdate {
     pub completed_files: Option<Option<i64>>,
     #[serde(default, deserialize_with = "deserialize_double_option")]
     pub failed_files: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_double_option")]
+    pub skipped_files: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_double_option")]
+    pub remote_mtime: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_double_option")]
+    pub remote_ctime: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_double_option")]
+    pub dir_times: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_double_option")]
+    pub extract_status: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_double_option")]
+    pub extract_error: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_double_option")]
+    pub reused_from: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_double_option")]
+    pub max_active_children: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_double_option")]
+    pub volume_root: Option<Option<String>>,
 }
 
 // ==================== 数据库迁移 ====================
@@ -102,7 +173,7 @@ pub struct TaskUpdate {
 // 规则：每个版本对应一个迁移步骤，只在新数据库或低版本时执行。
 
 /// 当前数据库迁移版本（每次新增迁移时递增）
-const DB_VERSION: u32 = 1;
+const DB_VERSION: u32 = 13;
 
 /// 迁移步骤：(版本号, SQL)
 const MIGRATIONS: &[(u32, &str)] = &[
@@ -132,8 +203,67 @@ const MIGRATIONS: &[(u32, &str)] = &[
             failed_files INTEGER
         );",
     ),
-    // v2: 示例 — 新增字段时在此添加
-    // (2, "ALTER TABLE downloads ADD COLUMN retry_count INTEGER NOT NULL DEFAULT 0;"),
+    // v2: 记录任务下载来源（HLS 等非 115 直链任务）
+    (2, "ALTER TABLE downloads ADD COLUMN source TEXT;"),
+    // v3: 记录因文件冲突策略被跳过的原因
+    (3, "ALTER TABLE downloads ADD COLUMN skip_reason TEXT;"),
+    // v4: 文件夹任务记录跳过的子任务数
+    (4, "ALTER TABLE downloads ADD COLUMN skipped_files INTEGER;"),
+    // v5: 记录文件名清理前的原始路径
+    (5, "ALTER TABLE downloads ADD COLUMN original_path TEXT;"),
+    // v6: 记录云端文件时间，下载完成后写回本地
+    (
+        6,
+        "ALTER TABLE downloads ADD COLUMN remote_mtime INTEGER;
+         ALTER TABLE downloads ADD COLUMN remote_ctime INTEGER;
+         ALTER TABLE downloads ADD COLUMN dir_times TEXT;",
+    ),
+    // v7: 记录下载后自动解压的阶段
+    (
+        7,
+        "ALTER TABLE downloads ADD COLUMN extract_status TEXT;
+         ALTER TABLE downloads ADD COLUMN extract_error TEXT;",
+    ),
+    // v8: 按 SHA1 索引已完成的文件，供新任务复用本地副本
+    (
+        8,
+        "ALTER TABLE downloads ADD COLUMN reused_from TEXT;
+         CREATE TABLE IF NOT EXISTS file_index (
+            sha1 TEXT NOT NULL,
+            path TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            mtime INTEGER NOT NULL,
+            indexed_at INTEGER NOT NULL,
+            PRIMARY KEY (sha1, path)
+        );",
+    ),
+    // v9: 文件夹任务的子任务并发上限
+    (
+        9,
+        "ALTER TABLE downloads ADD COLUMN max_active_children INTEGER;",
+    ),
+    // v10: 按 CDN 主机保存学到的限流状态
+    (
+        10,
+        "CREATE TABLE IF NOT EXISTS cdn_host_limits (
+            host TEXT PRIMARY KEY,
+            max_connections INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );",
+    ),
+    // v11: 按 CDN 主机保存测得的最佳分片连接数
+    (
+        11,
+        "CREATE TABLE IF NOT EXISTS cdn_host_tuning (
+            host TEXT PRIMARY KEY,
+            connections INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );",
+    ),
+    // v12: 记录保存卷的挂载点，卸载后残留的挂载点目录不再被当作可用
+    (12, "ALTER TABLE downloads ADD COLUMN volume_root TEXT;"),
+    // v13: 保存预期 SHA1，不写 .oofp 的小文件恢复后也能校验
+    (13, "ALTER TABLE downloads ADD COLUMN expected_sha1 TEXT;"),
 ];
 
 // ==================== Helper Functions ====================
@@ -160,6 +290,19 @@ fn row_to_task(row: &rusqlite::Row) -> Result<DownloadTask, rusqlite::Error> {
         total_files: row.get("total_files")?,
         completed_files: row.get("completed_files")?,
         failed_files: row.get("failed_files")?,
+        source: row.get("source")?,
+        skip_reason: row.get("skip_reason")?,
+        skipped_files: row.get("skipped_files")?,
+        original_path: row.get("original_path")?,
+        remote_mtime: row.get("remote_mtime")?,
+        remote_ctime: row.get("remote_ctime")?,
+        dir_times: row.get("dir_times")?,
+        extract_status: row.get("extract_status")?,
+        extract_error: row.get("extract_error")?,
+        reused_from: row.get("reused_from")?,
+        max_active_children: row.get("max_active_children")?,
+        volume_root: row.get("volume_root")?,
+        expected_sha1: row.get("expected_sha1")?,
     })
 }
 
@@ -236,6 +379,11 @@ enum DbRequest {
         status: String,
         reply: oneshot::Sender<Result<Vec<DownloadTask>, DmError>>,
     },
+    CompleteTasks {
+        tasks: Vec<(String, Option<String>)>,
+        completed_at: i64,
+        reply: oneshot::Sender<Result<(), DmError>>,
+    },
     IncrementFolderCounter {
         gid: String,
         field: String,
@@ -248,6 +396,33 @@ enum DbRequest {
     GetRecoverableTasks {
         reply: oneshot::Sender<Result<Vec<DownloadTask>, DmError>>,
     },
+    IndexFile {
+        file: IndexedFile,
+        reply: oneshot::Sender<Result<(), DmError>>,
+    },
+    FindIndexedFiles {
+        sha1: String,
+        reply: oneshot::Sender<Result<Vec<IndexedFile>, DmError>>,
+    },
+    RemoveIndexedFile {
+        sha1: String,
+        path: String,
+        reply: oneshot::Sender<Result<(), DmError>>,
+    },
+    LoadHostLimits {
+        reply: oneshot::Sender<Result<Vec<HostLimit>, DmError>>,
+    },
+    SaveHostLimits {
+        limits: Vec<HostLimit>,
+        reply: oneshot::Sender<Result<(), DmError>>,
+    },
+    LoadHostTuning {
+        reply: oneshot::Sender<Result<Vec<HostTuning>, DmError>>,
+    },
+    SaveHostTuning {
+        tuning: Vec<HostTuning>,
+        reply: oneshot::Sender<Result<(), DmError>>,
+    },
 }
 
 #[derive(Clone)]
@@ -309,6 +484,13 @@ impl DbHandle {
                         let _ =
                             reply.send(get_child_tasks_by_status_impl(&conn, &parent_gid, &status));
                     }
+                    DbRequest::CompleteTasks {
+                        tasks,
+                        completed_at,
+                        reply,
+                    } => {
+                        let _ = reply.send(complete_tasks_impl(&conn, &tasks, completed_at));
+                    }
                     DbRequest::IncrementFolderCounter {
                         gid,
                         field,
@@ -324,6 +506,27 @@ impl DbHandle {
                     DbRequest::GetRecoverableTasks { reply } => {
                         let _ = reply.send(get_recoverable_tasks_impl(&conn));
                     }
+                    DbRequest::IndexFile { file, reply } => {
+                        let _ = reply.send(index_file_impl(&conn, &file));
+                    }
+                    DbRequest::FindIndexedFiles { sha1, reply } => {
+                        let _ = reply.send(find_indexed_files_impl(&conn, &sha1));
+                    }
+                    DbRequest::RemoveIndexedFile { sha1, path, reply } => {
+                        let _ = reply.send(remove_indexed_file_impl(&conn, &sha1, &path));
+                    }
+                    DbRequest::LoadHostLimits { reply } => {
+                        let _ = reply.send(load_host_limits_impl(&conn));
+                    }
+                    DbRequest::SaveHostLimits { limits, reply } => {
+                        let _ = reply.send(save_host_limits_impl(&conn, &limits));
+                    }
+                    DbRequest::LoadHostTuning { reply } => {
+                        let _ = reply.send(load_host_tuning_impl(&conn));
+                    }
+                    DbRequest::SaveHostTuning { tuning, reply } => {
+                        let _ = reply.send(save_host_tuning_impl(&conn, &tuning));
+                    }
                 }
             }
         });
@@ -406,6 +609,20 @@ impl DbHandle {
         .await
     }
 
+    /// 批量标记任务完成（gid 与父文件夹 gid），父文件夹完成数在同一事务中递增。
+    pub async fn complete_tasks(
+        &self,
+        tasks: Vec<(String, Option<String>)>,
+        completed_at: i64,
+    ) -> Result<(), DmError> {
+        self.send_request(|reply| DbRequest::CompleteTasks {
+            tasks,
+            completed_at,
+            reply,
+        })
+        .await
+    }
+
     pub async fn increment_folder_counter(
         &self,
         gid: String,
@@ -430,6 +647,43 @@ impl DbHandle {
         self.send_request(|reply| DbRequest::GetPausedTopLevelTasks { reply })
             .await
     }
+
+    pub async fn index_file(&self, file: IndexedFile) -> Result<(), DmError> {
+        self.send_request(|reply| DbRequest::IndexFile { file, reply })
+            .await
+    }
+
+    pub async fn find_indexed_files(&self, sha1: String) -> Result<Vec<IndexedFile>, DmError> {
+        self.send_request(|reply| DbRequest::FindIndexedFiles { sha1, reply })
+            .await
+    }
+
+    pub async fn remove_indexed_file(&self, sha1: String, path: String) -> Result<(), DmError> {
+        self.send_request(|reply| DbRequest::RemoveIndexedFile { sha1, path, reply })
+            .await
+    }
+
+    pub async fn load_host_limits(&self) -> Result<Vec<HostLimit>, DmError> {
+        self.send_request(|reply| DbRequest::LoadHostLimits { reply })
+            .await
+    }
+
+    /// 以给定列表整体替换已保存的限流状态。
+    pub async fn save_host_limits(&self, limits: Vec<HostLimit>) -> Result<(), DmError> {
+        self.send_request(|reply| DbRequest::SaveHostLimits { limits, reply })
+            .await
+    }
+
+    pub async fn load_host_tuning(&self) -> Result<Vec<HostTuning>, DmError> {
+        self.send_request(|reply| DbRequest::LoadHostTuning { reply })
+            .await
+    }
+
+    /// 以给定列表整体替换已保存的调优结果。
+    pub async fn save_host_tuning(&self, tuning: Vec<HostTuning>) -> Result<(), DmError> {
+        self.send_request(|reply| DbRequest::SaveHostTuning { tuning, reply })
+            .await
+    }
 }
 
 // ==================== DB Implementation Functions ====================
@@ -440,8 +694,12 @@ fn insert_task_impl(conn: &Connection, task: &DownloadTask) -> Result<(), DmErro
             gid, fid, name, pick_code, size, status, progress, path,
             download_speed, eta, error_message, error_code,
             created_at, completed_at, is_folder, is_collecting,
-            parent_gid, total_files, completed_files, failed_files
-        ) VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11,?12,?13,?14,?15,?16,?17,?18,?19,?20)",
+            parent_gid, total_files, completed_files, failed_files, source, skip_reason,
+            skipped_files, original_path, remote_mtime, remote_ctime, dir_times,
+            extract_status, extract_error, reused_from, max_active_children, volume_root,
+            expected_sha1
+        ) VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11,?12,?13,?14,?15,?16,?17,?18,?19,?20,?21,?22,?23,?24,
+            ?25,?26,?27,?28,?29,?30,?31,?32,?33)",
         rusqlite::params![
             task.gid,
             task.fid,
@@ -463,6 +721,19 @@ fn insert_task_impl(conn: &Connection, task: &DownloadTask) -> Result<(), DmErro
             task.total_files,
             task.completed_files,
             task.failed_files,
+            task.source,
+            task.skip_reason,
+            task.skipped_files,
+            task.original_path,
+            task.remote_mtime,
+            task.remote_ctime,
+            task.dir_times,
+            task.extract_status,
+            task.extract_error,
+            task.reused_from,
+            task.max_active_children,
+            task.volume_root,
+            task.expected_sha1,
         ],
     )?;
     Ok(())
@@ -531,6 +802,15 @@ fn update_task_impl(conn: &Connection, gid: &str, updates: &TaskUpdate) -> Resul
     add_nullable_field!(updates.total_files, "total_files");
     add_nullable_field!(updates.completed_files, "completed_files");
     add_nullable_field!(updates.failed_files, "failed_files");
+    add_nullable_field!(updates.skipped_files, "skipped_files");
+    add_nullable_field!(updates.remote_mtime, "remote_mtime");
+    add_nullable_field!(updates.remote_ctime, "remote_ctime");
+    add_nullable_field!(updates.dir_times, "dir_times");
+    add_nullable_field!(updates.extract_status, "extract_status");
+    add_nullable_field!(updates.extract_error, "extract_error");
+    add_nullable_field!(updates.reused_from, "reused_from");
+    add_nullable_field!(updates.max_active_children, "max_active_children");
+    add_nullable_field!(updates.volume_root, "volume_root");
 
     if set_clauses.is_empty() {
         return Ok(());
@@ -616,6 +896,99 @@ fn get_task_by_gid_impl(conn: &Connection, gid: &str) -> Result<Option<DownloadT
     }
 }
 
+fn index_file_impl(conn: &Connection, file: &IndexedFile) -> Result<(), DmError> {
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    conn.execute(
+        "INSERT OR REPLACE INTO file_index (sha1, path, size, mtime, indexed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![file.sha1, file.path, file.size, file.mtime, now_ms],
+    )?;
+    Ok(())
+}
+
+/// 按最近记录优先返回同一 SHA1 的全部文件。
+fn find_indexed_files_impl(conn: &Connection, sha1: &str) -> Result<Vec<IndexedFile>, DmError> {
+    let mut stmt = conn.prepare(
+        "SELECT sha1, path, size, mtime FROM file_index WHERE sha1 = ?1 ORDER BY indexed_at DESC",
+    )?;
+    let files = stmt
+        .query_map(rusqlite::params![sha1], |row| {
+            Ok(IndexedFile {
+                sha1: row.get(0)?,
+                path: row.get(1)?,
+                size: row.get(2)?,
+                mtime: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(files)
+}
+
+fn remove_indexed_file_impl(conn: &Connection, sha1: &str, path: &str) -> Result<(), DmError> {
+    conn.execute(
+        "DELETE FROM file_index WHERE sha1 = ?1 AND path = ?2",
+        rusqlite::params![sha1, path],
+    )?;
+    Ok(())
+}
+
+fn load_host_limits_impl(conn: &Connection) -> Result<Vec<HostLimit>, DmError> {
+    let mut stmt = conn.prepare("SELECT host, max_connections, updated_at FROM cdn_host_limits")?;
+    let limits = stmt
+        .query_map([], |row| {
+            Ok(HostLimit {
+                host: row.get(0)?,
+                max_connections: row.get(1)?,
+                updated_at: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(limits)
+}
+
+fn save_host_limits_impl(conn: &Connection, limits: &[HostLimit]) -> Result<(), DmError> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM cdn_host_limits", [])?;
+    for limit in limits {
+        tx.execute(
+            "INSERT INTO cdn_host_limits (host, max_connections, updated_at) VALUES (?1, ?2, ?3)",
+            rusqlite::params![limit.host, limit.max_connections, limit.updated_at],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn load_host_tuning_impl(conn: &Connection) -> Result<Vec<HostTuning>, DmError> {
+    let mut stmt = conn.prepare("SELECT host, connections, updated_at FROM cdn_host_tuning")?;
+    let tuning = stmt
+        .query_map([], |row| {
+            Ok(HostTuning {
+                host: row.get(0)?,
+                connections: row.get(1)?,
+                updated_at: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(tuning)
+}
+
+fn save_host_tuning_impl(conn: &Connection, tuning: &[HostTuning]) -> Result<(), DmError> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM cdn_host_tuning", [])?;
+    for entry in tuning {
+        tx.execute(
+            "INSERT INTO cdn_host_tuning (host, connections, updated_at) VALUES (?1, ?2, ?3)",
+            rusqlite::params![entry.host, entry.connections, entry.updated_at],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
 fn get_child_tasks_impl(conn: &Connection, parent_gid: &str) -> Result<Vec<DownloadTask>, DmError> {
     let mut stmt =
         conn.prepare("SELECT * FROM downloads WHERE parent_gid = ?1 ORDER BY created_at ASC")?;
@@ -641,6 +1014,34 @@ fn get_child_tasks_by_status_impl(
     Ok(tasks)
 }
 
+fn complete_tasks_impl(
+    conn: &Connection,
+    tasks: &[(String, Option<String>)],
+    completed_at: i64,
+) -> Result<(), DmError> {
+    let tx = conn.unchecked_transaction()?;
+    for (gid, parent_gid) in tasks {
+        let rows = tx.execute(
+            "UPDATE downloads SET status = 'complete', completed_at = ?1, progress = 100.0,
+                download_speed = 0, eta = NULL WHERE gid = ?2",
+            rusqlite::params![completed_at, gid],
+        )?;
+        // 任务已被删除时不再计入父文件夹；文件夹可能已被取消删除，此时只更新子任务本身。
+        if rows == 0 {
+            log::warn!("[数据库] 完成的任务已不存在 gid={}", gid);
+            continue;
+        }
+        if let Some(parent_gid) = parent_gid {
+            tx.execute(
+                "UPDATE downloads SET completed_files = COALESCE(completed_files, 0) + 1 WHERE gid = ?1",
+                rusqlite::params![parent_gid],
+            )?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
 fn increment_folder_counter_impl(
     conn: &Connection,
     gid: &str,
@@ -707,3 +1108,16 @@ pub async fn download_get_top_level_tasks(
 ) -> Result<Vec<DownloadTask>, DmError> {
     db.get_top_level_tasks().await
 }
+
+/// 获取文件夹中因文件名清理而改名的子任务。
+#[tauri::command]
+pub async fn download_get_renamed_children(
+    parent_gid: String,
+    db: tauri::State<'_, DbHandle>,
+) -> Result<Vec<DownloadTask>, DmError> {
+    let children = db.get_child_tasks(parent_gid).await?;
+    Ok(children
+        .into_iter()
+        .filter(|task| task.original_path.is_some())
+        .collect())
+}
@@ -39,6 +39,11 @@ impl TokenBucket {
     /// refill 和 consume 在同一把锁内完成，避免多分片并发重复补充令牌。
     pub async fn consume(&self, bytes: usize) {
         let limit = *SPEED_LIMIT_CHANNEL.1.borrow();
+        self.consume_with_limit(bytes, limit).await;
+    }
+
+    /// 按指定上限消耗令牌 — 供全局以外的限速（如按保存卷）复用同一套令牌桶逻辑
+    pub async fn consume_with_limit(&self, bytes: usize, limit: u64) {
         if limit == 0 {
             return; // 不限速
         }
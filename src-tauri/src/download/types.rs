@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
@@ -17,6 +18,10 @@ pub struct DownloadConfig {
     pub split: u16,
     /// 全局下载速度上限，单位为 bytes/sec；0 表示不限速。
     pub speed_limit: u64,
+    /// 新建下载文件时的空间预分配策略。
+    pub preallocation: PreallocationMode,
+    /// 保存卷的挂载点，写盘失败时据此判断卷是否已断开。
+    pub volume_root: Option<String>,
 }
 
 impl Default for DownloadConfig {
@@ -24,6 +29,93 @@ impl Default for DownloadConfig {
         Self {
             split: DEFAULT_SEGMENT_COUNT, // 16
             speed_limit: 0,
+            preallocation: PreallocationMode::default(),
+            volume_root: None,
+        }
+    }
+}
+
+/// 下载文件空间预分配策略。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PreallocationMode {
+    /// 不预分配，文件随写入增长；适合预分配很慢的网络共享。
+    None,
+    /// `set_len` 设置文件长度；多数 Linux 文件系统上为稀疏文件，并不真正占用空间。
+    #[default]
+    Sparse,
+    /// 通过 `fallocate`/`posix_fallocate` 真正占用磁盘空间，空间不足时立即失败。
+    Full,
+}
+
+/// 下载任务来源。
+///
+/// 持久化到数据库 `source` 列（JSON），恢复或重试任务时据此选择下载引擎。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum DownloadSource {
+    /// 115 网盘文件，下载地址通过 pick_code 换取。
+    #[default]
+    Pan115,
+    /// 115 视频转码后的 HLS 流，落盘为单个 `.ts` 文件。
+    Hls {
+        /// 主播放列表或媒体播放列表地址。
+        playlist_url: String,
+        /// 期望的最大画面高度；为空时选择最高码率。
+        max_height: Option<u32>,
+    },
+    /// 任意 HTTP(S) 直链，使用原生分片引擎下载。没有 pick_code，地址失效后无法刷新。
+    Url {
+        url: String,
+        /// 自定义请求头，覆盖同名的默认请求头。
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        /// `Cookie` 请求头原文。
+        #[serde(default)]
+        cookies: Option<String>,
+        #[serde(default)]
+        referer: Option<String>,
+        /// 请求头或 Cookie 只保存在内存中；重启后凭据已丢失时据此报错，而不是不带凭据重试。
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        credentials_required: bool,
+    },
+}
+
+impl DownloadSource {
+    /// 从数据库 `source` 列解析；为空或无法解析时视为 115 文件。
+    pub fn from_db(value: Option<&str>) -> Self {
+        value
+            .and_then(|raw| serde_json::from_str(raw).ok())
+            .unwrap_or_default()
+    }
+
+    /// 编码为数据库 `source` 列；115 文件保持 NULL，兼容旧记录。
+    ///
+    /// 直链的自定义请求头和 Cookie 可能含登录凭据，不写入数据库，只记录任务需要凭据。
+    pub fn to_db(&self) -> Option<String> {
+        match self {
+            Self::Pan115 => None,
+            Self::Url {
+                url,
+                headers,
+                cookies,
+                referer,
+                credentials_required,
+            } => serde_json::to_string(&Self::Url {
+                url: url.clone(),
+                headers: BTreeMap::new(),
+                cookies: None,
+                referer: referer.clone(),
+                credentials_required: *credentials_required
+                    || !headers.is_empty()
+                    || cookies.is_some(),
+            })
+            .ok(),
+            _ => serde_json::to_string(self).ok(),
         }
     }
 }
@@ -107,8 +199,16 @@ pub enum DownloadError {
     TaskAborted(TaskAbortReason),
     #[error("文件完整性校验失败：{0}")]
     VerificationFailed(String),
+    #[error("保存位置不可用：{0}")]
+    VolumeUnavailable(String),
     #[error("异步任务执行失败：{0}")]
     JoinError(String),
+    #[error("HLS 处理失败：{0}")]
+    Hls(String),
+    #[error("请求头无效：{0}")]
+    InvalidHeader(String),
+    #[error("直链任务的请求头和 Cookie 未保存，应用重启后已丢失，请重新添加任务")]
+    CredentialsLost,
 }
 
 impl DownloadError {
@@ -163,6 +263,10 @@ pub struct RangeInfo {
     pub supports_range: bool,
     /// 服务器返回的 ETag，用于续传前校验文件是否发生变化。
     pub etag: Option<String>,
+    /// 服务器返回的 Last-Modified，ETag 缺失时作为续传校验依据。
+    pub last_modified: Option<String>,
+    /// 服务器返回的 Content-Length；直链任务入队时据此确定文件大小。
+    pub content_length: Option<u64>,
 }
 
 /// 下载任务状态。
@@ -190,6 +294,8 @@ pub struct DownloadTask {
     pub pick_code: String,
     /// HEAD 探测得到的 ETag，用于续传前判断远端文件是否变化。
     pub etag: Option<String>,
+    /// HEAD 探测得到的 Last-Modified，ETag 缺失时用于续传校验。
+    pub last_modified: Option<String>,
     /// 115 接口返回的预期 SHA1，用于下载完成后的完整性校验。
     pub expected_sha1: Option<String>,
     pub segments: Vec<Segment>,
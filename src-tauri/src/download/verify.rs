@@ -0,0 +1,156 @@
+//! 本地文件与云端 SHA1 比对。
+//!
+//! 不下载任何数据，只对本地已有文件计算 SHA1 并与 115 返回的值比较，
+//! 用于确认旧的本地副本是否仍与云端一致。不一致或缺失的文件可选择直接加入下载队列。
+
+use std::path::Path;
+
+use futures_util::{StreamExt, stream};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::conflict::ConflictPolicy;
+use super::events::EventBridge;
+use super::file_times::RemoteTimes;
+use super::http::compute_file_sha1;
+use super::queue::{EnqueueRequest, TaskQueue, enqueue_single};
+use super::store::{DbHandle, DmError};
+use super::types::DownloadSource;
+
+/// 同时计算哈希的文件数 — 机械硬盘上并发过高反而会因寻道变慢。
+pub(super) const VERIFY_CONCURRENCY: usize = 4;
+
+/// 待比对的本地文件及其对应的 115 文件信息。
+///
+/// 文件夹比对由前端遍历 115 目录后按相对路径拼出本地路径，逐项传入。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalVerifyItem {
+    pub path: String,
+    /// 115 接口返回的 SHA1。
+    pub sha1: String,
+    pub fid: String,
+    pub name: String,
+    pub pick_code: String,
+    pub size: i64,
+}
+
+/// 单个文件的比对结论。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LocalVerifyStatus {
+    Match,
+    Mismatch,
+    Missing,
+}
+
+/// 单个文件的比对结果。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalVerifyResult {
+    pub path: String,
+    pub status: LocalVerifyStatus,
+    /// 本地文件 SHA1；文件缺失或读取失败时为空。
+    pub local_sha1: Option<String>,
+    /// 读取本地文件或加入下载队列失败的原因；读取失败时按不一致处理。
+    pub error: Option<String>,
+    /// 按需入队后的下载任务 gid。
+    pub queued_gid: Option<String>,
+}
+
+/// 计算单个本地文件的 SHA1 并与预期值比较。
+async fn verify_one(item: &LocalVerifyItem) -> LocalVerifyResult {
+    let mut result = LocalVerifyResult {
+        path: item.path.clone(),
+        status: LocalVerifyStatus::Missing,
+        local_sha1: None,
+        error: None,
+        queued_gid: None,
+    };
+    if !Path::new(&item.path).is_file() {
+        return result;
+    }
+    match compute_file_sha1(&item.path).await {
+        Ok(sha1) => {
+            result.status = if sha1.eq_ignore_ascii_case(&item.sha1) {
+                LocalVerifyStatus::Match
+            } else {
+                LocalVerifyStatus::Mismatch
+            };
+            result.local_sha1 = Some(sha1);
+        }
+        Err(e) => {
+            warn!("[比对] 读取本地文件失败 {}: {}", item.path, e);
+            result.status = LocalVerifyStatus::Mismatch;
+            result.error = Some(e.to_string());
+        }
+    }
+    result
+}
+
+/// 比对本地文件与 115 文件的 SHA1。
+///
+/// 结果顺序与输入一致。`enqueue_mismatched` 为 true 时，不一致或缺失的文件按覆盖策略
+/// 加入下载队列，下载完成后仍会按预期 SHA1 校验。
+#[tauri::command]
+pub async fn download_verify_local(
+    items: Vec<LocalVerifyItem>,
+    enqueue_mismatched: Option<bool>,
+    token: String,
+    user_agent: String,
+    split: u16,
+    max_global_connections: u16,
+    queue: tauri::State<'_, TaskQueue>,
+    db: tauri::State<'_, DbHandle>,
+    event_bridge: tauri::State<'_, EventBridge>,
+) -> Result<Vec<LocalVerifyResult>, DmError> {
+    info!("[比对] 开始比对{}个本地文件", items.len());
+    let mut results: Vec<LocalVerifyResult> = stream::iter(&items)
+        .map(verify_one)
+        .buffered(VERIFY_CONCURRENCY)
+        .collect()
+        .await;
+
+    if enqueue_mismatched.unwrap_or(false) {
+        for (item, result) in items.iter().zip(results.iter_mut()) {
+            if result.status == LocalVerifyStatus::Match {
+                continue;
+            }
+            let req = EnqueueRequest {
+                gid: uuid::Uuid::new_v4().to_string(),
+                fid: item.fid.clone(),
+                name: item.name.clone(),
+                pick_code: item.pick_code.clone(),
+                size: item.size,
+                save_path: item.path.clone(),
+                expected_sha1: Some(item.sha1.clone()),
+                parent_gid: None,
+                token: token.clone(),
+                user_agent: user_agent.clone(),
+                split,
+                max_global_connections,
+                source: DownloadSource::Pan115,
+                repair: false,
+                remote_times: RemoteTimes::default(),
+                volume_root: None,
+            };
+            // 单个文件入队失败不影响其余文件，失败原因随比对结果返回
+            match enqueue_single(req, ConflictPolicy::Overwrite, &queue, &db, &event_bridge).await {
+                Ok(gid) => result.queued_gid = Some(gid),
+                Err(e) => {
+                    warn!("[比对] 加入下载队列失败 {}: {}", item.path, e);
+                    result.error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    info!(
+        "[比对] 完成: 一致{} 不一致{} 缺失{}",
+        count(LocalVerifyStatus::Match),
+        count(LocalVerifyStatus::Mismatch),
+        count(LocalVerifyStatus::Missing)
+    );
+    Ok(results)
+}
@@ -0,0 +1,204 @@
+//! 按保存卷或根目录限制并发数和写入带宽。
+//!
+//! 限制由设置页通过 `download_set_volume_limits` 下发，每条规则对应一个根目录（如 `D:\` 或
+//! `/mnt/usb`）。任务的保存路径按最长前缀归属到某个根目录，未命中任何规则的任务只受全局限制。
+//!
+//! - 并发：队列挑选下一个任务时跳过所属根目录已满的任务，全局最大并发仍然生效。
+//! - 带宽：同一根目录下的所有任务共享一个令牌桶，与全局限速叠加。
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use super::queue::TaskQueue;
+use super::throttle::TokenBucket;
+
+static STATE: LazyLock<Mutex<VolumeState>> = LazyLock::new(|| Mutex::new(VolumeState::default()));
+
+/// 单个根目录的限制。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeLimit {
+    /// 根目录，保存路径位于其下的任务都受该规则约束。
+    pub root: String,
+    /// 最大同时下载数，0 或空表示不限制。
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+    /// 写入带宽上限（bytes/sec），0 或空表示不限速。
+    #[serde(default)]
+    pub max_bytes_per_sec: Option<u64>,
+}
+
+/// 根目录共享的令牌桶，上限可在运行中调整。
+struct VolumeThrottle {
+    limit: AtomicU64,
+    bucket: TokenBucket,
+}
+
+#[derive(Default)]
+struct VolumeState {
+    limits: Vec<VolumeLimit>,
+    /// 根目录 → 令牌桶；修改设置时保留已有的桶，只更新上限。
+    throttles: HashMap<String, Arc<VolumeThrottle>>,
+    /// 运行中任务 gid → 保存路径，限速时按当前规则归属根目录。
+    tasks: HashMap<String, String>,
+}
+
+/// Windows 路径不区分大小写，统一转为小写再比较。
+fn normalize(path: &str) -> String {
+    if cfg!(windows) {
+        path.to_lowercase()
+    } else {
+        path.to_string()
+    }
+}
+
+/// 找出保存路径所属的规则（最长根目录优先）。
+fn matching_limit<'a>(limits: &'a [VolumeLimit], save_path: &str) -> Option<&'a VolumeLimit> {
+    let path = normalize(save_path);
+    limits
+        .iter()
+        .filter(|limit| Path::new(&path).starts_with(normalize(&limit.root)))
+        .max_by_key(|limit| limit.root.len())
+}
+
+/// 各根目录当前的运行任务数快照，供队列挑选任务时判断是否还有空位。
+pub struct VolumeSlots {
+    limits: Vec<VolumeLimit>,
+    active: HashMap<String, usize>,
+}
+
+impl VolumeSlots {
+    fn new<'a>(limits: Vec<VolumeLimit>, running: impl Iterator<Item = &'a str>) -> Self {
+        let mut active: HashMap<String, usize> = HashMap::new();
+        for path in running {
+            if let Some(limit) = matching_limit(&limits, path) {
+                *active.entry(limit.root.clone()).or_default() += 1;
+            }
+        }
+        Self { limits, active }
+    }
+
+    /// 保存路径所属根目录是否还能再启动一个任务；未受限的路径总是可以。
+    pub fn allows(&self, save_path: &str) -> bool {
+        match matching_limit(&self.limits, save_path) {
+            Some(VolumeLimit {
+                root,
+                max_concurrent: Some(max),
+                ..
+            }) if *max > 0 => self.active.get(root).copied().unwrap_or(0) < *max,
+            _ => true,
+        }
+    }
+}
+
+/// 按当前规则统计运行中任务的保存路径。
+pub fn slots<'a>(running: impl Iterator<Item = &'a str>) -> VolumeSlots {
+    let limits = STATE.lock().unwrap().limits.clone();
+    VolumeSlots::new(limits, running)
+}
+
+/// 任务启动时登记保存路径，之后按所属根目录限速。
+pub fn register_task(gid: &str, save_path: &str) {
+    STATE
+        .lock()
+        .unwrap()
+        .tasks
+        .insert(gid.to_string(), save_path.to_string());
+}
+
+/// 任务结束时取消登记。
+pub fn unregister_task(gid: &str) {
+    STATE.lock().unwrap().tasks.remove(gid);
+}
+
+/// 按任务所属根目录消耗写入带宽令牌，未受限的任务立即返回。
+pub async fn consume(gid: &str, bytes: usize) {
+    let throttle = {
+        let state = STATE.lock().unwrap();
+        state
+            .tasks
+            .get(gid)
+            .and_then(|path| matching_limit(&state.limits, path))
+            .and_then(|limit| state.throttles.get(&limit.root))
+            .cloned()
+    };
+    if let Some(throttle) = throttle {
+        let limit = throttle.limit.load(Ordering::Relaxed);
+        throttle.bucket.consume_with_limit(bytes, limit).await;
+    }
+}
+
+/// 设置按保存卷或根目录的并发与带宽限制。
+#[tauri::command]
+pub fn download_set_volume_limits(limits: Vec<VolumeLimit>, queue: tauri::State<'_, TaskQueue>) {
+    let limits: Vec<VolumeLimit> = limits
+        .into_iter()
+        .filter(|limit| !limit.root.trim().is_empty())
+        .collect();
+    {
+        let mut state = STATE.lock().unwrap();
+        let mut throttles = HashMap::new();
+        for limit in &limits {
+            let throttle = state.throttles.remove(&limit.root).unwrap_or_else(|| {
+                Arc::new(VolumeThrottle {
+                    limit: AtomicU64::new(0),
+                    bucket: TokenBucket::new(),
+                })
+            });
+            throttle
+                .limit
+                .store(limit.max_bytes_per_sec.unwrap_or(0), Ordering::Relaxed);
+            throttles.insert(limit.root.clone(), throttle);
+        }
+        state.throttles = throttles;
+        info!("[设置卷限制] {} 条规则", limits.len());
+        state.limits = limits;
+    }
+    // 并发上限可能放宽，唤醒队列重新挑选任务。
+    queue.wake();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(root: &str, max_concurrent: usize) -> VolumeLimit {
+        VolumeLimit {
+            root: root.into(),
+            max_concurrent: Some(max_concurrent),
+            max_bytes_per_sec: None,
+        }
+    }
+
+    #[test]
+    fn skips_tasks_whose_volume_is_full() {
+        let limits = vec![limit("/mnt/usb", 1), limit("/mnt/usb/fast", 2)];
+
+        // 最长前缀优先，按路径组件匹配
+        assert_eq!(
+            matching_limit(&limits, "/mnt/usb/fast/a.bin").unwrap().root,
+            "/mnt/usb/fast"
+        );
+        assert!(matching_limit(&limits, "/mnt/usb2/a.bin").is_none());
+
+        let slots = VolumeSlots::new(
+            limits.clone(),
+            ["/mnt/usb/a.bin", "/mnt/usb/fast/b.bin"].into_iter(),
+        );
+        assert!(!slots.allows("/mnt/usb/c.bin"));
+        assert!(slots.allows("/mnt/usb/fast/d.bin"));
+        assert!(slots.allows("/home/e.bin"));
+
+        let slots = VolumeSlots::new(
+            limits,
+            ["/mnt/usb/fast/b.bin", "/mnt/usb/fast/x.bin"].into_iter(),
+        );
+        assert!(slots.allows("/mnt/usb/c.bin"));
+        assert!(!slots.allows("/mnt/usb/fast/d.bin"));
+    }
+}
@@ -1,37 +1,148 @@
 use std::fs::{self, File};
-use std::io::{Seek, SeekFrom, Write};
 use std::path::Path;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
 
-use super::types::DownloadError;
+use tokio::sync::{mpsc, oneshot};
 
-/// 文件写入器 — 持有共享文件句柄，支持多分片并发写入
+use super::types::{DownloadError, PreallocationMode};
+
+/// 全局预分配策略 — 由设置页通过 `download_set_preallocation` 更新，新建任务时读取。
+static PREALLOCATION_MODE: Mutex<PreallocationMode> = Mutex::new(PreallocationMode::Sparse);
+
+/// 更新全局预分配策略，仅影响之后新建的下载文件。
+pub fn set_preallocation_mode(mode: PreallocationMode) {
+    *PREALLOCATION_MODE.lock().unwrap() = mode;
+}
+
+/// 读取当前全局预分配策略。
+pub fn preallocation_mode() -> PreallocationMode {
+    *PREALLOCATION_MODE.lock().unwrap()
+}
+
+/// 写盘线程数 — 顺序写为主的场景下少量线程即可打满 NVMe，更多线程只会增加随机 I/O。
+const WRITE_POOL_THREADS: usize = 4;
+/// 每个写盘线程的队列深度；队列满时分片在 `write_at` 上等待，形成背压。
+const WRITE_QUEUE_DEPTH: usize = 16;
+
+/// 一次写盘请求。写入完成后通过 `reply` 归还缓冲区，供分片复用。
+struct WriteJob {
+    file: Arc<File>,
+    offset: u64,
+    data: Vec<u8>,
+    reply: oneshot::Sender<std::io::Result<Vec<u8>>>,
+}
+
+/// 专用阻塞写盘线程池 — 磁盘 I/O 不占用 tokio 工作线程。
 ///
-/// 通过 Arc<Mutex<File>> 共享文件句柄，避免每次 write_at 都 open/close。
-/// Clone 后多个分片共享同一个文件句柄。
-pub struct FileWriter {
-    path: String,
-    file: Mutex<File>,
+/// 每个线程持有一个有界队列，请求按轮询分发。
+struct WritePool {
+    senders: Vec<mpsc::Sender<WriteJob>>,
+    next: AtomicUsize,
 }
 
-impl Clone for FileWriter {
-    /// 每次 clone 都重新打开一个独立句柄，避免多分片共享同一把 Mutex。
-    ///
-    /// 这样可以减少串行写入带来的 tokio 工作线程阻塞。
-    fn clone(&self) -> Self {
-        let file = File::options()
-            .write(true)
-            .open(&self.path)
-            .unwrap_or_else(|err| {
-                panic!("复制文件写入器失败，无法重新打开 {}：{}", self.path, err)
-            });
+static WRITE_POOL: LazyLock<WritePool> = LazyLock::new(WritePool::start);
+
+impl WritePool {
+    fn start() -> Self {
+        let senders = (0..WRITE_POOL_THREADS)
+            .filter_map(|index| {
+                let (tx, mut rx) = mpsc::channel::<WriteJob>(WRITE_QUEUE_DEPTH);
+                let spawned = std::thread::Builder::new()
+                    .name(format!("download-writer-{}", index))
+                    .spawn(move || {
+                        while let Some(mut job) = rx.blocking_recv() {
+                            let result = write_all_at(&job.file, &job.data, job.offset).map(|_| {
+                                job.data.clear();
+                                job.data
+                            });
+                            let _ = job.reply.send(result);
+                        }
+                    });
+                match spawned {
+                    Ok(_) => Some(tx),
+                    Err(e) => {
+                        log::error!("[写盘] 启动写盘线程{}失败: {}", index, e);
+                        None
+                    }
+                }
+            })
+            .collect();
         Self {
-            path: self.path.clone(),
-            file: Mutex::new(file),
+            senders,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    async fn submit(&self, job: WriteJob) -> Result<(), DownloadError> {
+        if self.senders.is_empty() {
+            return Err(DownloadError::JoinError("没有可用的写盘线程".to_string()));
         }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.senders.len();
+        self.senders[index]
+            .send(job)
+            .await
+            .map_err(|_| DownloadError::JoinError("写盘线程已退出".to_string()))
     }
 }
 
+/// 定位写入完整字节块，不依赖文件游标，多线程并发调用互不干扰。
+#[cfg(unix)]
+fn write_all_at(file: &File, data: &[u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, data, offset)
+}
+
+/// Windows 的 `seek_write` 会移动游标，但每次调用都显式指定偏移，并发写入仍然安全。
+#[cfg(windows)]
+fn write_all_at(file: &File, mut data: &[u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !data.is_empty() {
+        match file.seek_write(data, offset) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                data = &data[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// 定位读取完整字节块，与 `write_all_at` 对应。
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// 文件写入器 — 持有共享文件句柄，支持多分片并发写入
+///
+/// 所有分片共享同一个 `Arc<File>`，通过定位写入（`pwrite`/`seek_write`）直接写到各自偏移，
+/// 无需加锁；clone 只增加引用计数，不会重新打开文件，因而不会失败。
+/// 实际写盘在专用线程池中执行，避免阻塞 tokio 工作线程。
+#[derive(Clone)]
+pub struct FileWriter {
+    file: Arc<File>,
+}
+
 impl FileWriter {
     /// 检查目标路径所在磁盘是否有足够空间
     pub fn check_disk_space(path: &str, needed: u64) -> Result<(), DownloadError> {
@@ -47,8 +158,15 @@ impl FileWriter {
         Ok(())
     }
 
-    /// 创建目标文件、预分配空间并返回写入器。
-    pub fn create(path: &str, file_size: u64) -> Result<Self, DownloadError> {
+    /// 创建目标文件、按策略预分配空间并返回写入器。
+    ///
+    /// `Full` 策略下空间不足会立即返回 `InsufficientDiskSpace` 并删除刚创建的文件，
+    /// 不会等到下载中途写满磁盘才失败。
+    pub fn create(
+        path: &str,
+        file_size: u64,
+        preallocation: PreallocationMode,
+    ) -> Result<Self, DownloadError> {
         let p = Path::new(path);
         if let Some(parent) = p.parent() {
             if !parent.exists() {
@@ -62,41 +180,77 @@ impl FileWriter {
             .truncate(true)
             .open(path)
             .map_err(DownloadError::Io)?;
-        file.set_len(file_size).map_err(DownloadError::Io)?;
+        match preallocation {
+            PreallocationMode::None => {}
+            PreallocationMode::Sparse => file.set_len(file_size).map_err(DownloadError::Io)?,
+            PreallocationMode::Full => {
+                if let Err(err) = fs4::FileExt::allocate(&file, file_size) {
+                    drop(file);
+                    let _ = fs::remove_file(path);
+                    if err.kind() == std::io::ErrorKind::StorageFull {
+                        let dir = p.parent().unwrap_or(p);
+                        return Err(DownloadError::InsufficientDiskSpace {
+                            needed: file_size,
+                            available: fs4::available_space(dir).unwrap_or(0),
+                        });
+                    }
+                    return Err(DownloadError::Io(err));
+                }
+            }
+        }
 
         Ok(Self {
-            path: path.to_string(),
-            file: Mutex::new(file),
+            file: Arc::new(file),
         })
     }
 
-    /// 打开已有文件用于续传写入
+    /// 打开已有文件用于续传写入或修复比对
     pub fn open(path: &str) -> Result<Self, DownloadError> {
         let file = File::options()
+            .read(true)
             .write(true)
             .open(path)
             .map_err(DownloadError::Io)?;
 
         Ok(Self {
-            path: path.to_string(),
-            file: Mutex::new(file),
+            file: Arc::new(file),
         })
     }
 
-    /// 在指定偏移位置写入完整字节块。
-    pub fn write_at(&self, offset: u64, data: &[u8]) -> Result<(), DownloadError> {
-        let mut file = self.file.lock().unwrap();
-        file.seek(SeekFrom::Start(offset))
-            .map_err(DownloadError::Io)?;
-        file.write_all(data).map_err(DownloadError::Io)?;
+    /// 在指定偏移位置写入完整字节块，写盘完成后归还清空的缓冲区供调用方复用。
+    ///
+    /// 写盘线程队列满时在此等待，下载速度超过磁盘速度时自然形成背压。
+    pub async fn write_at(&self, offset: u64, data: Vec<u8>) -> Result<Vec<u8>, DownloadError> {
+        let (reply, reply_rx) = oneshot::channel();
+        WRITE_POOL
+            .submit(WriteJob {
+                file: self.file.clone(),
+                offset,
+                data,
+                reply,
+            })
+            .await?;
+        let buffer = reply_rx
+            .await
+            .map_err(|_| DownloadError::JoinError("写盘线程未返回结果".to_string()))??;
+        Ok(buffer)
+    }
 
-        Ok(())
+    /// 读取指定偏移处的 `len` 字节，供修复模式与远端数据比对。
+    pub async fn read_at(&self, offset: u64, len: usize) -> Result<Vec<u8>, DownloadError> {
+        let file = self.file.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut buf = vec![0u8; len];
+            read_exact_at(&file, &mut buf, offset).map(|_| buf)
+        })
+        .await
+        .map_err(|e| DownloadError::JoinError(e.to_string()))?
+        .map_err(DownloadError::Io)
     }
 
     /// 将已写入数据刷盘，尽量保证断电或崩溃后进度记录仍与磁盘一致。
     pub fn sync_data(&self) -> Result<(), DownloadError> {
-        let file = self.file.lock().unwrap();
-        file.sync_data().map_err(DownloadError::Io)?;
+        self.file.sync_data().map_err(DownloadError::Io)?;
         Ok(())
     }
 }
@@ -0,0 +1,292 @@
+//! 分段磁盘缓存。
+//!
+//! 以 URL 的 SHA1 作为文件名落盘，计算前去掉查询串中的时效签名参数（重新签名后仍命中同一分段），
+//! 其余参数保留，按查询串区分资源（如 `/key?fid=…`）的地址不会互相串用；
+//! 内存中只维护大小与最近访问序号；
+//! 总大小超过上限时按最久未访问淘汰。启动时扫描目录，按 mtime 恢复访问顺序。
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{debug, warn};
+use sha1::{Digest, Sha1};
+
+/// 计算缓存键时忽略的查询参数（按小写比较）：签名、过期时间等每次重新签名都会变化的值。
+/// `x-oss-`、`x-amz-` 开头的对象存储签名参数同样忽略。
+const VOLATILE_QUERY_PARAMS: &[&str] = &[
+    "t",
+    "sign",
+    "signature",
+    "expires",
+    "expire",
+    "e",
+    "token",
+    "auth_key",
+    "policy",
+    "key-pair-id",
+    "ossaccesskeyid",
+];
+
+fn is_volatile_param(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    VOLATILE_QUERY_PARAMS.contains(&name.as_str())
+        || name.starts_with("x-oss-")
+        || name.starts_with("x-amz-")
+}
+
+struct CacheEntry {
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheIndex {
+    entries: HashMap<String, CacheEntry>,
+    total: u64,
+    /// 单调递增的访问序号，代替时间戳比较新旧。
+    clock: u64,
+}
+
+impl CacheIndex {
+    fn touch(&mut self, key: &str) -> bool {
+        self.clock += 1;
+        let clock = self.clock;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_used = clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, key: String, size: u64) {
+        self.clock += 1;
+        let last_used = self.clock;
+        if let Some(old) = self.entries.insert(key, CacheEntry { size, last_used }) {
+            self.total -= old.size;
+        }
+        self.total += size;
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(old) = self.entries.remove(key) {
+            self.total -= old.size;
+        }
+    }
+
+    /// 弹出最久未访问的条目，直到总大小不超过 `limit`，返回被淘汰的键。
+    fn evict_to(&mut self, limit: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total > limit {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+            evicted.push(oldest);
+        }
+        evicted
+    }
+}
+
+/// 有界的 LRU 分段磁盘缓存。
+pub struct SegmentCache {
+    dir: PathBuf,
+    limit: Mutex<u64>,
+    index: Mutex<CacheIndex>,
+}
+
+impl SegmentCache {
+    /// 打开（必要时创建）缓存目录并重建索引。
+    pub fn open(dir: PathBuf, limit: u64) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if !meta.is_file() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".tmp") {
+                // 上次写入中断留下的临时文件
+                let _ = std::fs::remove_file(entry.path());
+                continue;
+            }
+            let mtime = meta.modified().unwrap_or(std::time::UNIX_EPOCH);
+            files.push((name, meta.len(), mtime));
+        }
+        files.sort_by_key(|(_, _, mtime)| *mtime);
+
+        let mut index = CacheIndex::default();
+        for (name, size, _) in files {
+            index.insert(name, size);
+        }
+
+        let cache = Self {
+            dir,
+            limit: Mutex::new(limit),
+            index: Mutex::new(index),
+        };
+        cache.evict();
+        debug!(
+            "[HLS缓存] 已载入 {} 个分段，共 {} 字节",
+            cache.index.lock().unwrap().entries.len(),
+            cache.index.lock().unwrap().total
+        );
+        Ok(cache)
+    }
+
+    fn key(url: &str) -> String {
+        let identity = match reqwest::Url::parse(url) {
+            Ok(parsed) => {
+                let mut identity =
+                    format!("{}{}", parsed.host_str().unwrap_or_default(), parsed.path());
+                let mut params: Vec<_> = parsed
+                    .query_pairs()
+                    .filter(|(name, _)| !is_volatile_param(name))
+                    .collect();
+                params.sort();
+                for (name, value) in params {
+                    identity.push_str(&format!("&{}={}", name, value));
+                }
+                identity
+            }
+            Err(_) => url.to_string(),
+        };
+        Sha1::digest(identity.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.index
+            .lock()
+            .unwrap()
+            .entries
+            .contains_key(&Self::key(url))
+    }
+
+    /// 读取缓存内容并刷新访问顺序；文件已被外部删除时同步移出索引。
+    pub fn get(&self, url: &str) -> Option<Vec<u8>> {
+        let key = Self::key(url);
+        if !self.index.lock().unwrap().touch(&key) {
+            return None;
+        }
+        match std::fs::read(self.path(&key)) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                warn!("[HLS缓存] 读取缓存分段失败 {}: {}", key, e);
+                self.index.lock().unwrap().remove(&key);
+                None
+            }
+        }
+    }
+
+    /// 写入缓存（临时文件 + rename），随后按上限淘汰。
+    pub fn put(&self, url: &str, data: &[u8]) -> std::io::Result<()> {
+        let limit = *self.limit.lock().unwrap();
+        if data.len() as u64 > limit {
+            return Ok(());
+        }
+        let key = Self::key(url);
+        let path = self.path(&key);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, &path)?;
+        self.index.lock().unwrap().insert(key, data.len() as u64);
+        self.evict();
+        Ok(())
+    }
+
+    /// 调整缓存上限，立即淘汰超出部分。
+    pub fn set_limit(&self, limit: u64) {
+        *self.limit.lock().unwrap() = limit;
+        self.evict();
+    }
+
+    fn evict(&self) {
+        let limit = *self.limit.lock().unwrap();
+        let evicted = self.index.lock().unwrap().evict_to(limit);
+        for key in evicted {
+            remove_quietly(&self.path(&key));
+        }
+    }
+}
+
+fn remove_quietly(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        warn!("[HLS缓存] 删除缓存文件失败 {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(limit: u64) -> (SegmentCache, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("hls-cache-test-{}", uuid::Uuid::new_v4()));
+        (SegmentCache::open(dir.clone(), limit).unwrap(), dir)
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let (cache, dir) = temp_cache(10);
+        cache.put("a", &[0; 4]).unwrap();
+        cache.put("b", &[0; 4]).unwrap();
+        // 访问 a 后 b 变为最久未用
+        assert!(cache.get("a").is_some());
+        cache.put("c", &[0; 4]).unwrap();
+
+        assert!(cache.contains("a"));
+        assert!(!cache.contains("b"));
+        assert!(cache.contains("c"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn resigned_urls_share_cache_entry() {
+        let (cache, dir) = temp_cache(100);
+        cache
+            .put("https://cdn.example.com/v/seg1.ts?t=1&sign=a", &[1; 4])
+            .unwrap();
+        assert!(cache.contains("https://cdn.example.com/v/seg1.ts?t=2&sign=b"));
+        assert!(!cache.contains("https://cdn.example.com/v/seg2.ts?t=1&sign=a"));
+
+        // 按查询串区分的资源（如解密密钥）不能共用缓存
+        cache
+            .put("https://cdn.example.com/key?fid=1&t=1&sign=a", &[2; 4])
+            .unwrap();
+        assert!(cache.contains("https://cdn.example.com/key?sign=b&fid=1&t=2"));
+        assert!(!cache.contains("https://cdn.example.com/key?fid=2&t=1&sign=a"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn reopen_restores_entries_and_applies_new_limit() {
+        let (cache, dir) = temp_cache(100);
+        cache.put("a", &[1; 8]).unwrap();
+        cache.put("b", &[2; 8]).unwrap();
+        drop(cache);
+
+        let reopened = SegmentCache::open(dir.clone(), 100).unwrap();
+        assert_eq!(reopened.get("b").as_deref(), Some(&[2u8; 8][..]));
+        reopened.set_limit(8);
+        assert_eq!(reopened.index.lock().unwrap().total, 8);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,119 @@
+//! 本地 HLS 缓存代理。
+//!
+//! 播放器通过 `hls_proxy_open` 拿到指向 `127.0.0.1` 的播放列表地址，代理负责：
+//! - 用正确的请求头向 115 CDN 拉取播放列表与分段，并把 m3u8 中的地址改写为代理地址
+//! - 播放时预取后续分段
+//! - 分段写入有界 LRU 磁盘缓存，回看与拖动进度无需重新下载
+//!
+//! 子模块：
+//! - `rewrite`：m3u8 URI 改写
+//! - `cache`：分段磁盘缓存
+//! - `server`：loopback HTTP 服务与上游拉取
+
+mod cache;
+mod rewrite;
+mod server;
+
+use std::sync::Arc;
+
+use log::info;
+use tauri::{App, Manager};
+
+use cache::SegmentCache;
+use rewrite::ResourceKind;
+use server::ProxyState;
+
+/// 默认缓存上限 1 GiB。
+const DEFAULT_CACHE_LIMIT: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HlsProxyInitError {
+    #[error("无法构建 HLS 代理 HTTP 客户端：{0}")]
+    BuildHttpClient(#[from] reqwest::Error),
+    #[error("无法解析应用缓存目录：{0}")]
+    ResolveCacheDir(String),
+    #[error("无法打开 HLS 缓存目录：{0}")]
+    OpenCache(#[source] std::io::Error),
+    #[error("无法监听本地代理端口：{0}")]
+    Bind(#[source] std::io::Error),
+}
+
+/// HLS 代理 Tauri managed state。
+pub struct HlsProxy {
+    state: Arc<ProxyState>,
+}
+
+/// `hls_proxy_open` 返回值。
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HlsProxySession {
+    pub session_id: String,
+    /// 交给播放器加载的本地播放列表地址。
+    pub url: String,
+}
+
+/// 初始化 HLS 代理：打开缓存目录、监听随机 loopback 端口并启动服务循环。
+pub fn init(app: &App) -> Result<(), HlsProxyInitError> {
+    let cache_dir = app
+        .path()
+        .app_cache_dir()
+        .map_err(|err| HlsProxyInitError::ResolveCacheDir(err.to_string()))?
+        .join("hls-cache");
+    let cache =
+        SegmentCache::open(cache_dir, DEFAULT_CACHE_LIMIT).map_err(HlsProxyInitError::OpenCache)?;
+
+    let client = reqwest::Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(30))
+        .build()?;
+
+    let listener =
+        std::net::TcpListener::bind(("127.0.0.1", 0)).map_err(HlsProxyInitError::Bind)?;
+    listener
+        .set_nonblocking(true)
+        .map_err(HlsProxyInitError::Bind)?;
+    let port = listener
+        .local_addr()
+        .map_err(HlsProxyInitError::Bind)?
+        .port();
+
+    let state = Arc::new(ProxyState::new(port, client, cache));
+    let state_for_server = state.clone();
+    tauri::async_runtime::spawn(async move {
+        match tokio::net::TcpListener::from_std(listener) {
+            Ok(listener) => server::serve(state_for_server, listener).await,
+            Err(e) => log::error!("[HLS代理] 启动监听失败: {}", e),
+        }
+    });
+
+    info!("[HLS代理] 已监听 127.0.0.1:{}", port);
+    app.manage(HlsProxy { state });
+    Ok(())
+}
+
+/// 为一次播放创建代理会话，返回本地播放列表地址。
+///
+/// `user_agent` 会用于该会话的所有上游请求。
+#[tauri::command]
+pub fn hls_proxy_open(
+    playlist_url: String,
+    user_agent: String,
+    proxy: tauri::State<'_, HlsProxy>,
+) -> HlsProxySession {
+    let session_id = proxy.state.open_session(user_agent);
+    let url = proxy
+        .state
+        .proxy_url(&session_id, &playlist_url, ResourceKind::Playlist);
+    HlsProxySession { session_id, url }
+}
+
+/// 关闭播放会话，停止为其预取；已缓存的分段保留。
+#[tauri::command]
+pub fn hls_proxy_close(session_id: String, proxy: tauri::State<'_, HlsProxy>) {
+    proxy.state.close_session(&session_id);
+}
+
+/// 动态调整缓存上限（字节）。
+#[tauri::command]
+pub fn hls_proxy_set_cache_limit(bytes: u64, proxy: tauri::State<'_, HlsProxy>) {
+    proxy.state.cache.set_limit(bytes);
+}
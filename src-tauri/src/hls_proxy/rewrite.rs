@@ -0,0 +1,158 @@
+//! m3u8 地址改写。
+//!
+//! 把播放列表中的子播放列表、分段、密钥等 URI 解析为绝对地址，
+//! 再交给调用方改写为指向本地代理的地址；其余行原样保留。
+
+use reqwest::Url;
+
+/// 被改写 URI 的资源类型。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// 子播放列表，需要经代理再次改写。
+    Playlist,
+    /// 分段、密钥、初始化分段等二进制资源，走缓存。
+    Resource,
+}
+
+/// 改写结果。
+pub struct RewrittenPlaylist {
+    pub content: String,
+    /// 媒体播放列表的分段绝对地址（按播放顺序），主播放列表为空。
+    pub segments: Vec<String>,
+}
+
+/// 带 `URI="..."` 属性的标签及其资源类型。
+const URI_TAGS: &[(&str, ResourceKind)] = &[
+    ("#EXT-X-KEY:", ResourceKind::Resource),
+    ("#EXT-X-MAP:", ResourceKind::Resource),
+    ("#EXT-X-MEDIA:", ResourceKind::Playlist),
+    ("#EXT-X-I-FRAME-STREAM-INF:", ResourceKind::Playlist),
+];
+
+fn absolute(base: &Url, uri: &str) -> String {
+    base.join(uri)
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_| uri.to_string())
+}
+
+/// 改写标签行中的 `URI="..."` 属性。
+fn rewrite_uri_attribute(
+    line: &str,
+    base: &Url,
+    kind: ResourceKind,
+    rewrite: &impl Fn(&str, ResourceKind) -> String,
+) -> String {
+    let Some(start) = line.find("URI=\"").map(|pos| pos + 5) else {
+        return line.to_string();
+    };
+    let Some(len) = line[start..].find('"') else {
+        return line.to_string();
+    };
+    let uri = &line[start..start + len];
+    format!(
+        "{}{}{}",
+        &line[..start],
+        rewrite(&absolute(base, uri), kind),
+        &line[start + len..]
+    )
+}
+
+/// 改写播放列表内所有 URI。
+///
+/// `rewrite` 接收绝对地址和资源类型，返回写回播放列表的新地址。
+pub fn rewrite_playlist(
+    content: &str,
+    base: &Url,
+    rewrite: impl Fn(&str, ResourceKind) -> String,
+) -> RewrittenPlaylist {
+    let is_master = content
+        .lines()
+        .any(|line| line.trim_start().starts_with("#EXT-X-STREAM-INF"));
+    let uri_kind = if is_master {
+        ResourceKind::Playlist
+    } else {
+        ResourceKind::Resource
+    };
+
+    let mut output = String::with_capacity(content.len() * 2);
+    let mut segments = Vec::new();
+    for raw in content.lines() {
+        let line = raw.trim();
+        if line.is_empty() {
+            output.push('\n');
+            continue;
+        }
+        if line.starts_with('#') {
+            match URI_TAGS.iter().find(|(tag, _)| line.starts_with(tag)) {
+                Some((_, kind)) => {
+                    output.push_str(&rewrite_uri_attribute(line, base, *kind, &rewrite))
+                }
+                None => output.push_str(line),
+            }
+        } else {
+            let absolute = absolute(base, line);
+            output.push_str(&rewrite(&absolute, uri_kind));
+            if !is_master {
+                segments.push(absolute);
+            }
+        }
+        output.push('\n');
+    }
+
+    RewrittenPlaylist {
+        content: output,
+        segments,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(url: &str, kind: ResourceKind) -> String {
+        match kind {
+            ResourceKind::Playlist => format!("P[{}]", url),
+            ResourceKind::Resource => format!("R[{}]", url),
+        }
+    }
+
+    #[test]
+    fn rewrites_media_playlist_segments_and_keys() {
+        let base = Url::parse("https://cdn.example.com/v/index.m3u8?t=1").unwrap();
+        let content = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x01\n#EXTINF:4,\nseg0.ts\n#EXTINF:4,\n/abs/seg1.ts\n#EXT-X-ENDLIST\n";
+        let result = rewrite_playlist(content, &base, tag);
+
+        assert_eq!(
+            result.segments,
+            vec![
+                "https://cdn.example.com/v/seg0.ts",
+                "https://cdn.example.com/abs/seg1.ts"
+            ]
+        );
+        assert!(
+            result
+                .content
+                .contains("URI=\"R[https://cdn.example.com/v/key.bin]\",IV=0x01")
+        );
+        assert!(
+            result
+                .content
+                .contains("\nR[https://cdn.example.com/v/seg0.ts]\n")
+        );
+    }
+
+    #[test]
+    fn rewrites_master_playlist_variants_as_playlists() {
+        let base = Url::parse("https://cdn.example.com/master.m3u8").unwrap();
+        let content =
+            "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=1280x720\n720/index.m3u8\n";
+        let result = rewrite_playlist(content, &base, tag);
+
+        assert!(result.segments.is_empty());
+        assert!(
+            result
+                .content
+                .contains("P[https://cdn.example.com/720/index.m3u8]")
+        );
+    }
+}
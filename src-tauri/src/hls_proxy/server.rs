@@ -0,0 +1,404 @@
+//! loopback HTTP 服务。
+//!
+//! 只实现播放器需要的最小 HTTP/1.1 子集：GET / HEAD / OPTIONS，每个连接处理一个请求后关闭。
+//! 路由：
+//! - `/<session>/playlist?u=<url>`：拉取并改写播放列表，不缓存（地址带时效签名）
+//! - `/<session>/resource?u=<url>`：分段与密钥，优先读缓存，并预取后续分段
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, warn};
+use reqwest::{StatusCode, Url};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::{Duration, Instant};
+
+use super::cache::SegmentCache;
+use super::rewrite::{ResourceKind, rewrite_playlist};
+
+/// 请求头最大长度，超出视为非法请求。
+const MAX_REQUEST_HEAD: usize = 16 * 1024;
+/// 读取请求头的超时秒数。
+const REQUEST_HEAD_TIMEOUT_SECS: u64 = 10;
+/// 播放位置之后预取的分段数。
+const PREFETCH_AHEAD: usize = 3;
+/// 全局同时进行的预取数，避免挤占播放请求的带宽。
+const MAX_CONCURRENT_PREFETCH: usize = 2;
+/// 会话空闲超过该时长即清理，防止播放器未调用 `hls_proxy_close` 时会话堆积。
+const SESSION_IDLE_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// 代理内部错误，最终映射为返回给播放器的 HTTP 状态码。
+#[derive(Debug, thiserror::Error)]
+pub(super) enum ProxyError {
+    #[error("请求格式错误：{0}")]
+    BadRequest(&'static str),
+    #[error("播放会话不存在或已关闭：{0}")]
+    SessionNotFound(String),
+    #[error("上游返回 HTTP {0}")]
+    UpstreamStatus(u16),
+    #[error("上游请求失败：{0}")]
+    Http(#[from] reqwest::Error),
+    #[error("缓存读写失败：{0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl ProxyError {
+    fn status(&self) -> u16 {
+        match self {
+            Self::BadRequest(_) => 400,
+            Self::SessionNotFound(_) => 404,
+            // 透传上游状态码，让 hls.js 按原有策略重试或报错
+            Self::UpstreamStatus(status) => *status,
+            Self::Http(_) => 502,
+            Self::Io(_) => 500,
+        }
+    }
+}
+
+/// 单个播放会话。
+///
+/// 保存访问 CDN 所需的请求头，以及最近一次媒体播放列表的分段顺序供预取使用。
+pub(super) struct Session {
+    user_agent: String,
+    segments: Mutex<Vec<String>>,
+    /// 最近一次收到该会话请求的时间。
+    last_active: Mutex<Instant>,
+}
+
+/// 代理共享状态。
+pub(super) struct ProxyState {
+    pub(super) port: u16,
+    client: reqwest::Client,
+    pub(super) cache: Arc<SegmentCache>,
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+    /// 正在拉取的资源：url → 锁，保证同一分段并发请求时只下载一次。
+    inflight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    prefetch_semaphore: Arc<Semaphore>,
+}
+
+impl ProxyState {
+    pub(super) fn new(port: u16, client: reqwest::Client, cache: SegmentCache) -> Self {
+        Self {
+            port,
+            client,
+            cache: Arc::new(cache),
+            sessions: Mutex::new(HashMap::new()),
+            inflight: Mutex::new(HashMap::new()),
+            prefetch_semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT_PREFETCH)),
+        }
+    }
+
+    pub(super) fn open_session(&self, user_agent: String) -> String {
+        self.prune_idle_sessions();
+        let session_id = uuid::Uuid::new_v4().simple().to_string();
+        self.sessions.lock().unwrap().insert(
+            session_id.clone(),
+            Arc::new(Session {
+                user_agent,
+                segments: Mutex::new(Vec::new()),
+                last_active: Mutex::new(Instant::now()),
+            }),
+        );
+        session_id
+    }
+
+    /// 清理空闲超时的会话。
+    fn prune_idle_sessions(&self) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions
+            .retain(|_, session| session.last_active.lock().unwrap().elapsed() < SESSION_IDLE_TTL);
+        if sessions.len() < before {
+            debug!("[HLS代理] 清理 {} 个空闲会话", before - sessions.len());
+        }
+    }
+
+    pub(super) fn close_session(&self, session_id: &str) {
+        self.sessions.lock().unwrap().remove(session_id);
+    }
+
+    fn session(&self, session_id: &str) -> Result<Arc<Session>, ProxyError> {
+        let session = self
+            .sessions
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| ProxyError::SessionNotFound(session_id.to_string()))?;
+        *session.last_active.lock().unwrap() = Instant::now();
+        Ok(session)
+    }
+
+    /// 构造指向本代理的地址。
+    pub(super) fn proxy_url(&self, session_id: &str, target: &str, kind: ResourceKind) -> String {
+        let route = match kind {
+            ResourceKind::Playlist => "playlist",
+            ResourceKind::Resource => "resource",
+        };
+        let mut url = Url::parse(&format!(
+            "http://127.0.0.1:{}/{}/{}",
+            self.port, session_id, route
+        ))
+        .expect("loopback 代理地址必然合法");
+        url.query_pairs_mut().append_pair("u", target);
+        url.to_string()
+    }
+
+    async fn fetch_upstream(&self, session: &Session, url: &str) -> Result<Vec<u8>, ProxyError> {
+        let resp = self
+            .client
+            .get(url)
+            .header("User-Agent", &session.user_agent)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(ProxyError::UpstreamStatus(resp.status().as_u16()));
+        }
+        Ok(resp.bytes().await?.to_vec())
+    }
+
+    async fn serve_playlist(
+        &self,
+        session_id: &str,
+        session: &Session,
+        url: &str,
+    ) -> Result<Vec<u8>, ProxyError> {
+        let base = Url::parse(url).map_err(|_| ProxyError::BadRequest("播放列表地址无效"))?;
+        let body = self.fetch_upstream(session, url).await?;
+        let content = String::from_utf8_lossy(&body);
+        let rewritten = rewrite_playlist(&content, &base, |target, kind| {
+            self.proxy_url(session_id, target, kind)
+        });
+        if !rewritten.segments.is_empty() {
+            *session.segments.lock().unwrap() = rewritten.segments;
+        }
+        Ok(rewritten.content.into_bytes())
+    }
+
+    /// 读取资源：命中缓存直接返回，否则拉取上游并写入缓存。
+    async fn fetch_resource(
+        self: &Arc<Self>,
+        session: &Session,
+        url: &str,
+    ) -> Result<Vec<u8>, ProxyError> {
+        if let Some(data) = self.cache_get(url).await {
+            return Ok(data);
+        }
+
+        let lock = self
+            .inflight
+            .lock()
+            .unwrap()
+            .entry(url.to_string())
+            .or_default()
+            .clone();
+        let result = async {
+            let _guard = lock.lock().await;
+            // 等锁期间可能已由预取写入缓存
+            if let Some(data) = self.cache_get(url).await {
+                return Ok(data);
+            }
+            let data = self.fetch_upstream(session, url).await?;
+            let cache = self.cache.clone();
+            let key = url.to_string();
+            let copy = data.clone();
+            if let Ok(Err(e)) = tokio::task::spawn_blocking(move || cache.put(&key, &copy)).await {
+                warn!("[HLS代理] 写入缓存失败: {}", e);
+            }
+            Ok(data)
+        }
+        .await;
+        self.inflight.lock().unwrap().remove(url);
+        result
+    }
+
+    async fn cache_get(&self, url: &str) -> Option<Vec<u8>> {
+        let cache = self.cache.clone();
+        let key = url.to_string();
+        tokio::task::spawn_blocking(move || cache.get(&key))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// 按播放列表顺序预取当前分段之后的若干分段。
+    fn schedule_prefetch(self: &Arc<Self>, session: &Arc<Session>, current: &str) {
+        let upcoming: Vec<String> = {
+            let segments = session.segments.lock().unwrap();
+            match segments.iter().position(|segment| segment == current) {
+                Some(pos) => segments
+                    .iter()
+                    .skip(pos + 1)
+                    .take(PREFETCH_AHEAD)
+                    .cloned()
+                    .collect(),
+                None => return,
+            }
+        };
+
+        for url in upcoming {
+            if self.cache.contains(&url) || self.inflight.lock().unwrap().contains_key(&url) {
+                continue;
+            }
+            let state = self.clone();
+            let session = session.clone();
+            tokio::spawn(async move {
+                let Ok(_permit) = state.prefetch_semaphore.clone().acquire_owned().await else {
+                    return;
+                };
+                if state.cache.contains(&url) {
+                    return;
+                }
+                match state.fetch_resource(&session, &url).await {
+                    Ok(data) => debug!("[HLS代理] 预取完成 {} 字节", data.len()),
+                    Err(e) => debug!("[HLS代理] 预取失败: {}", e),
+                }
+            });
+        }
+    }
+
+    async fn route(self: &Arc<Self>, target: &str) -> Result<(&'static str, Vec<u8>), ProxyError> {
+        let url = Url::parse(&format!("http://127.0.0.1{}", target))
+            .map_err(|_| ProxyError::BadRequest("请求路径无效"))?;
+        let mut path = url
+            .path_segments()
+            .ok_or(ProxyError::BadRequest("请求路径无效"))?;
+        let (Some(session_id), Some(route)) = (path.next(), path.next()) else {
+            return Err(ProxyError::BadRequest("请求路径无效"));
+        };
+        let upstream = url
+            .query_pairs()
+            .find(|(key, _)| key == "u")
+            .map(|(_, value)| value.into_owned())
+            .ok_or(ProxyError::BadRequest("缺少上游地址"))?;
+        let session = self.session(session_id)?;
+
+        match route {
+            "playlist" => {
+                let body = self.serve_playlist(session_id, &session, &upstream).await?;
+                Ok(("application/vnd.apple.mpegurl", body))
+            }
+            "resource" => {
+                let body = self.fetch_resource(&session, &upstream).await?;
+                self.schedule_prefetch(&session, &upstream);
+                Ok(("video/mp2t", body))
+            }
+            _ => Err(ProxyError::BadRequest("未知路由")),
+        }
+    }
+}
+
+/// 代理主循环：逐个接受连接并交给独立任务处理。
+pub(super) async fn serve(state: Arc<ProxyState>, listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(state, stream).await {
+                        debug!("[HLS代理] 连接处理失败: {}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                warn!("[HLS代理] 接受连接失败: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+/// 读取请求头，返回 (method, target)。
+async fn read_request_head(stream: &mut TcpStream) -> std::io::Result<Option<(String, String)>> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|window| window == b"\r\n\r\n") {
+        if buf.len() > MAX_REQUEST_HEAD {
+            return Ok(None);
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let head = String::from_utf8_lossy(&buf);
+    let mut parts = head.lines().next().unwrap_or_default().split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => Ok(Some((method.to_string(), target.to_string()))),
+        _ => Ok(None),
+    }
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+    include_body: bool,
+) -> std::io::Result<()> {
+    let reason = StatusCode::from_u16(status)
+        .ok()
+        .and_then(|code| code.canonical_reason())
+        .unwrap_or("Unknown");
+    let head = format!(
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: GET, HEAD, OPTIONS\r\n\
+         Access-Control-Allow-Headers: *\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        reason,
+        content_type,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    if include_body {
+        stream.write_all(body).await?;
+    }
+    stream.flush().await
+}
+
+async fn handle_connection(state: Arc<ProxyState>, mut stream: TcpStream) -> std::io::Result<()> {
+    let head = tokio::time::timeout(
+        Duration::from_secs(REQUEST_HEAD_TIMEOUT_SECS),
+        read_request_head(&mut stream),
+    )
+    .await
+    .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "读取请求头超时"))??;
+    let Some((method, target)) = head else {
+        return write_response(&mut stream, 400, "text/plain", b"", true).await;
+    };
+
+    match method.as_str() {
+        "OPTIONS" => write_response(&mut stream, 204, "text/plain", b"", false).await,
+        "GET" | "HEAD" => match state.route(&target).await {
+            Ok((content_type, body)) => {
+                write_response(&mut stream, 200, content_type, &body, method == "GET").await
+            }
+            Err(e) => {
+                debug!(
+                    "[HLS代理] 请求失败 {}: {}",
+                    &target[..target.len().min(80)],
+                    e
+                );
+                let message = e.to_string();
+                write_response(
+                    &mut stream,
+                    e.status(),
+                    "text/plain; charset=utf-8",
+                    message.as_bytes(),
+                    method == "GET",
+                )
+                .await
+            }
+        },
+        _ => write_response(&mut stream, 405, "text/plain", b"", true).await,
+    }
+}
@@ -10,11 +10,12 @@
 //!   │   ├─ bind_log_level_to_setting_store  → 同步前端日志等级
 //!   │   ├─ 扩展 asset scope → macOS/Linux 系统字体目录
 //!   │   ├─ upload::init / download::init    → 业务模块初始化
+//!   │   ├─ hls_proxy::init                  → 本地 HLS 播放代理
 //!   │   └─ tray::create                     → 系统托盘
 //!   ├─ invoke_handler → 注册所有 Tauri command
 //!   └─ run 事件循环
 //!       └─ ExitRequested(code=None) → Cmd+Q / Alt+F4 → 阻止退出
-//!           ExitRequested(code=0)   → 前端 exit(0)   → 放行
+//!           ExitRequested(code=0)   → 前端 exit(0)   → 落盘下载状态后放行
 //! ```
 //!
 //! # 模块职责
@@ -23,6 +24,7 @@
 //! |------|------|
 //! | `tray`     | 系统托盘图标、右键菜单、点击事件 |
 //! | `download` | HTTP 多分片并发下载、断点续传、限速 |
+//! | `hls_proxy` | 本地 HLS 播放代理、分段预取与 LRU 磁盘缓存 |
 //! | `upload`   | 115 网盘 OSS 上传、分片、队列调度 |
 //! | `subtitle` | 系统字体扫描、ASS 字幕字体匹配 |
 
@@ -34,6 +36,7 @@ use tauri_plugin_pinia::ManagerExt as PiniaManagerExt;
 use tauri_plugin_window_state::StateFlags;
 
 mod download;
+mod hls_proxy;
 mod subtitle;
 mod tray;
 mod upload;
@@ -175,6 +178,10 @@ pub fn run() {
 
             upload::init(app).map_err(|err| -> Box<dyn std::error::Error> { Box::new(err) })?;
             download::init(app).map_err(|err| -> Box<dyn std::error::Error> { Box::new(err) })?;
+            // HLS 代理只用于播放加速，初始化失败时播放器回退直连，不阻止应用启动。
+            if let Err(err) = hls_proxy::init(app) {
+                log::error!("[HLS代理] 初始化失败，播放将直连 CDN: {}", err);
+            }
             tray::create(app.handle())?;
 
             log::info!("应用初始化完成");
@@ -183,6 +190,10 @@ pub fn run() {
         // ---- Tauri command 注册 ----
         .invoke_handler(tauri::generate_handler![
             subtitle::subtitle_get_system_font_config,
+            // HLS 播放代理
+            hls_proxy::hls_proxy_open,
+            hls_proxy::hls_proxy_close,
+            hls_proxy::hls_proxy_set_cache_limit,
             // 上传
             upload::local::upload_get_file_size,
             upload::local::upload_is_directory,
@@ -208,34 +219,53 @@ pub fn run() {
             // 下载
             download::store::download_delete_finished_tasks,
             download::store::download_get_top_level_tasks,
+            download::store::download_get_renamed_children,
             download::events::url::download_provide_url,
             download::queue::download_enqueue_file,
+            download::queue::download_enqueue_hls,
+            download::queue::download_enqueue_url,
+            download::path_rules::download_set_path_rules,
+            download::path_rules::download_preview_save_path,
+            download::hooks::download_set_hooks,
+            download::extract::download_set_auto_extract,
+            download::dedup::download_set_dedup_mode,
+            download::volume::download_set_volume_limits,
             download::queue::download_set_max_concurrent,
             download::queue::download_set_speed_limit,
+            download::queue::download_set_preallocation,
+            download::queue::download_set_disk_reserve,
             download::queue::download_pause_task,
             download::queue::download_cancel_task,
             download::queue::download_resume_task,
             download::queue::download_retry_task,
+            download::queue::download_repair_task,
+            download::verify::download_verify_local,
             download::queue::download_create_folder_task,
             download::queue::download_restart_folder_collection,
             download::queue::download_fail_folder_collection,
             download::queue::download_enqueue_folder,
+            download::plan::download_plan_folder,
             download::queue::download_pause_folder,
             download::queue::download_resume_folder,
             download::queue::download_cancel_folder,
+            download::queue::download_set_folder_max_active,
             download::queue::download_retry_folder,
             download::queue::download_pause_all,
             download::queue::download_resume_all,
+            download::queue::download_set_launch_params,
+            download::hls::download_probe_hls,
         ])
         // ---- 运行 ----
         .build(tauri::generate_context!())
         .unwrap_or_else(|err| panic!("Tauri 应用运行失败：{}", err))
-        .run(|_app, event| {
+        .run(|app, event| {
             if let RunEvent::ExitRequested { code, api, .. } = event {
                 // code=None  → 系统快捷键（Cmd+Q / Alt+F4）→ 阻止，窗口已隐藏
                 // code=0     → 前端 exit(0) → 托盘确认退出 → 放行
                 if code.is_none() {
                     api.prevent_exit();
+                } else {
+                    download::shutdown(app);
                 }
             }
         });
@@ -30,6 +30,7 @@ export type ContextMenuAction =
   | 'open'
   | 'reload'
   | 'download'
+  | 'syncDownload'
   | 'uploadFile'
   | 'copy'
   | 'move'
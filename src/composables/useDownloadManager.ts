@@ -1,8 +1,9 @@
 import { invoke } from '@tauri-apps/api/core';
 import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import { fileDownloadUrl, fileList } from '@/api/file';
+import { videoPlayUrl } from '@/api/video';
 import type { MyFile } from '@/api/types/file';
-import { useSettingStore } from '@/store/setting';
+import { useSettingStore, type PathRule } from '@/store/setting';
 import { useUserStore } from '@/store/user';
 
 // 下载列表前端桥接层。
@@ -22,6 +23,8 @@ export interface DownLoadFile {
     | 'active'
     | 'waiting'
     | 'paused'
+    | 'paused_disk_full'
+    | 'waiting_for_volume'
     | 'pausing'
     | 'complete'
     | 'error'
@@ -53,6 +56,29 @@ export interface DownLoadFile {
   completedFiles?: number;
   /** 文件夹内失败文件数 */
   failedFiles?: number;
+  /** 按冲突策略跳过下载的原因 */
+  skipReason?: string;
+  /** 文件夹内因本地已有文件而跳过的子任务数 */
+  skippedFiles?: number;
+  /** 文件名清理前的原始名称（文件夹子任务为相对路径） */
+  originalPath?: string;
+  /** 自动解压阶段 */
+  extractStatus?: 'extracting' | 'extracted' | 'failed';
+  /** 自动解压失败原因 */
+  extractError?: string;
+  /** 解压进度 (0-100)，仅来自 download:extract-progress 事件 */
+  extractProgress?: number;
+  /** 复用的本地文件路径（按 SHA1 复用，未从网络下载） */
+  reusedFrom?: string;
+  /** 文件夹同时下载的子任务上限，为空表示只受全局并发限制 */
+  maxActiveChildren?: number;
+}
+
+/** download:extract-progress 事件 (snake_case, 来自 Rust ExtractProgressEvent) */
+interface ExtractProgressEvent {
+  task_ids: string[];
+  extracted_bytes: number;
+  total_bytes: number;
 }
 
 /** download:progress 事件的单项进度快照 (camelCase, 来自 Rust ProgressItem) */
@@ -75,6 +101,10 @@ interface UrlNeededPayload {
   requestId: string;
   taskId: string;
   pickCode: string;
+  /** file: 文件直链；hls: 视频播放列表 */
+  kind: 'file' | 'hls';
+  /** HLS 任务期望的最大画面高度，null 表示最高清晰度 */
+  maxHeight: number | null;
 }
 
 /** download_enqueue_folder 的文件项参数 */
@@ -84,6 +114,28 @@ interface FolderFileItem {
   pickCode: string;
   size: number;
   path: string;
+  sha1?: string;
+  /** 115 修改时间（Unix 秒） */
+  remoteMtime?: number;
+  /** 115 创建时间（Unix 秒） */
+  remoteCtime?: number;
+}
+
+/** download_enqueue_folder 的子目录参数，用于下载完成后还原目录时间 */
+interface FolderDirItem {
+  path: string;
+  remoteMtime?: number;
+  remoteCtime?: number;
+}
+
+/** 同步下载时判断本地文件是否已是最新的方式 */
+type FolderSyncCompare = 'size' | 'sha1';
+
+/** 保存路径规则预览结果，matchedRule 为命中规则的序号 */
+interface PathPreview {
+  savePath: string;
+  matchedRule?: number;
+  ruleName?: string;
 }
 
 interface FolderDownloadTarget {
@@ -91,6 +143,8 @@ interface FolderDownloadTarget {
   fid: string;
   name: string;
   pickCode: string;
+  remoteMtime?: number;
+  remoteCtime?: number;
 }
 
 /** progressCache 中的快照（per D-02，防止 state-sync 进度回跳） */
@@ -124,7 +178,12 @@ interface DownloadStats {
 
 const ACTIVE_DOWNLOAD_STATUS_SET = new Set<DownloadStatus>(['active']);
 const PROCESSING_DOWNLOAD_STATUS_SET = new Set<DownloadStatus>(['active']);
-const PAUSED_DOWNLOAD_STATUS_SET = new Set<DownloadStatus>(['paused', 'pausing']);
+const PAUSED_DOWNLOAD_STATUS_SET = new Set<DownloadStatus>([
+  'paused',
+  'pausing',
+  'paused_disk_full',
+  'waiting_for_volume',
+]);
 const TERMINAL_DOWNLOAD_STATUS_SET = new Set<DownloadStatus>([
   'complete',
   'error',
@@ -133,6 +192,10 @@ const TERMINAL_DOWNLOAD_STATUS_SET = new Set<DownloadStatus>([
 ]);
 const PRESERVED_DOWNLOAD_STATUS_SET = new Set<DownloadStatus>(['active', 'pausing', 'paused']);
 const FOLDER_COLLECTION_ABORTED = 'folder-collection-aborted';
+/** 一次 downurl 调用最多合并的提取码数 */
+const FILE_URL_BATCH_SIZE = 50;
+/** 合并文件地址请求的等待时间 */
+const FILE_URL_BATCH_DELAY_MS = 20;
 
 /** 统一的下载 command 调用入口。 */
 const invokeDownloadCommand = async <T = void>(
@@ -202,8 +265,55 @@ export const useDownloadManager = createSharedComposable(() => {
     });
   };
 
+  const syncDiskReserve = async (mb = settingStore.downloadSetting.diskReserveMb) => {
+    await invokeDownloadCommand('download_set_disk_reserve', { bytes: mb * 1024 * 1024 });
+  };
+
+  const syncPreallocation = async (mode = settingStore.downloadSetting.preallocation) => {
+    await invokeDownloadCommand('download_set_preallocation', { mode });
+  };
+
+  const syncPathRules = async (rules = settingStore.downloadSetting.pathRules) => {
+    await invokeDownloadCommand('download_set_path_rules', { rules });
+  };
+
+  const syncHooks = async (hooks = settingStore.downloadSetting.hooks) => {
+    await invokeDownloadCommand('download_set_hooks', { hooks });
+  };
+
+  const syncAutoExtract = async () => {
+    await invokeDownloadCommand('download_set_auto_extract', {
+      enabled: settingStore.downloadSetting.autoExtract,
+      deleteAfter: settingStore.downloadSetting.deleteArchiveAfterExtract,
+    });
+  };
+
+  const syncDedupMode = async (mode = settingStore.downloadSetting.dedupMode) => {
+    await invokeDownloadCommand('download_set_dedup_mode', { mode });
+  };
+
+  const syncVolumeLimits = async (limits = settingStore.downloadSetting.volumeLimits) => {
+    await invokeDownloadCommand('download_set_volume_limits', { limits });
+  };
+
+  // 启动时因保存卷未挂载而停放的任务，卷恢复后用这些参数自动继续。
+  const syncLaunchParams = async () => {
+    await invokeDownloadCommand('download_set_launch_params', getDownloadParams());
+  };
+
   const syncDownloadSettings = async () => {
-    await Promise.all([syncMaxConcurrent(), syncSpeedLimit()]);
+    await Promise.all([
+      syncMaxConcurrent(),
+      syncSpeedLimit(),
+      syncPreallocation(),
+      syncDiskReserve(),
+      syncPathRules(),
+      syncHooks(),
+      syncAutoExtract(),
+      syncDedupMode(),
+      syncVolumeLimits(),
+      syncLaunchParams(),
+    ]);
   };
 
   const updateTask = (gid: string, updater: (task: DownLoadFile) => void) => {
@@ -302,6 +412,9 @@ export const useDownloadManager = createSharedComposable(() => {
       target.status = task.status;
       target.errorMessage = task.errorMessage;
       target.errorCode = task.errorCode;
+      target.extractStatus = task.extractStatus;
+      target.extractError = task.extractError;
+      target.reusedFrom = task.reusedFrom;
 
       if (typeof task.progress === 'number') target.progress = task.progress;
       if (task.completedFiles != null) target.completedFiles = task.completedFiles;
@@ -326,19 +439,91 @@ export const useDownloadManager = createSharedComposable(() => {
     });
   };
 
+  // 解压进度只用于展示，解压阶段以 task-status 中的 extractStatus 为准。
+  const handleExtractProgress = (event: ExtractProgressEvent) => {
+    const progress =
+      event.total_bytes > 0
+        ? Math.min(100, Math.round((event.extracted_bytes / event.total_bytes) * 100))
+        : 0;
+    for (const gid of event.task_ids) {
+      updateTask(gid, (task) => {
+        task.extractProgress = progress;
+      });
+    }
+  };
+
   // 直链刷新仍然必须由前端发起，因为它依赖现有 Web API 和鉴权上下文。
-  const handleUrlNeeded = async ({ requestId, pickCode }: UrlNeededPayload) => {
+  /** 告知后端无法换取地址，等待中的任务随即失败而不是等到超时 */
+  const reportUrlFailure = (requestId: string, error: string) =>
+    invokeDownloadCommand('download_provide_url', { requestId, url: '', error }).catch(
+      (reportError) => logDownloadManagerError('回报 URL 刷新失败出错:', reportError),
+    );
+
+  // 同一时间段内的文件地址请求合并为一次 downurl 调用（多个提取码以逗号分隔），
+  // 文件夹里大量小文件同时启动时不必逐个请求。
+  let pendingFileUrls: UrlNeededPayload[] = [];
+  let fileUrlTimer: ReturnType<typeof setTimeout> | null = null;
+
+  const resolveFileUrls = async (requests: UrlNeededPayload[]) => {
     try {
-      const response = await fileDownloadUrl({ pick_code: pickCode });
-      const fileData = Object.values(response.data)[0];
-      if (fileData?.url?.url) {
-        await invokeDownloadCommand('download_provide_url', {
-          requestId,
-          url: fileData.url.url,
-        });
+      const pickCodes = [...new Set(requests.map((item) => item.pickCode))];
+      const response = await fileDownloadUrl({ pick_code: pickCodes.join(',') });
+      const urls = new Map(
+        Object.values(response.data).map((item) => [item.pick_code, item.url?.url]),
+      );
+      await Promise.all(
+        requests.map(({ requestId, pickCode }) => {
+          const url = urls.get(pickCode);
+          return url
+            ? invokeDownloadCommand('download_provide_url', { requestId, url })
+            : reportUrlFailure(requestId, '未获取到文件下载地址');
+        }),
+      );
+    } catch (error) {
+      logDownloadManagerError('URL 刷新失败:', error);
+      const message = error instanceof Error ? error.message : String(error);
+      await Promise.all(requests.map(({ requestId }) => reportUrlFailure(requestId, message)));
+    }
+  };
+
+  const flushFileUrls = () => {
+    fileUrlTimer = null;
+    const requests = pendingFileUrls;
+    pendingFileUrls = [];
+    for (let i = 0; i < requests.length; i += FILE_URL_BATCH_SIZE) {
+      void resolveFileUrls(requests.slice(i, i + FILE_URL_BATCH_SIZE));
+    }
+  };
+
+  const handleUrlNeeded = async (payload: UrlNeededPayload) => {
+    if (payload.kind === 'file') {
+      pendingFileUrls.push(payload);
+      if (!fileUrlTimer) {
+        fileUrlTimer = setTimeout(flushFileUrls, FILE_URL_BATCH_DELAY_MS);
+      }
+      return;
+    }
+    const { requestId, pickCode, maxHeight } = payload;
+    try {
+      // 回传与任务相同清晰度的播放列表，规则与后端 select_variant 一致：
+      // 不超过 maxHeight 的最高清晰度，都超过时取最低清晰度。
+      const response = await videoPlayUrl({ pick_code: pickCode });
+      const variants = [...(response.data.video_url ?? [])]
+        .filter((item) => item.url)
+        .sort((a, b) => Number(b.height) - Number(a.height));
+      const lowest = variants[variants.length - 1];
+      const chosen =
+        maxHeight == null
+          ? variants[0]
+          : (variants.find((item) => Number(item.height) <= maxHeight) ?? lowest);
+      if (!chosen) {
+        await reportUrlFailure(requestId, '未获取到视频播放地址');
+        return;
       }
+      await invokeDownloadCommand('download_provide_url', { requestId, url: chosen.url });
     } catch (error) {
       logDownloadManagerError('URL 刷新失败:', error);
+      await reportUrlFailure(requestId, error instanceof Error ? error.message : String(error));
     }
   };
 
@@ -359,6 +544,9 @@ export const useDownloadManager = createSharedComposable(() => {
         listen<DownLoadFile>('download:task-status', (event) => {
           handleTaskStatus(event.payload);
         }),
+        listen<ExtractProgressEvent>('download:extract-progress', (event) => {
+          handleExtractProgress(event.payload);
+        }),
       ])
         .then((listeners) => {
           unlisteners.push(...listeners);
@@ -381,6 +569,7 @@ export const useDownloadManager = createSharedComposable(() => {
     result: { file: MyFile; path: string }[],
     parentGid: string,
     offset = 0,
+    dirs?: FolderDirItem[],
   ) => {
     if (cancelledFolderCollections.has(parentGid)) {
       throw new Error(FOLDER_COLLECTION_ABORTED);
@@ -396,7 +585,8 @@ export const useDownloadManager = createSharedComposable(() => {
       if (item.fc === '0') {
         // 子文件夹：拼接相对路径继续递归
         const subPath = currentPath ? `${currentPath}/${item.fn}` : item.fn;
-        await collectFolderFiles(item.fid, subPath, result, parentGid);
+        dirs?.push({ path: subPath, remoteMtime: item.upt, remoteCtime: item.uppt });
+        await collectFolderFiles(item.fid, subPath, result, parentGid, 0, dirs);
       } else {
         // 文件：path 为相对于文件夹根目录的完整文件路径（含文件名）
         const filePath = currentPath ? `${currentPath}/${item.fn}` : item.fn;
@@ -405,7 +595,7 @@ export const useDownloadManager = createSharedComposable(() => {
     }
 
     if (offset + res.data.length < res.count) {
-      await collectFolderFiles(folderId, currentPath, result, parentGid, offset + 1150);
+      await collectFolderFiles(folderId, currentPath, result, parentGid, offset + 1150, dirs);
     }
   };
 
@@ -430,6 +620,7 @@ export const useDownloadManager = createSharedComposable(() => {
     folder: FolderDownloadTarget,
     reuseExistingTask = false,
     customParentPath?: string,
+    sync?: FolderSyncCompare,
   ) => {
     const parentGid = folder.gid ?? `folder-${Date.now()}-${Math.random().toString(36).slice(2)}`;
     const parentPath =
@@ -450,8 +641,9 @@ export const useDownloadManager = createSharedComposable(() => {
     }
 
     const allFiles: { file: MyFile; path: string }[] = [];
+    const dirs: FolderDirItem[] = [];
     try {
-      await collectFolderFiles(folder.fid, '', allFiles, parentGid);
+      await collectFolderFiles(folder.fid, '', allFiles, parentGid, 0, dirs);
     } catch (error) {
       if (isFolderCollectionAbortedError(error)) {
         cancelledFolderCollections.delete(parentGid);
@@ -473,6 +665,9 @@ export const useDownloadManager = createSharedComposable(() => {
       pickCode: f.file.pc,
       size: f.file.fs,
       path: f.path,
+      sha1: f.file.sha1 || undefined,
+      remoteMtime: f.file.upt || undefined,
+      remoteCtime: f.file.uppt || undefined,
     }));
 
     try {
@@ -484,6 +679,12 @@ export const useDownloadManager = createSharedComposable(() => {
         parentPath,
         files,
         ...getDownloadParams(),
+        conflictPolicy: settingStore.downloadSetting.conflictPolicy,
+        sync,
+        parentRemoteMtime: folder.remoteMtime,
+        parentRemoteCtime: folder.remoteCtime,
+        dirs,
+        maxActiveChildren: settingStore.downloadSetting.folderMaxActiveChildren,
       });
     } catch (error) {
       logDownloadManagerError('创建文件夹下载任务失败:', error);
@@ -500,9 +701,10 @@ export const useDownloadManager = createSharedComposable(() => {
    * @param targetPath 可选的自定义保存路径：
    *   - 文件：完整的保存路径（含文件名）
    *   - 文件夹：文件夹的保存路径
-   *   不传则使用设置中的 downloadPath
+   *   不传则使用设置中的 downloadPath，并按保存路径规则分类
+   * @param sourceFolder 文件所在的 115 文件夹路径，供按来源文件夹匹配的规则使用
    */
-  const download = async (file: MyFile, targetPath?: string) => {
+  const download = async (file: MyFile, targetPath?: string, sourceFolder?: string) => {
     const downloadPath = settingStore.downloadSetting.downloadPath;
 
     if (file.fc === '0') {
@@ -513,6 +715,8 @@ export const useDownloadManager = createSharedComposable(() => {
           fid: file.fid,
           name: file.fn,
           pickCode: file.pc,
+          remoteMtime: file.upt || undefined,
+          remoteCtime: file.uppt || undefined,
         },
         false,
         folderParentPath,
@@ -536,6 +740,11 @@ export const useDownloadManager = createSharedComposable(() => {
         savePath,
         expectedSha1: fileData.sha1,
         ...getDownloadParams(),
+        conflictPolicy: settingStore.downloadSetting.conflictPolicy,
+        remoteMtime: file.upt || undefined,
+        remoteCtime: file.uppt || undefined,
+        sourceFolder,
+        usePathRules: targetPath === undefined,
       });
     }
   };
@@ -545,18 +754,66 @@ export const useDownloadManager = createSharedComposable(() => {
    * @param files 要下载的文件/文件夹列表
    * @param targetDir 可选的目标目录（所有文件/文件夹保存到此目录下）
    *   不传则每个文件使用设置中的 downloadPath
+   * @param sourceFolder 文件所在的 115 文件夹路径
    */
-  const batchDownload = async (files: MyFile[], targetDir?: string) => {
+  const batchDownload = async (files: MyFile[], targetDir?: string, sourceFolder?: string) => {
     for (const file of files) {
       if (targetDir !== undefined) {
         const customPath = `${targetDir}/${file.fn}`;
-        await download(file, customPath);
+        await download(file, customPath, sourceFolder);
       } else {
-        await download(file);
+        await download(file, undefined, sourceFolder);
       }
     }
   };
 
+  /**
+   * 同步下载文件夹：只下载本地缺失或有变化的文件
+   * @param compare 比对方式，size 只比较大小，sha1 大小一致后再比较 SHA1
+   */
+  const syncDownloadFolder = async (
+    file: MyFile,
+    targetPath?: string,
+    compare: FolderSyncCompare = 'sha1',
+  ) => {
+    const folderParentPath =
+      targetPath ?? `${settingStore.downloadSetting.downloadPath}/${file.fn}`;
+    await enqueueCollectedFolder(
+      {
+        fid: file.fid,
+        name: file.fn,
+        pickCode: file.pc,
+        remoteMtime: file.upt || undefined,
+        remoteCtime: file.uppt || undefined,
+      },
+      false,
+      folderParentPath,
+      compare,
+    );
+  };
+
+  /** 获取文件夹中因名称不受目标文件系统支持而改名的子文件 */
+  const getRenamedChildren = (parentGid: string) =>
+    invokeDownloadCommand<DownLoadFile[]>('download_get_renamed_children', { parentGid });
+
+  /**
+   * 预览单文件按保存路径规则分类后的保存路径
+   * @param rules 待测试的规则，不传则使用当前生效的规则
+   */
+  const previewSavePath = (
+    name: string,
+    size: number,
+    sourceFolder?: string,
+    rules?: PathRule[],
+  ) =>
+    invokeDownloadCommand<PathPreview>('download_preview_save_path', {
+      name,
+      size,
+      savePath: `${settingStore.downloadSetting.downloadPath}/${name}`,
+      sourceFolder,
+      rules,
+    });
+
   /** 重试失败的下载任务 */
   const retryDownload = async (downloadFile: DownLoadFile) => {
     if (downloadFile.isFolder) {
@@ -584,6 +841,14 @@ export const useDownloadManager = createSharedComposable(() => {
     }
   };
 
+  /** 修复校验失败的单文件任务：只重写与远端不一致的块 */
+  const repairDownload = async (downloadFile: DownLoadFile) => {
+    await invokeDownloadCommand('download_repair_task', {
+      gid: downloadFile.gid,
+      ...getDownloadParams(),
+    });
+  };
+
   /** 移除下载任务 */
   const removeTask = async (downloadFile: DownLoadFile) => {
     if (downloadFile.isFolder) {
@@ -621,6 +886,16 @@ export const useDownloadManager = createSharedComposable(() => {
     });
   };
 
+  /** 调整文件夹同时下载的子任务上限，0 表示只受全局并发限制 */
+  const setFolderMaxActive = async (folder: DownLoadFile, maxActiveChildren: number) => {
+    await invokeDownloadCommand('download_set_folder_max_active', {
+      parentGid: folder.gid,
+      maxActiveChildren,
+    });
+    const folderItem = displayList.value.find((d) => d.gid === folder.gid);
+    if (folderItem) folderItem.maxActiveChildren = maxActiveChildren || undefined;
+  };
+
   /** 恢复单个已暂停的下载任务 */
   const resumeSingleFile = async (item: DownLoadFile) => {
     await invokeDownloadCommand('download_resume_task', { gid: item.gid, ...getDownloadParams() });
@@ -704,6 +979,80 @@ export const useDownloadManager = createSharedComposable(() => {
           });
         },
       ),
+      watch(
+        () => settingStore.downloadSetting.preallocation,
+        (mode) => {
+          void syncPreallocation(mode).catch((error) => {
+            logDownloadManagerError('同步预分配设置失败:', error);
+          });
+        },
+      ),
+      watch(
+        () => settingStore.downloadSetting.diskReserveMb,
+        (mb) => {
+          void syncDiskReserve(mb).catch((error) => {
+            logDownloadManagerError('同步磁盘预留设置失败:', error);
+          });
+        },
+      ),
+      watch(
+        () => settingStore.downloadSetting.pathRules,
+        (rules) => {
+          void syncPathRules(rules).catch((error) => {
+            logDownloadManagerError('同步保存路径规则失败:', error);
+          });
+        },
+        { deep: true },
+      ),
+      watch(
+        () => settingStore.downloadSetting.hooks,
+        (hooks) => {
+          void syncHooks(hooks).catch((error) => {
+            logDownloadManagerError('同步下载钩子失败:', error);
+          });
+        },
+        { deep: true },
+      ),
+      watch(
+        () => [
+          settingStore.downloadSetting.autoExtract,
+          settingStore.downloadSetting.deleteArchiveAfterExtract,
+        ],
+        () => {
+          void syncAutoExtract().catch((error) => {
+            logDownloadManagerError('同步自动解压设置失败:', error);
+          });
+        },
+      ),
+      watch(
+        () => settingStore.downloadSetting.dedupMode,
+        (mode) => {
+          void syncDedupMode(mode).catch((error) => {
+            logDownloadManagerError('同步文件复用设置失败:', error);
+          });
+        },
+      ),
+      watch(
+        () => settingStore.downloadSetting.volumeLimits,
+        (limits) => {
+          void syncVolumeLimits(limits).catch((error) => {
+            logDownloadManagerError('同步保存卷限制失败:', error);
+          });
+        },
+        { deep: true },
+      ),
+      watch(
+        () => [
+          userStore.accessToken,
+          settingStore.downloadSetting.split,
+          settingStore.downloadSetting.maxGlobalConnections,
+        ],
+        () => {
+          void syncLaunchParams().catch((error) => {
+            logDownloadManagerError('同步下载启动参数失败:', error);
+          });
+        },
+      ),
     );
   };
 
@@ -713,6 +1062,11 @@ export const useDownloadManager = createSharedComposable(() => {
     initPromise = null;
     progressCache.clear();
     cancelledFolderCollections.clear();
+    if (fileUrlTimer) {
+      clearTimeout(fileUrlTimer);
+      fileUrlTimer = null;
+    }
+    pendingFileUrls = [];
 
     for (const unlisten of unlisteners.splice(0)) {
       unlisten();
@@ -753,11 +1107,16 @@ export const useDownloadManager = createSharedComposable(() => {
     displayList: computed(() => displayList.value),
     download,
     batchDownload,
+    syncDownloadFolder,
+    getRenamedChildren,
+    previewSavePath,
     retryDownload,
+    repairDownload,
     removeTask,
     clearFinished,
     pauseFolder,
     resumeFolder,
+    setFolderMaxActive,
     resumeSingleFile,
     pauseAllTasks,
     resumeAllTasks,
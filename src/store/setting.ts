@@ -2,6 +2,48 @@ import store from '.';
 
 export type AppLogLevel = 'trace' | 'debug' | 'info' | 'warn' | 'error';
 
+/** 保存路径规则，已设置的条件需同时满足，按顺序第一条命中的规则生效 */
+export interface PathRule {
+  name?: string;
+  /** 扩展名（不含点） */
+  extensions?: string[];
+  minSize?: number;
+  maxSize?: number;
+  /** 匹配文件名的正则表达式 */
+  namePattern?: string;
+  /** 来源 115 文件夹路径，如 `/电影` */
+  sourceFolder?: string;
+  /** 保存目录模板，支持 `~` 与 `{date}` `{ext}` `{parent}` `{name}` 占位符，相对路径基于下载目录 */
+  target: string;
+}
+
+/** 下载钩子，任务结束时运行本地程序或 POST JSON 到指定地址 */
+export type DownloadHook = {
+  name?: string;
+  enabled?: boolean;
+  events: ('fileComplete' | 'folderComplete' | 'taskFailed')[];
+  /** 超时（秒），默认 30 */
+  timeoutSecs?: number;
+} & (
+  | {
+      type: 'command';
+      program: string;
+      /** 支持 {path} {name} {gid} {event} {status} {size} 占位符 */
+      args?: string[];
+    }
+  | { type: 'webhook'; url: string; headers?: Record<string, string> }
+);
+
+/** 按保存卷或根目录的下载限制，保存路径按最长根目录匹配 */
+export interface VolumeLimit {
+  /** 根目录，如 `D:\` 或 `/mnt/usb` */
+  root: string;
+  /** 最大同时下载数，0 或不填表示不限制 */
+  maxConcurrent?: number;
+  /** 写入带宽上限 (bytes/s)，0 或不填表示不限速 */
+  maxBytesPerSec?: number;
+}
+
 export const useSettingStore = defineStore(
   'setting',
   () => {
@@ -34,6 +76,8 @@ export const useSettingStore = defineStore(
       defaultRate: 1,
       autoPlay: true,
       isHistory: true,
+      /** 播放代理的分段磁盘缓存上限（MB） */
+      hlsCacheLimitMb: 1024,
     });
 
     const cloudDownloadSetting = ref({
@@ -58,6 +102,26 @@ export const useSettingStore = defineStore(
       speedLimitUnit: 'MB/s' as 'KB/s' | 'MB/s',
       /** 下载前询问每个文件的保存位置 */
       askSavePath: false,
+      /** 保存路径已存在同名文件时的处理策略 */
+      conflictPolicy: 'overwrite' as 'overwrite' | 'rename' | 'skip' | 'skipIfSameSha1',
+      /** 新建下载文件的磁盘空间预分配策略 */
+      preallocation: 'sparse' as 'none' | 'sparse' | 'full',
+      /** 磁盘预留空间 (MB)，剩余空间低于该值时暂停下载，0 表示不检查 */
+      diskReserveMb: 512,
+      /** 单文件下载的保存路径规则，手动选择保存位置时不生效 */
+      pathRules: [] as PathRule[],
+      /** 下载完成、文件夹结束或任务失败时执行的钩子 */
+      hooks: [] as DownloadHook[],
+      /** 下载完成后自动解压 zip、tar、tar.gz 与 gz（含 .001 分卷） */
+      autoExtract: false,
+      /** 解压成功后删除压缩包 */
+      deleteArchiveAfterExtract: false,
+      /** 本地已有相同 SHA1 的文件时的复用方式，off 表示始终下载 */
+      dedupMode: 'reflink' as 'off' | 'copy' | 'reflink' | 'hardlink',
+      /** 按保存卷或根目录限制并发数与写入带宽，全局限制仍然生效 */
+      volumeLimits: [] as VolumeLimit[],
+      /** 新建文件夹下载时同时下载的子任务上限，0 表示只受全局并发限制 */
+      folderMaxActiveChildren: 0,
     });
 
     const uploadSetting = ref({